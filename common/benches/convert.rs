@@ -0,0 +1,77 @@
+use common::convert::MyImageData;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use image::{codecs::jpeg::JpegEncoder, ColorType, GrayImage, ImageBuffer, Rgb, RgbImage};
+use nokhwa::{
+    pixel_format::RgbFormat,
+    utils::{FrameFormat, Resolution},
+    Buffer,
+};
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 480;
+
+/// A gradient frame, so that JPEG encoding does not degenerate to a trivial case.
+fn synthetic_rgb() -> RgbImage {
+    #[allow(clippy::cast_possible_truncation)]
+    ImageBuffer::from_fn(WIDTH, HEIGHT, |x, y| {
+        Rgb([(x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8])
+    })
+}
+
+fn synthetic_gray() -> GrayImage {
+    image::imageops::grayscale(&synthetic_rgb())
+}
+
+/// Packs a frame as YUYV 4:2:2 without chroma, the way most webcams deliver uncompressed frames.
+fn synthetic_yuyv() -> Vec<u8> {
+    #[allow(clippy::cast_possible_truncation)]
+    let luma = |p: &[u8]| {
+        ((77 * u32::from(p[0]) + 150 * u32::from(p[1]) + 29 * u32::from(p[2])) >> 8) as u8
+    };
+    synthetic_rgb()
+        .as_raw()
+        .chunks_exact(6)
+        .flat_map(|pair| [luma(&pair[..3]), 128, luma(&pair[3..]), 128])
+        .collect()
+}
+
+fn synthetic_mjpeg() -> Vec<u8> {
+    let image = synthetic_rgb();
+    let mut jpeg = Vec::new();
+    JpegEncoder::new(&mut jpeg)
+        .encode(image.as_raw(), WIDTH, HEIGHT, ColorType::Rgb8)
+        .expect("should be able to encode synthetic frame");
+    jpeg
+}
+
+fn camera_frame(c: &mut Criterion) {
+    let resolution = Resolution::new(WIDTH, HEIGHT);
+    let yuyv = Buffer::new(resolution, &synthetic_yuyv(), FrameFormat::YUYV);
+    let mjpeg = Buffer::new(resolution, &synthetic_mjpeg(), FrameFormat::MJPEG);
+
+    let mut group = c.benchmark_group("camera frame");
+    group.bench_function("decode YUYV to RgbImage", |b| {
+        b.iter(|| yuyv.decode_image::<RgbFormat>());
+    });
+    group.bench_function("decode MJPEG to RgbImage", |b| {
+        b.iter(|| mjpeg.decode_image::<RgbFormat>());
+    });
+    group.finish();
+}
+
+fn display(c: &mut Criterion) {
+    let rgb = synthetic_rgb();
+    let gray = synthetic_gray();
+
+    let mut group = c.benchmark_group("display");
+    group.bench_function("MyImageData from RgbImage", |b| {
+        b.iter_batched(|| rgb.clone(), MyImageData::from, BatchSize::LargeInput);
+    });
+    group.bench_function("MyImageData from GrayImage", |b| {
+        b.iter_batched(|| gray.clone(), MyImageData::from, BatchSize::LargeInput);
+    });
+    group.finish();
+}
+
+criterion_group!(benches, camera_frame, display);
+criterion_main!(benches);
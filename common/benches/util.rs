@@ -0,0 +1,55 @@
+use common::util::MatMovingAverage;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use opencv::core::{self, Mat, Scalar};
+
+const WIDTH: i32 = 640;
+const HEIGHT: i32 = 480;
+const WINDOW_SIZES: [usize; 4] = [1, 5, 15, 30];
+
+/// Creates `count` frames of uniform noise, shaped like a camera frame.
+fn synthetic_frames(count: usize) -> Vec<Mat> {
+    (0..count)
+        .map(|_| {
+            let mut frame =
+                Mat::new_rows_cols_with_default(HEIGHT, WIDTH, core::CV_8UC3, Scalar::all(0.))
+                    .expect("should be able to create matrix");
+            core::randu(&mut frame, &Scalar::all(0.), &Scalar::all(255.))
+                .expect("should be able to fill matrix with noise");
+            frame
+        })
+        .collect()
+}
+
+fn moving_average(c: &mut Criterion) {
+    let frames = synthetic_frames(WINDOW_SIZES.into_iter().max().unwrap_or(1));
+
+    let mut group = c.benchmark_group("MatMovingAverage");
+    group.throughput(Throughput::Elements(1));
+    for window_size in WINDOW_SIZES {
+        let mut average = MatMovingAverage::new(window_size);
+        for frame in frames.iter().take(window_size) {
+            average
+                .push(frame.clone())
+                .expect("should be able to push frame");
+        }
+        let mut frames = frames.iter().cycle();
+
+        group.bench_with_input(
+            BenchmarkId::new("push and average", window_size),
+            &window_size,
+            |b, _| {
+                b.iter(|| {
+                    let frame = frames.next().expect("cycle should never end");
+                    average
+                        .push(frame.clone())
+                        .expect("should be able to push frame");
+                    average.average()
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, moving_average);
+criterion_main!(benches);
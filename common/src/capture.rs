@@ -0,0 +1,214 @@
+use crate::pipeline::{PipelineConfiguration, StageOutputs};
+use opencv::{
+    core::{Mat, MatTraitConst, Size, Vector},
+    imgcodecs,
+    imgproc::{self, cvt_color},
+    videoio::{VideoWriter, VideoWriterTrait, VideoWriterTraitConst},
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::info;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CaptureError {
+    #[error("opencv error: {0}")]
+    OpenCv(#[from] opencv::Error),
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("could not write {0}")]
+    Write(PathBuf),
+    #[error("frame size changed from {expected:?} to {actual:?} during recording")]
+    FrameSizeChanged { expected: Size, actual: Size },
+}
+
+/// Milliseconds since the Unix epoch, used to name captures so that they sort chronologically.
+#[must_use]
+pub fn timestamp() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time should be after the Unix epoch")
+        .as_millis()
+}
+
+/// Converts a frame from the pipeline's RGB channel order, or a single-channel frame, to the
+/// 3-channel BGR order that `imgcodecs` and `videoio` expect.
+///
+/// # Errors
+///
+/// * `opencv::Error` if the frame has an unsupported number of channels.
+pub fn to_bgr(frame: &Mat) -> opencv::Result<Mat> {
+    let code = match frame.channels() {
+        1 => imgproc::COLOR_GRAY2BGR,
+        4 => imgproc::COLOR_RGBA2BGR,
+        _ => imgproc::COLOR_RGB2BGR,
+    };
+    let mut out = Mat::default();
+    cvt_color(frame, &mut out, code, 3)?;
+    Ok(out)
+}
+
+/// Writes `frame` to `path` in the format of its extension, converted with `to_bgr`.
+///
+/// # Errors
+///
+/// * `CaptureError::Write` if `imwrite` reports that the file was not written.
+/// * `CaptureError::OpenCv` if the frame cannot be converted or encoded.
+pub fn write_image(path: &Path, frame: &Mat) -> Result<(), CaptureError> {
+    let written = imgcodecs::imwrite(&path.to_string_lossy(), &to_bgr(frame)?, &Vector::new())?;
+    if written {
+        Ok(())
+    } else {
+        Err(CaptureError::Write(path.into()))
+    }
+}
+
+/// Saves the raw input frame, the output of every enabled stage and the configuration that
+/// produced them to a new directory `snapshot-<timestamp>` in `directory`, and returns its path.
+///
+/// The stage images are numbered in processing order, and the configuration is written in its
+/// `Debug` representation to `configuration.txt`.
+///
+/// # Errors
+///
+/// * `CaptureError` if the directory or one of the files cannot be written.
+pub fn save_snapshot(
+    directory: &Path,
+    raw: &Mat,
+    stages: &StageOutputs,
+    configuration: &PipelineConfiguration,
+) -> Result<PathBuf, CaptureError> {
+    let bundle = directory.join(format!("snapshot-{}", timestamp()));
+    fs::create_dir_all(&bundle)?;
+
+    write_image(&bundle.join("raw.png"), raw)?;
+    for (i, (stage, frame)) in stages.iter().enumerate() {
+        let name = format!("{i:02}-{}.png", stage.name().replace(' ', "_"));
+        write_image(&bundle.join(name), frame)?;
+    }
+    fs::write(
+        bundle.join("configuration.txt"),
+        format!("{configuration:#?}\n"),
+    )?;
+
+    info!(path = ?bundle, "saved snapshot");
+    Ok(bundle)
+}
+
+/// How a recording is stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// A single MJPEG-encoded `.avi` file.
+    #[default]
+    Video,
+    /// Numbered PNG files in a directory.
+    ImageSequence,
+}
+
+impl RecordingFormat {
+    pub const ALL: [Self; 2] = [Self::Video, Self::ImageSequence];
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Video => "video",
+            Self::ImageSequence => "image sequence",
+        }
+    }
+}
+
+enum Sink {
+    /// The writer is opened on the first frame, as it needs the frame size.
+    Video(Option<(VideoWriter, Size)>),
+    ImageSequence,
+}
+
+/// Writes a stream of frames to `recording-<timestamp>.avi` or `recording-<timestamp>/` in a
+/// directory. A video recording is finalized when the recorder is dropped.
+pub struct Recorder {
+    path: PathBuf,
+    fps: f64,
+    sink: Sink,
+    frames: usize,
+}
+
+impl Recorder {
+    /// # Errors
+    ///
+    /// * `CaptureError::Io` if the output directory cannot be created.
+    pub fn new(directory: &Path, format: RecordingFormat, fps: f64) -> Result<Self, CaptureError> {
+        let name = format!("recording-{}", timestamp());
+        fs::create_dir_all(directory)?;
+        let (path, sink) = match format {
+            RecordingFormat::Video => (
+                directory.join(name).with_extension("avi"),
+                Sink::Video(None),
+            ),
+            RecordingFormat::ImageSequence => {
+                let path = directory.join(name);
+                fs::create_dir(&path)?;
+                (path, Sink::ImageSequence)
+            }
+        };
+        info!(?path, format = format.name(), "started recording");
+        Ok(Self {
+            path,
+            fps,
+            sink,
+            frames: 0,
+        })
+    }
+
+    /// The video file or image directory being written.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// # Errors
+    ///
+    /// * `CaptureError` if the frame cannot be encoded or written, or if a video recording
+    ///   receives a frame of a different size than the first one.
+    pub fn write(&mut self, frame: &Mat) -> Result<(), CaptureError> {
+        match &mut self.sink {
+            Sink::Video(writer) => {
+                let size = frame.size()?;
+                if writer.is_none() {
+                    let fourcc = VideoWriter::fourcc('M', 'J', 'P', 'G')?;
+                    let opened = VideoWriter::new(
+                        &self.path.to_string_lossy(),
+                        fourcc,
+                        self.fps,
+                        size,
+                        true,
+                    )?;
+                    if !opened.is_opened()? {
+                        return Err(CaptureError::Write(self.path.clone()));
+                    }
+                    *writer = Some((opened, size));
+                }
+                let (writer, expected) = writer.as_mut().expect("writer should have been opened");
+                if size != *expected {
+                    return Err(CaptureError::FrameSizeChanged {
+                        expected: *expected,
+                        actual: size,
+                    });
+                }
+                writer.write(&to_bgr(frame)?)?;
+            }
+            Sink::ImageSequence => {
+                write_image(&self.path.join(format!("{:06}.png", self.frames)), frame)?;
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        info!(path = ?self.path, frames = self.frames, "stopped recording");
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{
+    preset::Preset,
+    source::{FrameSize, SourceOptions, SourceSpec, MIN_FPS},
+    telemetry::TelemetryArgs,
+};
+
+/// Command line arguments shared by the lab applications.
+#[derive(Debug, Clone, clap::Args)]
+pub struct LabArgs {
+    #[command(flatten)]
+    pub source: SourceArgs,
+    /// Initial processing configuration [default: depends on the application]
+    #[arg(short, long, value_enum)]
+    pub preset: Option<Preset>,
+    #[command(flatten)]
+    pub layout: LayoutArgs,
+    #[command(flatten)]
+    pub telemetry: TelemetryArgs,
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct SourceArgs {
+    /// Frame source: a camera index or name, an image file, a directory of images or `synthetic`
+    #[arg(default_value = "0")]
+    pub source: SourceSpec,
+    /// Requested frame resolution, e.g. `1280x720`
+    #[arg(short, long)]
+    pub resolution: Option<FrameSize>,
+    /// Requested frame rate
+    #[arg(short, long, value_parser = parse_fps)]
+    pub fps: Option<f64>,
+}
+
+/// Parses a frame rate, which has to be finite and at least [`MIN_FPS`] to pace frames by.
+fn parse_fps(s: &str) -> Result<f64, String> {
+    let fps = s.parse::<f64>().map_err(|error| error.to_string())?;
+    if fps.is_finite() && fps >= MIN_FPS {
+        Ok(fps)
+    } else {
+        Err(format!("{fps} is not a frame rate of at least {MIN_FPS}"))
+    }
+}
+
+impl SourceArgs {
+    #[must_use]
+    pub fn options(&self) -> SourceOptions {
+        SourceOptions {
+            resolution: self.resolution,
+            fps: self.fps,
+        }
+    }
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct LayoutArgs {
+    /// Initial window size
+    #[arg(short, long, default_value = "800x600")]
+    pub window_size: FrameSize,
+    /// Start in fullscreen
+    #[arg(long)]
+    pub fullscreen: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_rates_have_to_be_paceable() {
+        assert_eq!(parse_fps("30"), Ok(30.));
+        assert_eq!(parse_fps("0.001"), Ok(MIN_FPS));
+        for fps in ["1e-300", "0", "-1", "NaN", "inf", "thirty"] {
+            assert!(parse_fps(fps).is_err(), "{fps}");
+        }
+    }
+}
@@ -1,13 +1,9 @@
 pub mod cam {
+    pub use crate::source::CameraIndex;
+    use crate::source::{SourceOptions, SourceSpec};
     use image::{ImageBuffer, RgbImage};
-    pub use nokhwa::utils::CameraIndex;
-    use nokhwa::{
-        pixel_format::RgbFormat,
-        utils::{RequestedFormat, RequestedFormatType},
-        Camera,
-    };
     use std::{sync::mpsc, thread};
-    use tracing::{debug, error};
+    use tracing::{debug, debug_span, error};
 
     pub type VectorImageBuffer<P> = ImageBuffer<P, Vec<u8>>;
 
@@ -20,7 +16,23 @@ pub mod cam {
     #[must_use]
     pub fn create_camera_stream<F, I>(index: CameraIndex, process: F) -> mpsc::Receiver<I>
     where
-        F: Fn(RgbImage) -> I + Sized + Send + 'static,
+        F: FnMut(RgbImage) -> I + Sized + Send + 'static,
+        I: Send + 'static,
+    {
+        create_frame_stream(SourceSpec::Camera(index), SourceOptions::default(), process)
+    }
+
+    /// Opens the source described by `spec` on a separate thread, and sends every frame through
+    /// `process` to the returned receiver. The receiver is disconnected if the source cannot be
+    /// opened, is exhausted, or fails too many times.
+    #[must_use]
+    pub fn create_frame_stream<F, I>(
+        spec: SourceSpec,
+        options: SourceOptions,
+        mut process: F,
+    ) -> mpsc::Receiver<I>
+    where
+        F: FnMut(RgbImage) -> I + Sized + Send + 'static,
         I: Send + 'static,
     {
         let (img_sender, img_receiver) = mpsc::sync_channel(2);
@@ -28,33 +40,27 @@ pub mod cam {
         thread::spawn(move || {
             const ERROR_LIMIT: u32 = 30;
             let mut fails = 0;
-            let mut camera = Camera::new(
-                index,
-                RequestedFormat::new::<RgbFormat>(RequestedFormatType::AbsoluteHighestFrameRate),
-            )
-            .expect("expected a camera on received index");
-            if !camera.is_stream_open() {
-                camera
-                    .open_stream()
-                    .expect("should be able to open stream on camera");
-            }
-            {
-                let info = camera.info();
-                debug!(?info, "opened camera");
-            }
+            let mut source = match spec.open(&options) {
+                Ok(source) => source,
+                Err(e) => {
+                    error!(%spec, %e, "could not open frame source");
+                    return;
+                }
+            };
 
             loop {
-                match camera
-                    .frame()
-                    .and_then(|frame| frame.decode_image::<RgbFormat>())
-                {
-                    Ok(frame) => {
-                        let frame = process(frame);
+                match source.next_frame() {
+                    Ok(Some(frame)) => {
+                        let frame = debug_span!("process").in_scope(|| process(frame));
                         if img_sender.send(frame).is_err() {
                             debug!("image receiver dropped");
                             break;
                         }
                     }
+                    Ok(None) => {
+                        debug!("frame source exhausted");
+                        break;
+                    }
                     Err(e) => {
                         fails += 1;
                         error!(%fails, %e);
@@ -74,6 +80,8 @@ pub mod cam {
 
 pub mod convert {
     use image::{GrayImage, RgbImage};
+    #[cfg(feature = "opencv")]
+    use opencv::core::{Mat, MatTraitConst};
 
     pub struct MyImageData(pub egui::ImageData);
 
@@ -98,6 +106,39 @@ pub mod convert {
         }
     }
 
+    /// Turns an `image::RgbImage` into an `opencv::core::Mat`
+    ///
+    /// # Arguments
+    ///
+    /// * `image`: `RgbImage`
+    ///
+    /// returns: Result<Mat, Error>
+    ///
+    /// # Errors
+    ///
+    /// * `opencv::Error` if constructing or copying Mats fails.
+    #[cfg(feature = "opencv")]
+    pub fn to_mat(mut image: RgbImage) -> Result<Mat, opencv::Error> {
+        let data = image.as_mut_ptr();
+        let step = opencv::core::Mat_AUTO_STEP;
+        let mat = unsafe {
+            // SAFETY
+            // The Mat from this block references the owned image, which is dropped at the end of
+            // the function. Before the drop, the data pointed to by this Mat is cloned to an
+            // owning Mat, which makes it safe.
+            Mat::new_rows_cols_with_data(
+                i32::try_from(image.height()).expect("image size should fit in an i32"),
+                i32::try_from(image.width()).expect("image size should fit in an i32"),
+                opencv::core::CV_8UC3,
+                data.cast::<std::ffi::c_void>(),
+                step,
+            )?
+        };
+        let mut out = Mat::default();
+        mat.copy_to(&mut out)?;
+        Ok(out)
+    }
+
     use lazy_static::lazy_static;
 
     lazy_static! {
@@ -111,72 +152,18 @@ pub mod convert {
     }
 }
 
-pub mod util {
-    use opencv::{
-        core::{self, MatExprTraitConst, MatTraitConst, MatTraitConstManual},
-        imgproc,
-    };
-    use std::collections::VecDeque;
-
-    pub struct MatMovingAverage {
-        size: usize,
-        buffer: VecDeque<::opencv::core::Mat>,
-    }
-
-    impl MatMovingAverage {
-        #[must_use]
-        pub fn new(size: usize) -> Self {
-            Self {
-                size,
-                buffer: VecDeque::with_capacity(size),
-            }
-        }
-
-        pub fn push(&mut self, mat: ::opencv::core::Mat) {
-            if self.buffer.len() == self.size {
-                self.buffer.pop_front();
-            }
-
-            self.buffer.push_back(mat);
-        }
-
-        #[must_use]
-        pub fn average(&self) -> Option<::opencv::core::Mat> {
-            if self.buffer.is_empty() {
-                return None;
-            }
-            let an_image = self
-                .buffer
-                .get(0)
-                .expect("should be an element at index 0 after checking emptiness prior");
-            let size = an_image
-                .size()
-                .expect("should be able to check size on image");
-            let depth = an_image.typ() & core::Mat_DEPTH_MASK;
-            let mut out = ::opencv::core::Mat::zeros(size.height, size.width, core::CV_32FC3)
-                .expect("should be able to create matrix")
-                .to_mat()
-                .expect("should be able to convert to matrix");
-            for img in &self.buffer {
-                imgproc::accumulate(img, &mut out, &core::no_array())
-                    .expect("should be able to accumulate");
-            }
-
-            #[allow(clippy::cast_precision_loss)]
-            let mut out = (out / (self.buffer.len() as f64))
-                .into_result()
-                .expect("should be able to divide")
-                .to_mat()
-                .expect("should be able to convert to matrix");
-
-            out = {
-                let mut res = ::opencv::core::Mat::default();
-                out.convert_to(&mut res, depth, 1., 0.)
-                    .expect("should be able to convert");
-                res
-            };
-
-            Some(out)
-        }
-    }
-}
+#[cfg(feature = "opencv")]
+pub mod capture;
+pub mod cli;
+pub mod overlay;
+pub mod picking;
+#[cfg(feature = "opencv")]
+pub mod pipeline;
+pub mod preset;
+pub mod profiler;
+pub mod source;
+pub mod stats;
+pub mod telemetry;
+#[cfg(feature = "opencv")]
+pub mod util;
+pub mod viewer;
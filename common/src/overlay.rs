@@ -0,0 +1,462 @@
+use crate::viewer::ImageTransform;
+use egui::{Align2, Color32, FontId, Painter, Shape as EShape, Stroke as EStroke};
+use image::{Rgb, RgbImage};
+use imageproc::{
+    drawing::{
+        draw_filled_circle_mut, draw_hollow_circle_mut, draw_line_segment_mut, draw_polygon_mut,
+        draw_text_mut,
+    },
+    point::Point as IPoint,
+};
+#[cfg(feature = "opencv")]
+use opencv::{
+    core::{Mat, MatTraitConst, Point as CvPoint, Scalar, Vector},
+    imgproc,
+};
+use rusttype::{Font, Scale};
+
+/// A position in image pixel coordinates, `[x, y]`.
+pub type Point = [f32; 2];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const BLACK: Self = Self::new(0, 0, 0);
+    pub const WHITE: Self = Self::new(255, 255, 255);
+    pub const RED: Self = Self::new(255, 0, 0);
+    pub const GREEN: Self = Self::new(0, 255, 0);
+    pub const BLUE: Self = Self::new(0, 0, 255);
+    pub const YELLOW: Self = Self::new(255, 255, 0);
+    pub const CYAN: Self = Self::new(0, 255, 255);
+    pub const MAGENTA: Self = Self::new(255, 0, 255);
+
+    #[must_use]
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Intensity for single-channel images.
+    #[cfg(feature = "opencv")]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn luma(self) -> u8 {
+        (0.299 * f32::from(self.r) + 0.587 * f32::from(self.g) + 0.114 * f32::from(self.b)).round()
+            as u8
+    }
+}
+
+impl From<Color> for Color32 {
+    fn from(color: Color) -> Self {
+        Self::from_rgb(color.r, color.g, color.b)
+    }
+}
+
+impl From<Color> for Rgb<u8> {
+    fn from(color: Color) -> Self {
+        Rgb([color.r, color.g, color.b])
+    }
+}
+
+/// Color and width of the outline of a shape. The width is in image pixels when rasterized, and
+/// in screen points when painted in egui, so that lines stay thin when zoomed in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stroke {
+    pub color: Color,
+    pub width: f32,
+}
+
+impl Stroke {
+    #[must_use]
+    pub fn new(color: Color, width: f32) -> Self {
+        Self { color, width }
+    }
+}
+
+impl From<Color> for Stroke {
+    fn from(color: Color) -> Self {
+        Self::new(color, 1.)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    /// A filled dot, e.g. a keypoint.
+    Point {
+        position: Point,
+        radius: f32,
+    },
+    Line {
+        from: Point,
+        to: Point,
+    },
+    Circle {
+        center: Point,
+        radius: f32,
+    },
+    /// Connected line segments, closed into a polygon if `closed`.
+    Polyline {
+        points: Vec<Point>,
+        closed: bool,
+    },
+    /// Text whose bottom left corner is at `position`, `size` pixels high.
+    Text {
+        position: Point,
+        text: String,
+        size: f32,
+    },
+}
+
+/// A list of shapes in image pixel coordinates, which can be drawn onto a `Mat`, onto an
+/// `RgbImage`, or painted on top of an image shown in egui.
+///
+/// Text is left out when drawing onto an `RgbImage` without a font, see `draw_on_image`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Overlay {
+    pub items: Vec<(Shape, Stroke)>,
+}
+
+impl Overlay {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, shape: Shape, stroke: impl Into<Stroke>) -> &mut Self {
+        self.items.push((shape, stroke.into()));
+        self
+    }
+
+    pub fn point(&mut self, position: Point, radius: f32, color: Color) -> &mut Self {
+        self.push(Shape::Point { position, radius }, color)
+    }
+
+    pub fn line(&mut self, from: Point, to: Point, stroke: impl Into<Stroke>) -> &mut Self {
+        self.push(Shape::Line { from, to }, stroke)
+    }
+
+    pub fn circle(&mut self, center: Point, radius: f32, stroke: impl Into<Stroke>) -> &mut Self {
+        self.push(Shape::Circle { center, radius }, stroke)
+    }
+
+    pub fn polyline(&mut self, points: Vec<Point>, stroke: impl Into<Stroke>) -> &mut Self {
+        self.push(
+            Shape::Polyline {
+                points,
+                closed: false,
+            },
+            stroke,
+        )
+    }
+
+    pub fn polygon(&mut self, points: Vec<Point>, stroke: impl Into<Stroke>) -> &mut Self {
+        self.push(
+            Shape::Polyline {
+                points,
+                closed: true,
+            },
+            stroke,
+        )
+    }
+
+    pub fn text(
+        &mut self,
+        position: Point,
+        text: impl Into<String>,
+        size: f32,
+        color: Color,
+    ) -> &mut Self {
+        let text = text.into();
+        self.push(
+            Shape::Text {
+                position,
+                text,
+                size,
+            },
+            color,
+        )
+    }
+
+    /// Coordinate axes from `origin` to the projected tips of the x, y and z axes, in red, green
+    /// and blue.
+    pub fn axes(&mut self, origin: Point, tips: [Point; 3], width: f32) -> &mut Self {
+        for (tip, color) in tips
+            .into_iter()
+            .zip([Color::RED, Color::GREEN, Color::BLUE])
+        {
+            self.line(origin, tip, Stroke::new(color, width));
+        }
+        self
+    }
+
+    pub fn extend(&mut self, other: &Self) -> &mut Self {
+        self.items.extend(other.items.iter().cloned());
+        self
+    }
+
+    /// Rasterizes the overlay onto `image` with `imageproc`.
+    ///
+    /// `imageproc` has no built-in font, so text is only drawn if a `font` is given.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn draw_on_image(&self, image: &mut RgbImage, font: Option<&Font<'_>>) {
+        let round = |[x, y]: Point| (x.round() as i32, y.round() as i32);
+        for (shape, stroke) in &self.items {
+            let color = Rgb::from(stroke.color);
+            match shape {
+                Shape::Point { position, radius } => {
+                    draw_filled_circle_mut(image, round(*position), radius.round() as i32, color);
+                }
+                Shape::Line { from, to } => draw_thick_line(image, *from, *to, stroke.width, color),
+                Shape::Circle { center, radius } => {
+                    // concentric circles, as imageproc only draws 1 pixel wide outlines
+                    let half = (stroke.width / 2.).max(0.5);
+                    let (inner, outer) = ((radius - half).round(), (radius + half).round() - 1.);
+                    for radius in inner as i32..=(outer as i32).max(inner as i32) {
+                        draw_hollow_circle_mut(image, round(*center), radius, color);
+                    }
+                }
+                Shape::Polyline { points, closed } => {
+                    for [from, to] in segments(points, *closed) {
+                        draw_thick_line(image, from, to, stroke.width, color);
+                    }
+                }
+                Shape::Text {
+                    position,
+                    text,
+                    size,
+                } => {
+                    if let Some(font) = font {
+                        let (x, y) = round([position[0], position[1] - size]);
+                        draw_text_mut(image, color, x, y, Scale::uniform(*size), font, text);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Rasterizes the overlay onto `mat` with OpenCV, assuming the RGB channel order of the
+    /// pipeline. Single-channel images are drawn on with the intensity of the colors.
+    ///
+    /// # Errors
+    ///
+    /// * `opencv::Error` if OpenCV cannot draw on `mat`.
+    #[cfg(feature = "opencv")]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn draw_on_mat(&self, mat: &mut Mat) -> opencv::Result<()> {
+        // coordinates are passed with SHIFT fractional bits for sub-pixel accuracy
+        const SHIFT: i32 = 4;
+        let fixed = |[x, y]: Point| {
+            let one = (1 << SHIFT) as f32;
+            CvPoint::new((x * one).round() as i32, (y * one).round() as i32)
+        };
+        let fixed_length = |length: f32| (length * (1 << SHIFT) as f32).round() as i32;
+        let channels = mat.channels();
+
+        for (shape, stroke) in &self.items {
+            let color = if channels == 1 {
+                Scalar::all(f64::from(stroke.color.luma()))
+            } else {
+                let Color { r, g, b } = stroke.color;
+                Scalar::new(f64::from(r), f64::from(g), f64::from(b), 255.)
+            };
+            let thickness = (stroke.width.round() as i32).max(1);
+            match shape {
+                Shape::Point { position, radius } => imgproc::circle(
+                    mat,
+                    fixed(*position),
+                    fixed_length(*radius),
+                    color,
+                    imgproc::FILLED,
+                    imgproc::LINE_AA,
+                    SHIFT,
+                )?,
+                Shape::Line { from, to } => imgproc::line(
+                    mat,
+                    fixed(*from),
+                    fixed(*to),
+                    color,
+                    thickness,
+                    imgproc::LINE_AA,
+                    SHIFT,
+                )?,
+                Shape::Circle { center, radius } => imgproc::circle(
+                    mat,
+                    fixed(*center),
+                    fixed_length(*radius),
+                    color,
+                    thickness,
+                    imgproc::LINE_AA,
+                    SHIFT,
+                )?,
+                Shape::Polyline { points, closed } => {
+                    let points: Vector<CvPoint> = points.iter().copied().map(fixed).collect();
+                    imgproc::polylines(
+                        mat,
+                        &points,
+                        *closed,
+                        color,
+                        thickness,
+                        imgproc::LINE_AA,
+                        SHIFT,
+                    )?;
+                }
+                Shape::Text {
+                    position,
+                    text,
+                    size,
+                } => {
+                    // the Hershey fonts are about 22 pixels high at scale 1
+                    let scale = f64::from(*size) / 22.;
+                    imgproc::put_text(
+                        mat,
+                        text,
+                        CvPoint::new(position[0].round() as i32, position[1].round() as i32),
+                        imgproc::FONT_HERSHEY_SIMPLEX,
+                        scale,
+                        color,
+                        thickness,
+                        imgproc::LINE_AA,
+                        false,
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Paints the overlay as vector shapes on top of an image shown as described by `transform`,
+    /// so that it stays sharp when the image is scaled up.
+    pub fn paint(&self, painter: &Painter, transform: &ImageTransform) {
+        let scale = transform.mean_scale();
+        let to_screen = |point: Point| transform.to_screen(point);
+        for (shape, stroke) in &self.items {
+            let color = Color32::from(stroke.color);
+            let line_stroke = EStroke::new(stroke.width, color);
+            let shape = match shape {
+                Shape::Point { position, radius } => {
+                    EShape::circle_filled(to_screen(*position), (radius * scale).max(1.), color)
+                }
+                Shape::Line { from, to } => {
+                    EShape::line_segment([to_screen(*from), to_screen(*to)], line_stroke)
+                }
+                Shape::Circle { center, radius } => {
+                    EShape::circle_stroke(to_screen(*center), radius * scale, line_stroke)
+                }
+                Shape::Polyline { points, closed } => {
+                    let points = points.iter().copied().map(to_screen).collect();
+                    if *closed {
+                        EShape::closed_line(points, line_stroke)
+                    } else {
+                        EShape::line(points, line_stroke)
+                    }
+                }
+                Shape::Text {
+                    position,
+                    text,
+                    size,
+                } => {
+                    painter.text(
+                        to_screen(*position),
+                        Align2::LEFT_BOTTOM,
+                        text,
+                        FontId::proportional(size * scale),
+                        color,
+                    );
+                    continue;
+                }
+            };
+            painter.add(shape);
+        }
+    }
+}
+
+/// Consecutive pairs of `points`, including the pair from the last to the first if `closed`.
+fn segments(points: &[Point], closed: bool) -> impl Iterator<Item = [Point; 2]> + '_ {
+    let closing = (closed && points.len() > 2)
+        .then(|| [points[points.len() - 1], points[0]])
+        .into_iter();
+    points
+        .windows(2)
+        .map(|pair| [pair[0], pair[1]])
+        .chain(closing)
+}
+
+/// Draws a line with `imageproc`, as a filled quadrilateral if it is wider than a pixel.
+#[allow(clippy::cast_possible_truncation)]
+fn draw_thick_line(image: &mut RgbImage, from: Point, to: Point, width: f32, color: Rgb<u8>) {
+    let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+    let length = dx.hypot(dy);
+    if width <= 1. || length == 0. {
+        draw_line_segment_mut(image, (from[0], from[1]), (to[0], to[1]), color);
+        return;
+    }
+    let (nx, ny) = (-dy / length * width / 2., dx / length * width / 2.);
+    let corner = |[x, y]: Point, sign: f32| {
+        IPoint::new(
+            (x + sign * nx).round() as i32,
+            (y + sign * ny).round() as i32,
+        )
+    };
+    let corners = [
+        corner(from, 1.),
+        corner(to, 1.),
+        corner(to, -1.),
+        corner(from, -1.),
+    ];
+    // imageproc rejects polygons whose first and last points coincide
+    if corners[0] == corners[3] {
+        draw_line_segment_mut(image, (from[0], from[1]), (to[0], to[1]), color);
+    } else {
+        draw_polygon_mut(image, &corners, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BACKGROUND: Rgb<u8> = Rgb([0, 0, 0]);
+
+    #[test]
+    fn draws_shapes_on_image() {
+        let mut image = RgbImage::new(10, 10);
+        Overlay::new()
+            .point([5., 5.], 1., Color::RED)
+            .line([0., 2.], [9., 2.], Color::GREEN)
+            .draw_on_image(&mut image, None);
+
+        assert_eq!(image.get_pixel(5, 5), &Rgb([255, 0, 0]));
+        assert_eq!(image.get_pixel(5, 4), &Rgb([255, 0, 0]));
+        assert_eq!(image.get_pixel(0, 2), &Rgb([0, 255, 0]));
+        assert_eq!(image.get_pixel(9, 2), &Rgb([0, 255, 0]));
+        assert_eq!(image.get_pixel(0, 0), &BACKGROUND);
+        assert_eq!(image.get_pixel(9, 9), &BACKGROUND);
+    }
+
+    #[test]
+    fn draws_thick_lines_across_their_width() {
+        let mut image = RgbImage::new(10, 10);
+        Overlay::new()
+            .line([1., 5.], [8., 5.], Stroke::new(Color::BLUE, 3.))
+            .draw_on_image(&mut image, None);
+
+        for y in 4..=6 {
+            assert_eq!(image.get_pixel(4, y), &Rgb([0, 0, 255]), "row {y}");
+        }
+        assert_eq!(image.get_pixel(4, 1), &BACKGROUND);
+        assert_eq!(image.get_pixel(4, 8), &BACKGROUND);
+    }
+
+    #[test]
+    fn text_without_font_is_left_out() {
+        let mut image = RgbImage::new(10, 10);
+        Overlay::new()
+            .text([0., 9.], "text", 8., Color::WHITE)
+            .draw_on_image(&mut image, None);
+
+        assert!(image.pixels().all(|pixel| *pixel == BACKGROUND));
+    }
+}
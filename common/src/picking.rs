@@ -0,0 +1,402 @@
+use crate::{
+    overlay::{Color, Overlay, Point, Stroke},
+    viewer::ImageTransform,
+};
+use egui::{CollapsingHeader, ComboBox, DragValue, Grid, Key, Pos2, Response};
+
+/// Distance in screen points within which a click or drag grabs a vertex.
+const HANDLE_RADIUS: f32 = 6.;
+
+/// A point or region picked on an image, in image pixel coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pick {
+    Point(Point),
+    Rectangle { min: Point, max: Point },
+    Polygon(Vec<Point>),
+}
+
+impl Pick {
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Point(_) => "point",
+            Self::Rectangle { .. } => "rectangle",
+            Self::Polygon(_) => "polygon",
+        }
+    }
+
+    /// The vertices that can be dragged, which for a rectangle are its four corners clockwise
+    /// from `min`.
+    #[must_use]
+    pub fn handles(&self) -> Vec<Point> {
+        match self {
+            Self::Point(point) => vec![*point],
+            Self::Rectangle { min, max } => {
+                vec![*min, [max[0], min[1]], *max, [min[0], max[1]]]
+            }
+            Self::Polygon(points) => points.clone(),
+        }
+    }
+
+    /// Moves the vertex `index` of [`Pick::handles`] to `position`.
+    pub fn set_handle(&mut self, index: usize, position: Point) {
+        match self {
+            Self::Point(point) => *point = position,
+            Self::Rectangle { min, max } => match index {
+                0 => *min = position,
+                1 => [max[0], min[1]] = position,
+                2 => *max = position,
+                _ => [min[0], max[1]] = position,
+            },
+            Self::Polygon(points) => points[index] = position,
+        }
+    }
+
+    /// Orders the corners of a rectangle after one has been dragged past another.
+    fn normalize(&mut self) {
+        if let Self::Rectangle { min, max } = self {
+            for axis in 0..2 {
+                if min[axis] > max[axis] {
+                    std::mem::swap(&mut min[axis], &mut max[axis]);
+                }
+            }
+        }
+    }
+
+    /// Whether a region is too small to be meant, e.g. after a click with the rectangle tool.
+    fn is_empty(&self) -> bool {
+        match self {
+            Self::Point(_) => false,
+            Self::Rectangle { min, max } => (max[0] - min[0]) * (max[1] - min[1]) < 1.,
+            Self::Polygon(points) => points.len() < 3,
+        }
+    }
+}
+
+/// Draws `picks` numbered from 1, with the pick at index `selected` highlighted.
+#[must_use]
+pub fn picks_overlay(picks: &[Pick], selected: Option<usize>) -> Overlay {
+    let mut overlay = Overlay::new();
+    for (index, pick) in picks.iter().enumerate() {
+        let color = if selected == Some(index) {
+            Color::YELLOW
+        } else {
+            Color::MAGENTA
+        };
+        let stroke = Stroke::new(color, 2.);
+        let label_position = match pick {
+            Pick::Point(point) => {
+                overlay.point(*point, 3., color);
+                [point[0] + 5., point[1] - 5.]
+            }
+            Pick::Rectangle { .. } | Pick::Polygon(_) => {
+                let handles = pick.handles();
+                let label_position = handles[0];
+                overlay.polygon(handles, stroke);
+                label_position
+            }
+        };
+        overlay.text(label_position, (index + 1).to_string(), 14., color);
+    }
+    overlay
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PickTool {
+    /// Leave the image alone, e.g. to select a region for the statistics.
+    #[default]
+    None,
+    Point,
+    Rectangle,
+    Polygon,
+}
+
+impl PickTool {
+    pub const ALL: [Self; 4] = [Self::None, Self::Point, Self::Rectangle, Self::Polygon];
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Point => "point",
+            Self::Rectangle => "rectangle",
+            Self::Polygon => "polygon",
+        }
+    }
+
+    fn hint(self) -> &'static str {
+        match self {
+            Self::None => "select a tool to pick on the image",
+            Self::Point => "click to add a point",
+            Self::Rectangle => "drag to add a rectangle",
+            Self::Polygon => {
+                "click to add vertices, then double-click, press Enter or click the first vertex \
+                 to close the polygon. Backspace removes the last vertex"
+            }
+        }
+    }
+}
+
+/// Points and regions picked on the shown image, and the state of the tools that edit them. Drag
+/// a vertex to move it, click a vertex to select its pick, and press Delete to remove the
+/// selected pick.
+#[derive(Debug, Default)]
+pub struct Picker {
+    pub tool: PickTool,
+    pub picks: Vec<Pick>,
+    selected: Option<usize>,
+    /// Vertices of the polygon being drawn.
+    polygon: Vec<Point>,
+    /// Pick and vertex being dragged.
+    dragging: Option<(usize, usize)>,
+}
+
+impl Picker {
+    /// The picked points in the order they were picked, e.g. as correspondences.
+    #[must_use]
+    pub fn points(&self) -> Vec<Point> {
+        self.picks
+            .iter()
+            .filter_map(|pick| match pick {
+                Pick::Point(point) => Some(*point),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Lets the active tool handle input on the image shown in `response`, and paints the picks
+    /// on top of it. `image_size` is the size of the shown image in pixels, so that the picks are
+    /// in image coordinates regardless of how the image is scaled. Returns whether the picks
+    /// changed.
+    pub fn interact(&mut self, ui: &egui::Ui, response: &Response, image_size: [usize; 2]) -> bool {
+        let transform = ImageTransform::new(response.rect, image_size);
+        let changed = self.tool != PickTool::None && self.handle_input(ui, response, &transform);
+
+        picks_overlay(&self.picks, self.selected).paint(ui.painter(), &transform);
+        if self.tool != PickTool::None {
+            let painter = ui.painter();
+            for handle in self.picks.iter().flat_map(Pick::handles) {
+                painter.circle_stroke(
+                    transform.to_screen(handle),
+                    HANDLE_RADIUS / 2.,
+                    (1., egui::Color32::WHITE),
+                );
+            }
+        }
+        if !self.polygon.is_empty() {
+            let mut polygon = self.polygon.clone();
+            if let Some(hover) = response.hover_pos() {
+                polygon.push(transform.to_image(hover));
+            }
+            let mut overlay = Overlay::new();
+            overlay.polyline(polygon, Stroke::new(Color::YELLOW, 2.));
+            overlay.paint(ui.painter(), &transform);
+        }
+
+        changed
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn handle_input(
+        &mut self,
+        ui: &egui::Ui,
+        response: &Response,
+        transform: &ImageTransform,
+    ) -> bool {
+        let [width, height] = transform.image_size;
+        let to_image = |pos: Pos2| {
+            let [x, y] = transform.to_image(pos);
+            [x.clamp(0., width as f32), y.clamp(0., height as f32)]
+        };
+        let mut changed = false;
+
+        if response.drag_started() {
+            if let Some(origin) = ui.input(|i| i.pointer.press_origin()) {
+                self.dragging = self.handle_at(origin, transform);
+                if self.dragging.is_none() && self.tool == PickTool::Rectangle {
+                    let corner = to_image(origin);
+                    self.picks.push(Pick::Rectangle {
+                        min: corner,
+                        max: corner,
+                    });
+                    self.dragging = Some((self.picks.len() - 1, 2));
+                }
+                self.selected = self.dragging.map(|(pick, _)| pick);
+            }
+        }
+        if response.dragged() {
+            if let (Some((pick, handle)), Some(pos)) =
+                (self.dragging, response.interact_pointer_pos())
+            {
+                self.picks[pick].set_handle(handle, to_image(pos));
+                changed = true;
+            }
+        }
+        if response.drag_released() {
+            if let Some((pick, _)) = self.dragging.take() {
+                self.picks[pick].normalize();
+                if self.picks[pick].is_empty() {
+                    self.delete(pick);
+                }
+                changed = true;
+            }
+        }
+
+        if response.double_clicked() {
+            changed |= self.close_polygon();
+        } else if let (true, Some(pos)) = (response.clicked(), response.interact_pointer_pos()) {
+            let closes_polygon = self.polygon.len() >= 3
+                && pos.distance(transform.to_screen(self.polygon[0])) <= HANDLE_RADIUS;
+            if closes_polygon {
+                changed |= self.close_polygon();
+            } else if let Some((pick, _)) = self.handle_at(pos, transform) {
+                self.selected = Some(pick);
+            } else {
+                match self.tool {
+                    PickTool::Point => {
+                        self.picks.push(Pick::Point(to_image(pos)));
+                        self.selected = Some(self.picks.len() - 1);
+                        changed = true;
+                    }
+                    PickTool::Polygon => self.polygon.push(to_image(pos)),
+                    PickTool::None | PickTool::Rectangle => self.selected = None,
+                }
+            }
+        }
+
+        // the keys belong to a focused widget, such as a text field, if there is one
+        let unfocused = ui.memory(|m| m.focus().is_none());
+        let (delete, enter, backspace) = ui.input(|i| {
+            (
+                unfocused && i.key_pressed(Key::Delete),
+                unfocused && i.key_pressed(Key::Enter),
+                unfocused && i.key_pressed(Key::Backspace),
+            )
+        });
+        if delete {
+            if let Some(selected) = self.selected {
+                self.delete(selected);
+                changed = true;
+            }
+        }
+        if enter {
+            changed |= self.close_polygon();
+        }
+        if backspace {
+            self.polygon.pop();
+        }
+
+        changed
+    }
+
+    /// The pick and vertex closest to `pos`, if any is within reach.
+    fn handle_at(&self, pos: Pos2, transform: &ImageTransform) -> Option<(usize, usize)> {
+        self.picks
+            .iter()
+            .enumerate()
+            .flat_map(|(pick_index, pick)| {
+                pick.handles()
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(handle_index, handle)| {
+                        let distance = pos.distance(transform.to_screen(handle));
+                        (distance, (pick_index, handle_index))
+                    })
+            })
+            .filter(|(distance, _)| *distance <= HANDLE_RADIUS)
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, handle)| handle)
+    }
+
+    fn close_polygon(&mut self) -> bool {
+        if self.polygon.len() < 3 {
+            return false;
+        }
+        self.picks
+            .push(Pick::Polygon(std::mem::take(&mut self.polygon)));
+        self.selected = Some(self.picks.len() - 1);
+        true
+    }
+
+    fn delete(&mut self, index: usize) {
+        self.picks.remove(index);
+        self.selected = None;
+        self.dragging = None;
+    }
+
+    /// Draws the tool selection and the list of picks, whose coordinates can be edited. Returns
+    /// whether the picks changed.
+    pub fn draw(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        CollapsingHeader::new("picking").show(ui, |ui| {
+            ComboBox::from_label("tool")
+                .selected_text(self.tool.name())
+                .show_ui(ui, |ui| {
+                    for tool in PickTool::ALL {
+                        ui.selectable_value(&mut self.tool, tool, tool.name());
+                    }
+                });
+            if self.tool != PickTool::Polygon {
+                self.polygon.clear();
+            }
+            ui.label(self.tool.hint());
+
+            let mut delete = None;
+            Grid::new("picks").striped(true).show(ui, |ui| {
+                for (index, pick) in self.picks.iter_mut().enumerate() {
+                    if ui
+                        .selectable_label(
+                            self.selected == Some(index),
+                            format!("{} {}", index + 1, pick.name()),
+                        )
+                        .clicked()
+                    {
+                        self.selected = Some(index);
+                    }
+                    ui.horizontal(|ui| match pick {
+                        Pick::Point(point) => changed |= draw_point(point, ui),
+                        Pick::Rectangle { min, max } => {
+                            changed |= draw_point(min, ui);
+                            changed |= draw_point(max, ui);
+                        }
+                        Pick::Polygon(points) => {
+                            ui.label(format!("{} vertices", points.len()));
+                        }
+                    });
+                    if ui.small_button("delete").clicked() {
+                        delete = Some(index);
+                    }
+                    ui.end_row();
+                }
+            });
+            if let Some(index) = delete {
+                self.delete(index);
+                changed = true;
+            }
+
+            if ui.button("clear").clicked() && !self.picks.is_empty() {
+                self.picks.clear();
+                self.selected = None;
+                self.dragging = None;
+                changed = true;
+            }
+        });
+        if changed {
+            for pick in &mut self.picks {
+                pick.normalize();
+            }
+        }
+        changed
+    }
+}
+
+/// Draws editable coordinates of `point`, and returns whether they changed.
+fn draw_point(point: &mut Point, ui: &mut egui::Ui) -> bool {
+    let mut changed = false;
+    for coordinate in point {
+        changed |= ui
+            .add(DragValue::new(coordinate).speed(0.5).max_decimals(1))
+            .changed();
+    }
+    changed
+}
@@ -0,0 +1,233 @@
+use crate::{
+    picking::{picks_overlay, Pick},
+    preset::Preset,
+    util::{TemporalFilterConfiguration, TemporalFilterSlot},
+};
+use opencv::{
+    core::{self, Mat, Size},
+    imgproc,
+};
+use tracing::debug_span;
+
+/// Parameters of the processing pipeline shared by the lab applications. Every stage can be
+/// toggled without losing its parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipelineConfiguration {
+    pub mirror: bool,
+    pub temporal: bool,
+    pub temporal_filter: TemporalFilterConfiguration,
+    pub blur: bool,
+    pub blur_sigma: f64,
+    pub bilateral: bool,
+    pub bilateral_diameter: i32,
+    pub bilateral_sigma_color: f64,
+    pub bilateral_sigma_space: f64,
+    pub canny: bool,
+    pub canny_low: f64,
+    pub canny_high: f64,
+    /// Points and regions picked in the GUI, in pixel coordinates of the output.
+    pub picks: Vec<Pick>,
+    /// Draw `picks` onto the output, so that they end up in snapshots and recordings.
+    pub annotate: bool,
+}
+
+impl Default for PipelineConfiguration {
+    fn default() -> Self {
+        Self {
+            mirror: false,
+            temporal: false,
+            temporal_filter: TemporalFilterConfiguration::default(),
+            blur: false,
+            blur_sigma: 1.,
+            bilateral: false,
+            bilateral_diameter: 10,
+            bilateral_sigma_color: 100.,
+            bilateral_sigma_space: 100.,
+            canny: false,
+            canny_low: 10.,
+            canny_high: 15.,
+            picks: Vec::new(),
+            annotate: false,
+        }
+    }
+}
+
+impl From<Preset> for PipelineConfiguration {
+    fn from(preset: Preset) -> Self {
+        let default = Self::default();
+        match preset {
+            Preset::Raw => default,
+            Preset::Smooth => Self {
+                blur: true,
+                blur_sigma: 2.,
+                ..default
+            },
+            Preset::Denoise => Self {
+                temporal: true,
+                temporal_filter: TemporalFilterConfiguration::MovingAverage { size: 5 },
+                bilateral: true,
+                ..default
+            },
+            Preset::Edges => Self {
+                mirror: true,
+                temporal: true,
+                temporal_filter: TemporalFilterConfiguration::MovingAverage { size: 5 },
+                blur: true,
+                blur_sigma: 4.,
+                bilateral: true,
+                canny: true,
+                ..default
+            },
+        }
+    }
+}
+
+/// A step of the pipeline whose output can be inspected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Input,
+    TemporalFilter,
+    Blur,
+    Bilateral,
+    Canny,
+    Annotation,
+}
+
+impl Stage {
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Input => "input",
+            Self::TemporalFilter => "temporal filter",
+            Self::Blur => "blur",
+            Self::Bilateral => "bilateral",
+            Self::Canny => "canny",
+            Self::Annotation => "annotation",
+        }
+    }
+}
+
+/// Outputs of the enabled stages of one pipeline run, in processing order, starting with the
+/// (possibly mirrored) input.
+pub struct StageOutputs(Vec<(Stage, Mat)>);
+
+impl StageOutputs {
+    /// Output of the last enabled stage.
+    #[must_use]
+    pub fn output(&self) -> &Mat {
+        latest(&self.0)
+    }
+
+    #[must_use]
+    pub fn into_output(mut self) -> Mat {
+        self.0.pop().expect("input should always be present").1
+    }
+
+    /// Output of `stage`, if it was enabled.
+    #[must_use]
+    pub fn get(&self, stage: Stage) -> Option<&Mat> {
+        self.0
+            .iter()
+            .find_map(|(s, mat)| (*s == stage).then_some(mat))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(Stage, Mat)> {
+        self.0.iter()
+    }
+}
+
+fn latest(stages: &[(Stage, Mat)]) -> &Mat {
+    &stages.last().expect("input should always be present").1
+}
+
+/// The stateful part of the processing pipeline. The configuration is passed on every call, so
+/// that it can be changed between frames.
+#[derive(Default)]
+pub struct Pipeline {
+    temporal_filter: TemporalFilterSlot,
+}
+
+impl Pipeline {
+    /// Runs `frame` through the stages enabled in `configuration`.
+    ///
+    /// # Errors
+    ///
+    /// * `opencv::Error` if a stage fails, e.g. because it does not support the type of its input.
+    pub fn process(
+        &mut self,
+        configuration: &PipelineConfiguration,
+        frame: Mat,
+    ) -> opencv::Result<StageOutputs> {
+        let frame = if configuration.mirror {
+            let _span = debug_span!("mirror").entered();
+            let mut out = Mat::default();
+            core::flip(&frame, &mut out, 1)?;
+            out
+        } else {
+            frame
+        };
+        let mut stages = vec![(Stage::Input, frame)];
+
+        if configuration.temporal {
+            let _span = debug_span!("temporal filter").entered();
+            let out = self.temporal_filter.filter(
+                Some(&configuration.temporal_filter),
+                latest(&stages).clone(),
+            )?;
+            stages.push((Stage::TemporalFilter, out));
+        } else {
+            self.temporal_filter.clear();
+        }
+
+        if configuration.blur {
+            let _span = debug_span!("blur").entered();
+            let mut out = Mat::default();
+            imgproc::gaussian_blur(
+                latest(&stages),
+                &mut out,
+                Size::new(0, 0),
+                configuration.blur_sigma,
+                configuration.blur_sigma,
+                core::BORDER_REFLECT,
+            )?;
+            stages.push((Stage::Blur, out));
+        }
+
+        if configuration.bilateral {
+            let _span = debug_span!("bilateral").entered();
+            let mut out = Mat::default();
+            imgproc::bilateral_filter(
+                latest(&stages),
+                &mut out,
+                configuration.bilateral_diameter,
+                configuration.bilateral_sigma_color,
+                configuration.bilateral_sigma_space,
+                core::BORDER_DEFAULT,
+            )?;
+            stages.push((Stage::Bilateral, out));
+        }
+
+        if configuration.canny {
+            let _span = debug_span!("canny").entered();
+            let mut out = Mat::default();
+            imgproc::canny(
+                latest(&stages),
+                &mut out,
+                configuration.canny_low,
+                configuration.canny_high,
+                3,
+                false,
+            )?;
+            stages.push((Stage::Canny, out));
+        }
+
+        if configuration.annotate && !configuration.picks.is_empty() {
+            let _span = debug_span!("annotation").entered();
+            let mut out = latest(&stages).clone();
+            picks_overlay(&configuration.picks, None).draw_on_mat(&mut out)?;
+            stages.push((Stage::Annotation, out));
+        }
+
+        Ok(StageOutputs(stages))
+    }
+}
@@ -0,0 +1,38 @@
+/// Named starting points for the configuration of the lab applications. Each application maps a
+/// preset to the closest equivalent of its own processing options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Preset {
+    /// Every stage disabled
+    #[default]
+    Raw,
+    /// Gaussian blur
+    Smooth,
+    /// Moving average over frames followed by a bilateral filter
+    Denoise,
+    /// Averaging, blurring and bilateral filtering followed by Canny edge detection
+    Edges,
+}
+
+impl Preset {
+    pub const ALL: [Self; 4] = [Self::Raw, Self::Smooth, Self::Denoise, Self::Edges];
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Raw => "raw",
+            Self::Smooth => "smooth",
+            Self::Denoise => "denoise",
+            Self::Edges => "edges",
+        }
+    }
+
+    /// The preset following this one, wrapping around.
+    #[must_use]
+    pub fn next(self) -> Self {
+        let position = Self::ALL
+            .iter()
+            .position(|&preset| preset == self)
+            .unwrap_or_default();
+        Self::ALL[(position + 1) % Self::ALL.len()]
+    }
+}
@@ -0,0 +1,169 @@
+use egui::{CollapsingHeader, Grid, Sense};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::{span, Subscriber};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+/// Number of recent spans of each name that the statistics are computed over.
+const WINDOW: usize = 120;
+
+/// Rolling statistics of the durations of spans with the same name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimingStats {
+    pub mean: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+    /// Spans per second.
+    pub rate: f64,
+}
+
+/// End time and duration of the latest spans of one name.
+#[derive(Debug, Default)]
+struct Timings(VecDeque<(Instant, Duration)>);
+
+impl Timings {
+    fn push(&mut self, end: Instant, duration: Duration) {
+        if self.0.len() == WINDOW {
+            self.0.pop_front();
+        }
+        self.0.push_back((end, duration));
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    fn stats(&self) -> TimingStats {
+        let mut durations: Vec<_> = self.0.iter().map(|(_, duration)| *duration).collect();
+        durations.sort_unstable();
+        let n = durations.len();
+        let rate = match (self.0.front(), self.0.back()) {
+            (Some((first, _)), Some((last, _))) if last > first => {
+                (n - 1) as f64 / last.duration_since(*first).as_secs_f64()
+            }
+            _ => 0.,
+        };
+        TimingStats {
+            mean: durations.iter().sum::<Duration>() / (n.max(1) as u32),
+            p95: durations
+                .get((n * 95).div_ceil(100).saturating_sub(1))
+                .copied()
+                .unwrap_or_default(),
+            max: durations.last().copied().unwrap_or_default(),
+            rate,
+        }
+    }
+}
+
+/// Collects the durations of `tracing` spans by name, in the order they are first seen. Clones
+/// share the same measurements. It is installed together with the logging by
+/// [`crate::telemetry::init`].
+#[derive(Debug, Clone, Default)]
+pub struct Profiler(Arc<Mutex<Vec<(&'static str, Timings)>>>);
+
+impl Profiler {
+    /// A layer that records every closed span into this profiler.
+    #[must_use]
+    pub fn layer(&self) -> ProfilerLayer {
+        ProfilerLayer(self.clone())
+    }
+
+    fn record(&self, name: &'static str, end: Instant, duration: Duration) {
+        let mut timings = self.0.lock().unwrap();
+        let position = timings
+            .iter()
+            .position(|(n, _)| *n == name)
+            .unwrap_or_else(|| {
+                timings.push((name, Timings::default()));
+                timings.len() - 1
+            });
+        timings[position].1.push(end, duration);
+    }
+
+    /// Statistics of every span name seen so far.
+    #[must_use]
+    pub fn stats(&self) -> Vec<(&'static str, TimingStats)> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, timings)| (*name, timings.stats()))
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+}
+
+/// Start of a span, stored in its extensions.
+struct Started(Instant);
+
+/// `tracing_subscriber` layer feeding a [`Profiler`].
+pub struct ProfilerLayer(Profiler);
+
+impl<S> Layer<S> for ProfilerLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, _attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(Started(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let start = span
+            .extensions()
+            .get::<Started>()
+            .map(|Started(start)| *start);
+        if let Some(start) = start {
+            let end = Instant::now();
+            self.0.record(span.name(), end, end.duration_since(start));
+        }
+    }
+}
+
+/// Draws a collapsible table of the span timings, with bars proportional to the mean durations.
+#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+pub fn draw_profiler(profiler: &Profiler, ui: &mut egui::Ui) {
+    CollapsingHeader::new("profiler").show(ui, |ui| {
+        let stats = profiler.stats();
+        let longest = stats
+            .iter()
+            .map(|(_, stats)| stats.mean)
+            .max()
+            .unwrap_or_default()
+            .max(Duration::from_micros(1));
+        let ms = |duration: Duration| format!("{:.2}", duration.as_secs_f64() * 1000.);
+
+        Grid::new("profiler").striped(true).show(ui, |ui| {
+            for heading in ["span", "mean ms", "p95 ms", "max ms", "per s", ""] {
+                ui.strong(heading);
+            }
+            ui.end_row();
+            for (name, stats) in &stats {
+                ui.label(*name);
+                ui.label(ms(stats.mean));
+                ui.label(ms(stats.p95));
+                ui.label(ms(stats.max));
+                ui.label(format!("{:.1}", stats.rate));
+
+                let (rect, _) = ui.allocate_exact_size(egui::vec2(80., 10.), Sense::hover());
+                let fraction = (stats.mean.as_secs_f64() / longest.as_secs_f64()) as f32;
+                let mut bar = rect;
+                bar.set_width(rect.width() * fraction);
+                ui.painter()
+                    .rect_filled(bar, 2., ui.visuals().selection.bg_fill);
+                ui.end_row();
+            }
+        });
+
+        if ui.button("reset").clicked() {
+            profiler.clear();
+        }
+    });
+}
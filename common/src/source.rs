@@ -0,0 +1,366 @@
+use image::{
+    imageops::{self, FilterType},
+    ImageBuffer, ImageFormat, Rgb, RgbImage,
+};
+pub use nokhwa::utils::CameraIndex;
+use nokhwa::{
+    pixel_format::RgbFormat,
+    utils::{CameraFormat, FrameFormat, RequestedFormat, RequestedFormatType, Resolution},
+    Camera, NokhwaError,
+};
+use std::{
+    convert::Infallible,
+    fmt::{self, Display},
+    num::ParseIntError,
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::{debug, debug_span};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SourceError {
+    #[error("camera error: {0}")]
+    Camera(#[from] NokhwaError),
+    #[error("image error: {0}")]
+    Image(#[from] image::ImageError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("no image files in directory {0}")]
+    EmptyDirectory(PathBuf),
+}
+
+/// Width and height of a frame or window, written as e.g. `640x480`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FrameSize {
+    #[must_use]
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FrameSizeError {
+    #[error("expected a size on the form WIDTHxHEIGHT")]
+    MissingSeparator,
+    #[error("invalid dimension: {0}")]
+    InvalidDimension(#[from] ParseIntError),
+}
+
+impl FromStr for FrameSize {
+    type Err = FrameSizeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = s
+            .split_once(['x', 'X'])
+            .ok_or(FrameSizeError::MissingSeparator)?;
+        Ok(Self::new(width.trim().parse()?, height.trim().parse()?))
+    }
+}
+
+impl Display for FrameSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// Requested properties of the frames from a source. Cameras pick their closest supported
+/// format, while other sources scale their frames to `resolution` and are paced to `fps`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SourceOptions {
+    pub resolution: Option<FrameSize>,
+    pub fps: Option<f64>,
+}
+
+impl SourceOptions {
+    fn fps(&self) -> f64 {
+        self.fps.unwrap_or(DEFAULT_FPS)
+    }
+
+    /// Scales `image` to the requested resolution, if any.
+    fn resize(&self, image: RgbImage) -> RgbImage {
+        match self.resolution {
+            Some(FrameSize { width, height })
+                if (width, height) != (image.width(), image.height()) =>
+            {
+                imageops::resize(&image, width, height, FilterType::Triangle)
+            }
+            _ => image,
+        }
+    }
+}
+
+/// Something that produces a sequence of frames.
+pub trait FrameSource {
+    /// The next frame, or `None` if the source is exhausted.
+    ///
+    /// # Errors
+    ///
+    /// * `SourceError` if a frame could not be read. The source may still produce frames later.
+    fn next_frame(&mut self) -> Result<Option<RgbImage>, SourceError>;
+}
+
+/// Describes a frame source, so that it can be opened on the thread that will read from it.
+#[derive(Debug, Clone)]
+pub enum SourceSpec {
+    Camera(CameraIndex),
+    /// A single image, repeated.
+    File(PathBuf),
+    /// The image files in a directory, in file name order, looping.
+    Directory(PathBuf),
+    /// A generated moving test pattern.
+    Synthetic,
+}
+
+impl SourceSpec {
+    /// # Errors
+    ///
+    /// * `SourceError` if the source cannot be opened.
+    pub fn open(&self, options: &SourceOptions) -> Result<Box<dyn FrameSource>, SourceError> {
+        Ok(match self {
+            Self::Camera(index) => Box::new(CameraSource::new(index.clone(), options)?),
+            Self::File(path) => Box::new(ImageFileSource::new(path, options)?),
+            Self::Directory(path) => Box::new(DirectorySource::new(path, options)?),
+            Self::Synthetic => Box::new(SyntheticSource::new(options)),
+        })
+    }
+}
+
+/// Parses `synthetic`, a camera index, a path to an image file or a directory, or otherwise a
+/// camera name.
+impl FromStr for SourceSpec {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let path = Path::new(s);
+        Ok(if s == "synthetic" {
+            Self::Synthetic
+        } else if let Ok(index) = s.parse() {
+            Self::Camera(CameraIndex::Index(index))
+        } else if path.is_dir() {
+            Self::Directory(path.into())
+        } else if path.is_file() {
+            Self::File(path.into())
+        } else {
+            Self::Camera(CameraIndex::String(s.into()))
+        })
+    }
+}
+
+impl Display for SourceSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Camera(index) => write!(f, "camera {index}"),
+            Self::File(path) => write!(f, "file {}", path.display()),
+            Self::Directory(path) => write!(f, "directory {}", path.display()),
+            Self::Synthetic => write!(f, "synthetic"),
+        }
+    }
+}
+
+/// Frame rate of sources that are not limited by hardware.
+const DEFAULT_FPS: f64 = 30.;
+/// Lowest frame rate that frames are paced to, one frame every 1000 seconds.
+pub const MIN_FPS: f64 = 1e-3;
+
+/// Delivers frames at a fixed rate from sources that could otherwise produce them as fast as
+/// they are read.
+struct Pacer {
+    interval: Duration,
+    next: Option<Instant>,
+}
+
+impl Pacer {
+    fn new(fps: f64) -> Self {
+        Self {
+            // also keeps NaN from reaching `from_secs_f64`, which panics on it
+            interval: Duration::from_secs_f64(1. / fps.max(MIN_FPS)),
+            next: None,
+        }
+    }
+
+    fn wait(&mut self) {
+        let now = Instant::now();
+        let next = self.next.map_or(now, |next| next.max(now));
+        thread::sleep(next - now);
+        self.next = Some(next + self.interval);
+    }
+}
+
+pub struct CameraSource(Camera);
+
+impl CameraSource {
+    /// Opens the camera on `index` with the format closest to the requested one. Without a
+    /// requested resolution or frame rate, the highest frame rate is used.
+    ///
+    /// # Errors
+    ///
+    /// * `SourceError::Camera` if there is no camera on `index`, or its stream cannot be opened.
+    pub fn new(index: CameraIndex, options: &SourceOptions) -> Result<Self, SourceError> {
+        let requested = if options.resolution.is_none() && options.fps.is_none() {
+            RequestedFormatType::AbsoluteHighestFrameRate
+        } else {
+            let FrameSize { width, height } =
+                options.resolution.unwrap_or(FrameSize::new(640, 480));
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let fps = options.fps().round() as u32;
+            RequestedFormatType::Closest(CameraFormat::new(
+                Resolution::new(width, height),
+                FrameFormat::MJPEG,
+                fps,
+            ))
+        };
+        let mut camera = Camera::new(index, RequestedFormat::new::<RgbFormat>(requested))?;
+        if !camera.is_stream_open() {
+            camera.open_stream()?;
+        }
+        {
+            let info = camera.info();
+            debug!(?info, "opened camera");
+        }
+        Ok(Self(camera))
+    }
+}
+
+impl FrameSource for CameraSource {
+    fn next_frame(&mut self) -> Result<Option<RgbImage>, SourceError> {
+        let frame = debug_span!("capture").in_scope(|| self.0.frame())?;
+        let frame = debug_span!("decode").in_scope(|| frame.decode_image::<RgbFormat>())?;
+        Ok(Some(frame))
+    }
+}
+
+pub struct ImageFileSource {
+    image: RgbImage,
+    pacer: Pacer,
+}
+
+impl ImageFileSource {
+    /// # Errors
+    ///
+    /// * `SourceError::Image` if the file cannot be read as an image.
+    pub fn new<P: AsRef<Path>>(path: P, options: &SourceOptions) -> Result<Self, SourceError> {
+        Ok(Self {
+            image: options.resize(image::open(path)?.to_rgb8()),
+            pacer: Pacer::new(options.fps()),
+        })
+    }
+}
+
+impl FrameSource for ImageFileSource {
+    fn next_frame(&mut self) -> Result<Option<RgbImage>, SourceError> {
+        self.pacer.wait();
+        let _span = debug_span!("capture").entered();
+        Ok(Some(self.image.clone()))
+    }
+}
+
+pub struct DirectorySource {
+    paths: Vec<PathBuf>,
+    position: usize,
+    options: SourceOptions,
+    pacer: Pacer,
+}
+
+impl DirectorySource {
+    /// # Errors
+    ///
+    /// * `SourceError::Io` if the directory cannot be listed.
+    /// * `SourceError::EmptyDirectory` if it contains no files with an image extension.
+    pub fn new<P: AsRef<Path>>(path: P, options: &SourceOptions) -> Result<Self, SourceError> {
+        let path = path.as_ref();
+        let mut paths = path
+            .read_dir()?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.retain(|path| path.is_file() && ImageFormat::from_path(path).is_ok());
+        paths.sort();
+        if paths.is_empty() {
+            return Err(SourceError::EmptyDirectory(path.into()));
+        }
+        debug!(n_images = paths.len(), ?path, "opened directory");
+        Ok(Self {
+            paths,
+            position: 0,
+            options: *options,
+            pacer: Pacer::new(options.fps()),
+        })
+    }
+}
+
+impl FrameSource for DirectorySource {
+    fn next_frame(&mut self) -> Result<Option<RgbImage>, SourceError> {
+        self.pacer.wait();
+        let _span = debug_span!("decode").entered();
+        let path = &self.paths[self.position];
+        self.position = (self.position + 1) % self.paths.len();
+        Ok(Some(self.options.resize(image::open(path)?.to_rgb8())))
+    }
+}
+
+/// Scrolling color gradients with a bright square bouncing across them, so that both smoothing
+/// and motion are visible in the processed output.
+pub struct SyntheticSource {
+    width: u32,
+    height: u32,
+    frame: u32,
+    pacer: Pacer,
+}
+
+impl SyntheticSource {
+    /// Generates frames of the requested resolution, or 640x480.
+    #[must_use]
+    pub fn new(options: &SourceOptions) -> Self {
+        let FrameSize { width, height } = options.resolution.unwrap_or(FrameSize::new(640, 480));
+        Self {
+            width,
+            height,
+            frame: 0,
+            pacer: Pacer::new(options.fps()),
+        }
+    }
+}
+
+impl Default for SyntheticSource {
+    fn default() -> Self {
+        Self::new(&SourceOptions::default())
+    }
+}
+
+impl FrameSource for SyntheticSource {
+    fn next_frame(&mut self) -> Result<Option<RgbImage>, SourceError> {
+        self.pacer.wait();
+        let _span = debug_span!("capture").entered();
+        let t = self.frame;
+        self.frame = self.frame.wrapping_add(1);
+
+        let side = self.height.min(self.width) / 4;
+        let bounce = |t: u32, range: u32| {
+            let range = range.max(1);
+            let position = t % (2 * range);
+            position.min(2 * range - position)
+        };
+        let left = bounce(t.wrapping_mul(4), self.width - side);
+        let top = bounce(t.wrapping_mul(3), self.height - side);
+
+        #[allow(clippy::cast_possible_truncation)]
+        let image = ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            if (left..left + side).contains(&x) && (top..top + side).contains(&y) {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([
+                    (x.wrapping_add(t) % 256) as u8,
+                    (y.wrapping_add(t.wrapping_mul(2)) % 256) as u8,
+                    ((x + y) / 4 % 256) as u8,
+                ])
+            }
+        });
+        Ok(Some(image))
+    }
+}
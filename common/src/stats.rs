@@ -0,0 +1,426 @@
+use crate::viewer::ImageTransform;
+use egui::{
+    plot::{Legend, Line, Plot, PlotPoints},
+    Color32, Grid, Rect, Response, Stroke,
+};
+use image::{ImageBuffer, Pixel};
+#[cfg(feature = "opencv")]
+use opencv::core::{self, Mat, MatTraitConst, MatTraitConstManual, Rect as CvRect};
+
+/// Number of histogram bins, one per 8-bit intensity.
+pub const BINS: usize = 256;
+
+/// A rectangular region of an image, in pixel coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Roi {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Roi {
+    #[must_use]
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// The region spanned by two opposite corners in (possibly fractional) pixel coordinates, or
+    /// `None` if it does not cover any pixel.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn from_corners(a: [f32; 2], b: [f32; 2]) -> Option<Self> {
+        let [left, right] = [a[0].min(b[0]).max(0.), a[0].max(b[0]).max(0.)];
+        let [top, bottom] = [a[1].min(b[1]).max(0.), a[1].max(b[1]).max(0.)];
+        let (x, y) = (left.floor() as u32, top.floor() as u32);
+        let roi = Self::new(x, y, right.ceil() as u32 - x, bottom.ceil() as u32 - y);
+        (roi.width > 0 && roi.height > 0).then_some(roi)
+    }
+
+    /// The part of the region inside an image of the given size, or `None` if they do not
+    /// overlap.
+    #[must_use]
+    pub fn clamp(self, width: u32, height: u32) -> Option<Self> {
+        let right = (self.x.saturating_add(self.width)).min(width);
+        let bottom = (self.y.saturating_add(self.height)).min(height);
+        (self.x < right && self.y < bottom)
+            .then(|| Self::new(self.x, self.y, right - self.x, bottom - self.y))
+    }
+}
+
+/// Statistics of one 8-bit channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelStats {
+    pub histogram: [u64; BINS],
+    pub pixels: u64,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub min: u8,
+    pub max: u8,
+    /// Share of the pixels at the maximum value, i.e. clipped highlights.
+    pub saturated: f64,
+}
+
+impl ChannelStats {
+    /// Derives the statistics from the histogram alone, so that all sources share one
+    /// implementation.
+    #[allow(clippy::cast_precision_loss)]
+    fn from_histogram(histogram: &[u64; BINS]) -> Self {
+        let pixels: u64 = histogram.iter().sum();
+        let values = || {
+            histogram
+                .iter()
+                .enumerate()
+                .filter(|(_, count)| **count > 0)
+                .map(|(value, count)| (value as f64, *count as f64))
+        };
+        let n = (pixels as f64).max(1.);
+        let mean = values().map(|(value, count)| value * count).sum::<f64>() / n;
+        let variance = values()
+            .map(|(value, count)| (value - mean).powi(2) * count)
+            .sum::<f64>()
+            / n;
+        let bin = |position: Option<usize>| {
+            position.map_or(0, |bin| u8::try_from(bin).expect("bin should fit in a u8"))
+        };
+        Self {
+            histogram: *histogram,
+            pixels,
+            mean,
+            std_dev: variance.sqrt(),
+            min: bin(histogram.iter().position(|count| *count > 0)),
+            max: bin(histogram.iter().rposition(|count| *count > 0)),
+            saturated: histogram[BINS - 1] as f64 / n,
+        }
+    }
+
+    /// The share of pixels at or below each value, ending at 1.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn cumulative(&self) -> Vec<f64> {
+        let n = (self.pixels as f64).max(1.);
+        self.histogram
+            .iter()
+            .scan(0, |sum, count| {
+                *sum += count;
+                Some(*sum as f64 / n)
+            })
+            .collect()
+    }
+}
+
+/// Per-channel statistics of an 8-bit image, or of a region of it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageStats {
+    pub channels: Vec<ChannelStats>,
+}
+
+impl ImageStats {
+    fn from_pixels<'a>(channels: usize, pixels: impl Iterator<Item = &'a [u8]>) -> Self {
+        let mut histograms = vec![[0; BINS]; channels];
+        for pixel in pixels {
+            for (histogram, value) in histograms.iter_mut().zip(pixel) {
+                histogram[usize::from(*value)] += 1;
+            }
+        }
+        Self {
+            channels: histograms
+                .iter()
+                .map(ChannelStats::from_histogram)
+                .collect(),
+        }
+    }
+
+    /// Statistics of `image`, restricted to `roi` if given. A region outside the image gives
+    /// empty histograms.
+    #[must_use]
+    pub fn from_image<P>(image: &ImageBuffer<P, Vec<u8>>, roi: Option<Roi>) -> Self
+    where
+        P: Pixel<Subpixel = u8>,
+    {
+        let channels = usize::from(P::CHANNEL_COUNT);
+        let full = Roi::new(0, 0, image.width(), image.height());
+        let Some(Roi {
+            x,
+            y,
+            width,
+            height,
+        }) = roi.unwrap_or(full).clamp(image.width(), image.height())
+        else {
+            return Self::from_pixels(channels, std::iter::empty());
+        };
+
+        let raw = image.as_raw();
+        let stride = image.width() as usize * channels;
+        let rows = (y..y + height).flat_map(|row| {
+            let start = row as usize * stride + x as usize * channels;
+            raw[start..start + width as usize * channels].chunks_exact(channels)
+        });
+        Self::from_pixels(channels, rows)
+    }
+
+    /// Statistics of `mat`, restricted to `roi` if given. A region outside the image gives empty
+    /// histograms.
+    ///
+    /// # Errors
+    ///
+    /// * `opencv::Error` if `mat` is not 8-bit.
+    #[cfg(feature = "opencv")]
+    pub fn from_mat(mat: &Mat, roi: Option<Roi>) -> opencv::Result<Self> {
+        if mat.depth() != core::CV_8U {
+            return Err(opencv::Error::new(
+                core::StsUnsupportedFormat,
+                "image statistics are only supported for 8-bit images".into(),
+            ));
+        }
+        let channels = usize::try_from(mat.channels()).expect("channels should be positive");
+        let size = mat.size()?;
+        #[allow(clippy::cast_sign_loss)]
+        let (width, height) = (size.width as u32, size.height as u32);
+        let full = Roi::new(0, 0, width, height);
+        let Some(roi) = roi.unwrap_or(full).clamp(width, height) else {
+            return Ok(Self::from_pixels(channels, std::iter::empty()));
+        };
+
+        #[allow(clippy::cast_possible_wrap)]
+        let rect = CvRect::new(
+            roi.x as i32,
+            roi.y as i32,
+            roi.width as i32,
+            roi.height as i32,
+        );
+        // cloning the region makes it continuous
+        let region = Mat::roi(mat, rect)?.try_clone()?;
+        Ok(Self::from_pixels(
+            channels,
+            region.data_bytes()?.chunks_exact(channels),
+        ))
+    }
+}
+
+/// Display name and plot color of each channel, assuming RGB(A) channel order.
+fn channel_styles(channels: usize) -> &'static [(&'static str, Color32)] {
+    const GRAY: [(&str, Color32); 1] = [("gray", Color32::GRAY)];
+    const RGBA: [(&str, Color32); 4] = [
+        ("red", Color32::RED),
+        ("green", Color32::GREEN),
+        ("blue", Color32::LIGHT_BLUE),
+        ("alpha", Color32::GRAY),
+    ];
+    if channels == 1 {
+        &GRAY
+    } else {
+        &RGBA[..channels.min(RGBA.len())]
+    }
+}
+
+/// Draws a table of the statistics and a histogram plot, cumulative if `cumulative` is set.
+pub fn draw_stats(stats: &ImageStats, cumulative: bool, ui: &mut egui::Ui) {
+    let styles = channel_styles(stats.channels.len());
+
+    Grid::new("image statistics")
+        .num_columns(6)
+        .striped(true)
+        .show(ui, |ui| {
+            for heading in ["", "mean", "std", "min", "max", "saturated"] {
+                ui.strong(heading);
+            }
+            ui.end_row();
+            for (channel, (name, color)) in stats.channels.iter().zip(styles) {
+                ui.colored_label(*color, *name);
+                ui.label(format!("{:.1}", channel.mean));
+                ui.label(format!("{:.1}", channel.std_dev));
+                ui.label(channel.min.to_string());
+                ui.label(channel.max.to_string());
+                ui.label(format!("{:.2} %", channel.saturated * 100.));
+                ui.end_row();
+            }
+        });
+
+    #[allow(clippy::cast_precision_loss)]
+    Plot::new("histogram")
+        .height(200.)
+        .legend(Legend::default())
+        .allow_drag(false)
+        .allow_zoom(false)
+        .allow_scroll(false)
+        .include_x(0.)
+        .include_x((BINS - 1) as f64)
+        .include_y(0.)
+        .show(ui, |plot_ui| {
+            for (channel, (name, color)) in stats.channels.iter().zip(styles) {
+                let values = if cumulative {
+                    channel.cumulative()
+                } else {
+                    channel
+                        .histogram
+                        .iter()
+                        .map(|count| *count as f64)
+                        .collect()
+                };
+                let points: PlotPoints = values
+                    .into_iter()
+                    .enumerate()
+                    .map(|(value, count)| [value as f64, count])
+                    .collect();
+                plot_ui.line(Line::new(points).color(*color).name(name));
+            }
+        });
+}
+
+/// Lets the user select `roi` by dragging a rectangle on an image shown in `response`, and draws
+/// the selection. A click clears it. `image_size` is the size of the shown image in pixels, so
+/// that the region is in image coordinates regardless of how the image is scaled.
+#[allow(clippy::cast_precision_loss)]
+pub fn select_roi(
+    ui: &egui::Ui,
+    response: &Response,
+    image_size: [usize; 2],
+    roi: &mut Option<Roi>,
+) {
+    let transform = ImageTransform::new(response.rect, image_size);
+    let to_screen = |x: u32, y: u32| transform.to_screen([x as f32, y as f32]);
+
+    if response.clicked() {
+        *roi = None;
+    }
+    if response.dragged() {
+        let origin = ui.input(|i| i.pointer.press_origin());
+        if let (Some(origin), Some(current)) = (origin, response.interact_pointer_pos()) {
+            *roi = Roi::from_corners(transform.to_image(origin), transform.to_image(current));
+        }
+    }
+
+    if let Some(roi) = roi {
+        let selection = Rect::from_min_max(
+            to_screen(roi.x, roi.y),
+            to_screen(roi.x + roi.width, roi.y + roi.height),
+        );
+        ui.painter()
+            .rect_stroke(selection, 0., Stroke::new(2., Color32::YELLOW));
+    }
+}
+
+/// State of a statistics panel shared between the GUI, which selects the region and shows the
+/// statistics, and the processing thread, which computes them for every frame.
+#[derive(Debug, Default)]
+pub struct StatsPanel {
+    pub enabled: bool,
+    pub cumulative: bool,
+    pub roi: Option<Roi>,
+    pub stats: Option<ImageStats>,
+}
+
+impl StatsPanel {
+    /// Draws the panel controls and the latest statistics.
+    pub fn draw(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.enabled, "statistics");
+        if !self.enabled {
+            return;
+        }
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.cumulative, "cumulative");
+            match self.roi {
+                Some(roi) => {
+                    ui.label(format!(
+                        "region {}x{} at ({}, {})",
+                        roi.width, roi.height, roi.x, roi.y
+                    ));
+                    if ui.button("clear").clicked() {
+                        self.roi = None;
+                    }
+                }
+                None => {
+                    ui.label("drag on the image to select a region");
+                }
+            }
+        });
+        if let Some(stats) = &self.stats {
+            draw_stats(stats, self.cumulative, ui);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, Luma, RgbImage};
+
+    #[test]
+    fn channel_stats_from_histogram() {
+        let mut histogram = [0; BINS];
+        histogram[0] = 2;
+        histogram[255] = 2;
+        let stats = ChannelStats::from_histogram(&histogram);
+        assert_eq!(stats.pixels, 4);
+        assert!((stats.mean - 127.5).abs() < 1e-9);
+        assert!((stats.std_dev - 127.5).abs() < 1e-9);
+        assert!((stats.saturated - 0.5).abs() < 1e-9);
+        assert_eq!((stats.min, stats.max), (0, 255));
+        assert_eq!(stats.cumulative()[0], 0.5);
+        assert_eq!(stats.cumulative()[BINS - 1], 1.);
+    }
+
+    #[test]
+    fn channel_stats_of_no_pixels() {
+        let stats = ChannelStats::from_histogram(&[0; BINS]);
+        assert_eq!(stats.pixels, 0);
+        assert_eq!(stats.mean, 0.);
+        assert_eq!(stats.std_dev, 0.);
+        assert_eq!(stats.saturated, 0.);
+        assert_eq!((stats.min, stats.max), (0, 0));
+    }
+
+    #[test]
+    fn roi_from_reversed_and_negative_corners() {
+        assert_eq!(
+            Roi::from_corners([5.5, 4.], [1.2, -3.]),
+            Some(Roi::new(1, 0, 5, 4))
+        );
+        assert_eq!(Roi::from_corners([2., 2.], [2., 5.]), None);
+        assert_eq!(Roi::from_corners([-3., -3.], [-1., -1.]), None);
+    }
+
+    #[test]
+    fn roi_clamped_to_image() {
+        assert_eq!(
+            Roi::new(2, 2, 10, 10).clamp(5, 4),
+            Some(Roi::new(2, 2, 3, 2))
+        );
+        assert_eq!(Roi::new(1, 1, 2, 2).clamp(5, 4), Some(Roi::new(1, 1, 2, 2)));
+        assert_eq!(Roi::new(6, 0, 2, 2).clamp(5, 4), None);
+        assert_eq!(Roi::new(u32::MAX - 1, 0, 10, 1).clamp(5, 4), None);
+    }
+
+    #[test]
+    fn image_stats_of_roi() {
+        #[allow(clippy::cast_possible_truncation)]
+        let image = GrayImage::from_fn(3, 2, |x, y| Luma([(x + 10 * y) as u8]));
+
+        let all = ImageStats::from_image(&image, None);
+        assert_eq!(all.channels.len(), 1);
+        assert_eq!(all.channels[0].pixels, 6);
+
+        let stats = ImageStats::from_image(&image, Some(Roi::new(1, 1, 2, 1)));
+        let channel = &stats.channels[0];
+        assert_eq!(channel.pixels, 2);
+        assert_eq!((channel.min, channel.max), (11, 12));
+        assert!((channel.mean - 11.5).abs() < 1e-9);
+
+        let outside = ImageStats::from_image(&image, Some(Roi::new(5, 5, 1, 1)));
+        assert_eq!(outside.channels[0].pixels, 0);
+    }
+
+    #[test]
+    fn image_stats_per_channel() {
+        let image = RgbImage::from_vec(2, 1, vec![1, 2, 3, 3, 4, 5]).expect("should fit");
+        let stats = ImageStats::from_image(&image, Some(Roi::new(1, 0, 5, 5)));
+        let means: Vec<_> = stats.channels.iter().map(|channel| channel.mean).collect();
+        assert_eq!(means, [3., 4., 5.]);
+    }
+}
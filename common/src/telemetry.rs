@@ -0,0 +1,257 @@
+use crate::profiler::Profiler;
+use egui::{CollapsingHeader, RichText, ScrollArea};
+use std::{
+    collections::VecDeque,
+    env,
+    fmt::{self, Write as _},
+    fs::File,
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use tracing::{field::Field, Event, Level, Subscriber};
+use tracing_subscriber::{
+    field::Visit, filter::ParseError, fmt::MakeWriter, layer::Context, prelude::*,
+    registry::LookupSpan, util::TryInitError, EnvFilter, Layer,
+};
+
+/// Filter used when neither `--log` nor `RUST_LOG` is given.
+const DEFAULT_FILTER: &str = "info";
+
+/// Number of events kept for the log console.
+const CONSOLE_CAPACITY: usize = 500;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Multi-line, human-readable output
+    #[default]
+    Pretty,
+    /// One line per event
+    Compact,
+    /// One JSON object per line
+    Json,
+}
+
+/// Command line arguments for logging and tracing, shared by all binaries.
+#[derive(Debug, Clone, clap::Args)]
+pub struct TelemetryArgs {
+    /// Log filter in `RUST_LOG` syntax, e.g. `debug` or `info,common=trace` [default: the
+    /// `RUST_LOG` environment variable, or `info`]
+    #[arg(long, value_name = "FILTER")]
+    pub log: Option<String>,
+    /// Format of the log on standard error
+    #[arg(long, value_enum, default_value_t)]
+    pub log_format: LogFormat,
+    /// Also write the log to a file, in the same format without colors
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+    /// Write all timing spans to a Chrome trace JSON file
+    #[arg(long, value_name = "PATH")]
+    pub chrome_trace: Option<PathBuf>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TelemetryError {
+    #[error("invalid log filter: {0}")]
+    Filter(#[from] ParseError),
+    #[error("could not create log or trace file: {0}")]
+    Io(#[from] io::Error),
+    #[error("could not install tracing subscriber: {0}")]
+    Init(#[from] TryInitError),
+}
+
+/// Handles to the installed subscriber. Keep it alive until the end of `main`, as dropping it
+/// completes the Chrome trace file.
+pub struct Telemetry {
+    /// Timings of all spans, see [`crate::profiler`].
+    pub profiler: Profiler,
+    /// Recent events that pass the log filter.
+    pub console: LogConsole,
+    _chrome_trace: Option<tracing_chrome::FlushGuard>,
+}
+
+/// Installs the global `tracing` subscriber described by `args`.
+///
+/// Events that pass the filter go to standard error, the log file and the log console, while the
+/// profiler and the Chrome trace see every span regardless of the filter.
+///
+/// # Errors
+///
+/// * `TelemetryError::Filter` if the filter cannot be parsed.
+/// * `TelemetryError::Io` if the log or trace file cannot be created.
+/// * `TelemetryError::Init` if a global subscriber is already installed.
+pub fn init(args: &TelemetryArgs) -> Result<Telemetry, TelemetryError> {
+    let directives = args
+        .log
+        .clone()
+        .or_else(|| env::var(EnvFilter::DEFAULT_ENV).ok())
+        .unwrap_or_else(|| DEFAULT_FILTER.into());
+    let filter = || EnvFilter::try_new(&directives);
+
+    let file = match &args.log_file {
+        Some(path) => Some(fmt_layer(
+            args.log_format,
+            Mutex::new(File::create(path)?),
+            false,
+        )),
+        None => None,
+    };
+    let (chrome, chrome_guard) = match &args.chrome_trace {
+        Some(path) => {
+            let (layer, guard) = tracing_chrome::ChromeLayerBuilder::new()
+                .writer(File::create(path)?)
+                .build();
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+    let profiler = Profiler::default();
+    let console = LogConsole::default();
+
+    tracing_subscriber::registry()
+        .with(fmt_layer(args.log_format, io::stderr, true).with_filter(filter()?))
+        .with(file.with_filter(filter()?))
+        .with(console.clone().with_filter(filter()?))
+        .with(profiler.layer())
+        .with(chrome)
+        .try_init()?;
+
+    Ok(Telemetry {
+        profiler,
+        console,
+        _chrome_trace: chrome_guard,
+    })
+}
+
+fn fmt_layer<S, W>(format: LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi);
+    match format {
+        LogFormat::Pretty => layer.pretty().boxed(),
+        LogFormat::Compact => layer.compact().boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+}
+
+/// An event as shown in the log console.
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    /// Time since the subscriber was installed.
+    pub elapsed: f64,
+    pub level: Level,
+    pub target: String,
+    /// The message followed by the other fields as `name=value`.
+    pub message: String,
+}
+
+/// Collects the message and fields of an event into one line.
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        if field.name() == "message" {
+            write!(self.0, "{value:?}")
+        } else {
+            write!(self.0, "{}={value:?}", field.name())
+        }
+        .expect("writing to a string should not fail");
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.record_debug(field, &format_args!("{value}"));
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Records {
+    start: Instant,
+    records: VecDeque<LogRecord>,
+}
+
+/// The latest events, kept for showing in the GUI. Clones share the same records.
+#[derive(Debug, Clone)]
+pub struct LogConsole(Arc<Mutex<Records>>);
+
+impl Default for LogConsole {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(Records {
+            start: Instant::now(),
+            records: VecDeque::with_capacity(CONSOLE_CAPACITY),
+        })))
+    }
+}
+
+impl LogConsole {
+    #[must_use]
+    pub fn records(&self) -> Vec<LogRecord> {
+        self.0.lock().unwrap().records.iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.0.lock().unwrap().records.clear();
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogConsole {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let metadata = event.metadata();
+
+        let mut records = self.0.lock().unwrap();
+        if records.records.len() == CONSOLE_CAPACITY {
+            records.records.pop_front();
+        }
+        let record = LogRecord {
+            elapsed: records.start.elapsed().as_secs_f64(),
+            level: *metadata.level(),
+            target: metadata.target().into(),
+            message: visitor.0,
+        };
+        records.records.push_back(record);
+    }
+}
+
+/// Draws a collapsible, scrolling list of the latest events.
+pub fn draw_log_console(console: &LogConsole, ui: &mut egui::Ui) {
+    CollapsingHeader::new("log").show(ui, |ui| {
+        if ui.button("clear").clicked() {
+            console.clear();
+        }
+        ScrollArea::vertical()
+            .max_height(200.)
+            .auto_shrink([false, true])
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for record in console.records() {
+                    let color = match record.level {
+                        Level::ERROR => ui.visuals().error_fg_color,
+                        Level::WARN => ui.visuals().warn_fg_color,
+                        _ => ui.visuals().text_color(),
+                    };
+                    ui.label(
+                        RichText::new(format!(
+                            "{:9.3} {:>5} {}: {}",
+                            record.elapsed, record.level, record.target, record.message
+                        ))
+                        .monospace()
+                        .color(color),
+                    );
+                }
+            });
+    });
+}
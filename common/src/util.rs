@@ -0,0 +1,815 @@
+use opencv::{
+    core::{self, Mat, MatTraitConst, MatTraitConstManual, Ptr, Scalar, Vector},
+    imgproc,
+    video::{self, BackgroundSubtractor, BackgroundSubtractorMOG2},
+};
+use std::collections::VecDeque;
+use tracing::debug;
+
+/// A filter over a sequence of frames.
+pub trait TemporalFilter: Send {
+    /// Adds a frame to the sequence. If the frame's size or type differs from the previous
+    /// frame's, the filter is reset before the frame is added.
+    ///
+    /// # Errors
+    ///
+    /// * `opencv::Error` if an OpenCV operation fails.
+    fn push(&mut self, frame: Mat) -> opencv::Result<()>;
+
+    /// The filtered frame, or `None` if no frames have been pushed since creation or reset.
+    ///
+    /// # Errors
+    ///
+    /// * `opencv::Error` if an OpenCV operation fails.
+    fn output(&self) -> opencv::Result<Option<Mat>>;
+
+    /// Forgets all pushed frames.
+    fn reset(&mut self);
+}
+
+/// Size and type of a frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Geometry {
+    size: core::Size,
+    typ: i32,
+}
+
+/// Tracks the geometry of the frames pushed to a temporal filter, so that the filter can start
+/// over when e.g. the camera resolution or the pipeline output type changes.
+#[derive(Default)]
+struct GeometryTracker(Option<Geometry>);
+
+impl GeometryTracker {
+    /// Records the geometry of `frame`, and returns whether it differs from the previous frame's.
+    fn update(&mut self, frame: &Mat) -> opencv::Result<bool> {
+        let geometry = Geometry {
+            size: frame.size()?,
+            typ: frame.typ(),
+        };
+        let changed = self.0.map_or(false, |previous| previous != geometry);
+        if changed {
+            debug!(from = ?self.0, to = ?geometry, "frame geometry changed");
+        }
+        self.0 = Some(geometry);
+        Ok(changed)
+    }
+}
+
+/// Box moving average over the last `size` frames.
+///
+/// A running sum of the buffered frames is kept in double precision, so that pushing a frame
+/// and computing the average are both independent of the window size. Frames can have any
+/// depth and number of channels, and the average is returned with the type of the frames.
+pub struct MatMovingAverage {
+    size: usize,
+    buffer: VecDeque<Mat>,
+    sum: Mat,
+    scratch: Mat,
+    geometry: GeometryTracker,
+}
+
+impl MatMovingAverage {
+    /// A window of `size` frames, of at least one frame.
+    #[must_use]
+    pub fn new(size: usize) -> Self {
+        let size = size.max(1);
+        Self {
+            size,
+            buffer: VecDeque::with_capacity(size),
+            sum: Mat::default(),
+            scratch: Mat::default(),
+            geometry: GeometryTracker::default(),
+        }
+    }
+
+    /// Adds a frame to the window, evicting the oldest frame if the window is full. If the
+    /// frame's size or type differs from the buffered frames', the window is emptied first.
+    ///
+    /// # Errors
+    ///
+    /// * `opencv::Error` if updating the running sum fails.
+    pub fn push(&mut self, mat: Mat) -> opencv::Result<()> {
+        if self.geometry.update(&mat)? {
+            self.reset();
+        }
+
+        if self.buffer.is_empty() {
+            let size = mat.size()?;
+            self.sum = Mat::new_rows_cols_with_default(
+                size.height,
+                size.width,
+                core::CV_MAKETYPE(core::CV_64F, mat.channels()),
+                Scalar::all(0.),
+            )?;
+        }
+
+        if self.buffer.len() == self.size {
+            if let Some(evicted) = self.buffer.pop_front() {
+                // `subtract` cannot operate in place, so alternate between two sum buffers
+                core::subtract(
+                    &self.sum,
+                    &evicted,
+                    &mut self.scratch,
+                    &core::no_array(),
+                    core::CV_64F,
+                )?;
+                std::mem::swap(&mut self.sum, &mut self.scratch);
+            }
+        }
+
+        // unlike `imgproc::accumulate`, `add` takes frames of every depth
+        core::add(
+            &self.sum,
+            &mat,
+            &mut self.scratch,
+            &core::no_array(),
+            core::CV_64F,
+        )?;
+        std::mem::swap(&mut self.sum, &mut self.scratch);
+        self.buffer.push_back(mat);
+        Ok(())
+    }
+
+    /// Average of the frames in the window, or `None` if the window is empty.
+    ///
+    /// # Errors
+    ///
+    /// * `opencv::Error` if converting the running sum fails.
+    pub fn average(&self) -> opencv::Result<Option<Mat>> {
+        let Some(depth) = self.buffer.front().map(MatTraitConst::depth) else {
+            return Ok(None);
+        };
+
+        let mut out = Mat::default();
+        #[allow(clippy::cast_precision_loss)]
+        self.sum
+            .convert_to(&mut out, depth, 1. / (self.buffer.len() as f64), 0.)?;
+
+        Ok(Some(out))
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+    }
+}
+
+impl TemporalFilter for MatMovingAverage {
+    fn push(&mut self, frame: Mat) -> opencv::Result<()> {
+        Self::push(self, frame)
+    }
+
+    fn output(&self) -> opencv::Result<Option<Mat>> {
+        self.average()
+    }
+
+    fn reset(&mut self) {
+        Self::reset(self);
+    }
+}
+
+/// Exponential moving average, where each new frame is weighted by `alpha`.
+pub struct ExponentialMovingAverage {
+    alpha: f64,
+    depth: i32,
+    state: Option<Mat>,
+    geometry: GeometryTracker,
+}
+
+impl ExponentialMovingAverage {
+    #[must_use]
+    pub fn new(alpha: f64) -> Self {
+        Self {
+            alpha,
+            depth: core::CV_8U,
+            state: None,
+            geometry: GeometryTracker::default(),
+        }
+    }
+}
+
+impl TemporalFilter for ExponentialMovingAverage {
+    fn push(&mut self, frame: Mat) -> opencv::Result<()> {
+        if self.geometry.update(&frame)? {
+            self.reset();
+        }
+
+        // `accumulate_weighted` does not take signed integer frames, so convert them all
+        let mut converted = Mat::default();
+        frame.convert_to(&mut converted, core::CV_64F, 1., 0.)?;
+        if let Some(state) = &mut self.state {
+            imgproc::accumulate_weighted(&converted, state, self.alpha, &core::no_array())?;
+        } else {
+            self.depth = frame.depth();
+            self.state = Some(converted);
+        }
+        Ok(())
+    }
+
+    fn output(&self) -> opencv::Result<Option<Mat>> {
+        self.state
+            .as_ref()
+            .map(|state| {
+                let mut out = Mat::default();
+                state.convert_to(&mut out, self.depth, 1., 0.)?;
+                Ok(out)
+            })
+            .transpose()
+    }
+
+    fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+/// Per-pixel median over the last `size` frames.
+///
+/// For an even number of buffered frames, the lower of the two middle values is used.
+pub struct TemporalMedian {
+    size: usize,
+    buffer: VecDeque<Mat>,
+    geometry: GeometryTracker,
+}
+
+impl TemporalMedian {
+    /// A window of `size` frames, of at least one frame.
+    #[must_use]
+    pub fn new(size: usize) -> Self {
+        let size = size.max(1);
+        Self {
+            size,
+            buffer: VecDeque::with_capacity(size),
+            geometry: GeometryTracker::default(),
+        }
+    }
+}
+
+impl TemporalFilter for TemporalMedian {
+    fn push(&mut self, frame: Mat) -> opencv::Result<()> {
+        if self.geometry.update(&frame)? {
+            self.reset();
+        }
+
+        if self.buffer.len() == self.size {
+            self.buffer.pop_front();
+        }
+        self.buffer.push_back(frame);
+        Ok(())
+    }
+
+    fn output(&self) -> opencv::Result<Option<Mat>> {
+        let Some(first) = self.buffer.front() else {
+            return Ok(None);
+        };
+        let (channels, rows) = (first.channels(), first.rows());
+
+        // Lay each frame out as a single-channel row, so that sorting every column of the stacked
+        // rows sorts each pixel value over time.
+        let mut rows_over_time = Vector::<Mat>::new();
+        for frame in &self.buffer {
+            rows_over_time.push(frame.reshape(1, 1)?);
+        }
+        let mut stacked = Mat::default();
+        core::vconcat(&rows_over_time, &mut stacked)?;
+        let mut sorted = Mat::default();
+        core::sort(
+            &stacked,
+            &mut sorted,
+            core::SORT_EVERY_COLUMN | core::SORT_ASCENDING,
+        )?;
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        let median = sorted.row(((self.buffer.len() - 1) / 2) as i32)?;
+        let mut out = Mat::default();
+        median.reshape(channels, rows)?.copy_to(&mut out)?;
+        Ok(Some(out))
+    }
+
+    fn reset(&mut self) {
+        self.buffer.clear();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Extremum {
+    Min,
+    Max,
+}
+
+/// Holds the per-pixel minimum or maximum of all frames pushed since creation or reset.
+pub struct ExtremumHold {
+    extremum: Extremum,
+    state: Option<Mat>,
+    scratch: Mat,
+    geometry: GeometryTracker,
+}
+
+impl ExtremumHold {
+    #[must_use]
+    pub fn new(extremum: Extremum) -> Self {
+        Self {
+            extremum,
+            state: None,
+            scratch: Mat::default(),
+            geometry: GeometryTracker::default(),
+        }
+    }
+}
+
+impl TemporalFilter for ExtremumHold {
+    fn push(&mut self, frame: Mat) -> opencv::Result<()> {
+        if self.geometry.update(&frame)? {
+            self.reset();
+        }
+
+        let Some(state) = &mut self.state else {
+            self.state = Some(frame);
+            return Ok(());
+        };
+        match self.extremum {
+            Extremum::Min => core::min(&*state, &frame, &mut self.scratch)?,
+            Extremum::Max => core::max(&*state, &frame, &mut self.scratch)?,
+        }
+        std::mem::swap(state, &mut self.scratch);
+        Ok(())
+    }
+
+    fn output(&self) -> opencv::Result<Option<Mat>> {
+        Ok(self.state.clone())
+    }
+
+    fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+/// Converts a frame to a single-channel `CV_64F` intensity image, assuming RGB(A) channel order.
+fn intensity(frame: &Mat) -> opencv::Result<Mat> {
+    // `cvt_color` only takes 8-bit, 16-bit unsigned and 32-bit float frames
+    let mut float = Mat::default();
+    frame.convert_to(&mut float, core::CV_32F, 1., 0.)?;
+    let gray = match float.channels() {
+        1 => float,
+        3 | 4 => {
+            let code = if float.channels() == 3 {
+                imgproc::COLOR_RGB2GRAY
+            } else {
+                imgproc::COLOR_RGBA2GRAY
+            };
+            let mut gray = Mat::default();
+            imgproc::cvt_color(&float, &mut gray, code, 1)?;
+            gray
+        }
+        c => {
+            return Err(opencv::Error::new(
+                core::StsBadArg,
+                format!("unsupported number of channels {c}"),
+            ))
+        }
+    };
+    let mut out = Mat::default();
+    gray.convert_to(&mut out, core::CV_64F, 1., 0.)?;
+    Ok(out)
+}
+
+/// Background subtraction which models every pixel's intensity as a Gaussian, updated with
+/// learning rate `alpha`. Pixels further than `threshold` standard deviations from the mean are
+/// foreground, and the output is a `CV_8UC1` foreground mask.
+pub struct RunningGaussian {
+    alpha: f64,
+    threshold: f64,
+    mean: Mat,
+    variance: Mat,
+    mask: Option<Mat>,
+    geometry: GeometryTracker,
+}
+
+impl RunningGaussian {
+    /// Variance assumed for every pixel before any motion has been observed.
+    const INITIAL_VARIANCE: f64 = 15. * 15.;
+
+    #[must_use]
+    pub fn new(alpha: f64, threshold: f64) -> Self {
+        Self {
+            alpha,
+            threshold,
+            mean: Mat::default(),
+            variance: Mat::default(),
+            mask: None,
+            geometry: GeometryTracker::default(),
+        }
+    }
+}
+
+impl TemporalFilter for RunningGaussian {
+    fn push(&mut self, frame: Mat) -> opencv::Result<()> {
+        if self.geometry.update(&frame)? {
+            self.reset();
+        }
+
+        let intensity = intensity(&frame)?;
+
+        if self.mask.is_none() {
+            let size = intensity.size()?;
+            self.variance = Mat::new_rows_cols_with_default(
+                size.height,
+                size.width,
+                core::CV_64FC1,
+                Scalar::all(Self::INITIAL_VARIANCE),
+            )?;
+            self.mask = Some(Mat::new_rows_cols_with_default(
+                size.height,
+                size.width,
+                core::CV_8UC1,
+                Scalar::all(0.),
+            )?);
+            self.mean = intensity;
+            return Ok(());
+        }
+
+        let mut difference = Mat::default();
+        core::subtract(
+            &intensity,
+            &self.mean,
+            &mut difference,
+            &core::no_array(),
+            -1,
+        )?;
+        let mut squared_difference = Mat::default();
+        core::multiply(&difference, &difference, &mut squared_difference, 1., -1)?;
+        let mut limit = Mat::default();
+        self.variance
+            .convert_to(&mut limit, -1, self.threshold * self.threshold, 0.)?;
+        let mut mask = Mat::default();
+        core::compare(&squared_difference, &limit, &mut mask, core::CMP_GT)?;
+
+        imgproc::accumulate_weighted(&intensity, &mut self.mean, self.alpha, &core::no_array())?;
+        imgproc::accumulate_weighted(
+            &squared_difference,
+            &mut self.variance,
+            self.alpha,
+            &core::no_array(),
+        )?;
+        self.mask = Some(mask);
+        Ok(())
+    }
+
+    fn output(&self) -> opencv::Result<Option<Mat>> {
+        Ok(self.mask.clone())
+    }
+
+    fn reset(&mut self) {
+        self.mask = None;
+    }
+}
+
+/// Wraps OpenCV's Gaussian mixture background subtractor. The output is a `CV_8UC1` foreground
+/// mask, where shadows are gray if `detect_shadows` is set.
+pub struct Mog2 {
+    history: i32,
+    var_threshold: f64,
+    detect_shadows: bool,
+    subtractor: Ptr<dyn BackgroundSubtractorMOG2>,
+    mask: Option<Mat>,
+    geometry: GeometryTracker,
+}
+
+// SAFETY: `Ptr` is not `Send` because it wraps a raw pointer to a C++ object, not because the
+// object is bound to a thread. The subtractor is created for and exclusively owned by this struct,
+// no other `Ptr` to it exists, and it is only used through `&mut self`, so moving the struct to
+// another thread moves the only access to it. MOG2 keeps no thread-local state.
+unsafe impl Send for Mog2 {}
+
+impl Mog2 {
+    /// # Errors
+    ///
+    /// * `opencv::Error` if the subtractor cannot be created.
+    pub fn new(history: i32, var_threshold: f64, detect_shadows: bool) -> opencv::Result<Self> {
+        Ok(Self {
+            history,
+            var_threshold,
+            detect_shadows,
+            subtractor: video::create_background_subtractor_mog2(
+                history,
+                var_threshold,
+                detect_shadows,
+            )?,
+            mask: None,
+            geometry: GeometryTracker::default(),
+        })
+    }
+}
+
+impl TemporalFilter for Mog2 {
+    fn push(&mut self, frame: Mat) -> opencv::Result<()> {
+        if self.geometry.update(&frame)? {
+            self.reset();
+        }
+
+        // MOG2 only takes 8-bit unsigned and 32-bit float frames
+        let depth = frame.depth();
+        let frame = if depth == core::CV_8U || depth == core::CV_32F {
+            frame
+        } else {
+            let mut converted = Mat::default();
+            frame.convert_to(&mut converted, core::CV_32F, 1., 0.)?;
+            converted
+        };
+        let mut mask = Mat::default();
+        self.subtractor.apply(&frame, &mut mask, -1.)?;
+        self.mask = Some(mask);
+        Ok(())
+    }
+
+    fn output(&self) -> opencv::Result<Option<Mat>> {
+        Ok(self.mask.clone())
+    }
+
+    fn reset(&mut self) {
+        // If a fresh subtractor cannot be created, keep learning on top of the old model
+        if let Ok(subtractor) = video::create_background_subtractor_mog2(
+            self.history,
+            self.var_threshold,
+            self.detect_shadows,
+        ) {
+            self.subtractor = subtractor;
+        }
+        self.mask = None;
+    }
+}
+
+/// Selects a temporal filter and its parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TemporalFilterConfiguration {
+    MovingAverage {
+        size: usize,
+    },
+    ExponentialMovingAverage {
+        alpha: f64,
+    },
+    Median {
+        size: usize,
+    },
+    MinHold,
+    MaxHold,
+    RunningGaussian {
+        alpha: f64,
+        threshold: f64,
+    },
+    Mog2 {
+        history: i32,
+        var_threshold: f64,
+        detect_shadows: bool,
+    },
+}
+
+impl Default for TemporalFilterConfiguration {
+    fn default() -> Self {
+        Self::MovingAverage { size: 5 }
+    }
+}
+
+impl TemporalFilterConfiguration {
+    /// Every kind of filter, with default parameters.
+    #[must_use]
+    pub fn defaults() -> [Self; 7] {
+        [
+            Self::MovingAverage { size: 5 },
+            Self::ExponentialMovingAverage { alpha: 0.2 },
+            Self::Median { size: 5 },
+            Self::MinHold,
+            Self::MaxHold,
+            Self::RunningGaussian {
+                alpha: 0.02,
+                threshold: 2.5,
+            },
+            Self::Mog2 {
+                history: 500,
+                var_threshold: 16.,
+                detect_shadows: true,
+            },
+        ]
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::MovingAverage { .. } => "moving average",
+            Self::ExponentialMovingAverage { .. } => "exponential moving average",
+            Self::Median { .. } => "median",
+            Self::MinHold => "min hold",
+            Self::MaxHold => "max hold",
+            Self::RunningGaussian { .. } => "running gaussian",
+            Self::Mog2 { .. } => "MOG2",
+        }
+    }
+
+    /// # Errors
+    ///
+    /// * `opencv::Error` if the filter cannot be created.
+    pub fn create(&self) -> opencv::Result<Box<dyn TemporalFilter>> {
+        Ok(match *self {
+            Self::MovingAverage { size } => Box::new(MatMovingAverage::new(size)),
+            Self::ExponentialMovingAverage { alpha } => {
+                Box::new(ExponentialMovingAverage::new(alpha))
+            }
+            Self::Median { size } => Box::new(TemporalMedian::new(size)),
+            Self::MinHold => Box::new(ExtremumHold::new(Extremum::Min)),
+            Self::MaxHold => Box::new(ExtremumHold::new(Extremum::Max)),
+            Self::RunningGaussian { alpha, threshold } => {
+                Box::new(RunningGaussian::new(alpha, threshold))
+            }
+            Self::Mog2 {
+                history,
+                var_threshold,
+                detect_shadows,
+            } => Box::new(Mog2::new(history, var_threshold, detect_shadows)?),
+        })
+    }
+}
+
+/// Owns the temporal filter of a processing pipeline, and recreates it whenever the configuration
+/// changes.
+#[derive(Default)]
+pub struct TemporalFilterSlot {
+    current: Option<(TemporalFilterConfiguration, Box<dyn TemporalFilter>)>,
+}
+
+impl TemporalFilterSlot {
+    /// Drops the current filter and its state.
+    pub fn clear(&mut self) {
+        self.current = None;
+    }
+
+    /// Pushes `frame` through the filter described by `configuration` and returns its output.
+    /// Without a configuration, the frame is returned as is.
+    ///
+    /// # Errors
+    ///
+    /// * `opencv::Error` if creating or running the filter fails.
+    pub fn filter(
+        &mut self,
+        configuration: Option<&TemporalFilterConfiguration>,
+        frame: Mat,
+    ) -> opencv::Result<Mat> {
+        let Some(configuration) = configuration else {
+            self.clear();
+            return Ok(frame);
+        };
+
+        let filter = match &mut self.current {
+            Some((current, filter)) if current == configuration => filter,
+            current => {
+                &mut current
+                    .insert((configuration.clone(), configuration.create()?))
+                    .1
+            }
+        };
+        filter.push(frame)?;
+        filter.output()?.ok_or_else(|| {
+            opencv::Error::new(core::StsError, "temporal filter has no output after a push")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_frame(rows: i32, cols: i32, typ: i32) -> Mat {
+        let mut frame = Mat::new_rows_cols_with_default(rows, cols, typ, Scalar::all(0.))
+            .expect("should create");
+        core::randu(&mut frame, &Scalar::all(0.), &Scalar::all(100.)).expect("should randomize");
+        frame
+    }
+
+    /// Average of `frames`, summed up from scratch in double precision.
+    fn naive_average(frames: &[Mat]) -> Mat {
+        let first = &frames[0];
+        let mut sum = Mat::default();
+        first
+            .convert_to(&mut sum, core::CV_64F, 1., 0.)
+            .expect("should convert");
+        for frame in &frames[1..] {
+            let mut converted = Mat::default();
+            frame
+                .convert_to(&mut converted, core::CV_64F, 1., 0.)
+                .expect("should convert");
+            let mut next = Mat::default();
+            core::add(&sum, &converted, &mut next, &core::no_array(), -1).expect("should add");
+            sum = next;
+        }
+        let mut average = Mat::default();
+        #[allow(clippy::cast_precision_loss)]
+        sum.convert_to(&mut average, first.depth(), 1. / frames.len() as f64, 0.)
+            .expect("should convert");
+        average
+    }
+
+    fn max_difference(a: &Mat, b: &Mat) -> f64 {
+        core::norm2(a, b, core::NORM_INF, &core::no_array()).expect("should compare")
+    }
+
+    #[test]
+    fn filters_reset_on_mixed_frames() {
+        // every geometry twice, so that each filter also combines two frames of the new geometry
+        let geometries = [
+            (4, 3, core::CV_8UC3),
+            (5, 2, core::CV_8UC1),
+            (4, 3, core::CV_16UC1),
+            (3, 3, core::CV_16SC3),
+            (2, 6, core::CV_32FC4),
+            (4, 3, core::CV_32SC1),
+            (6, 1, core::CV_64FC3),
+            (4, 3, core::CV_8UC3),
+        ];
+        for configuration in TemporalFilterConfiguration::defaults() {
+            let mut filter = configuration.create().expect("should create");
+            let is_mask = matches!(
+                configuration,
+                TemporalFilterConfiguration::RunningGaussian { .. }
+                    | TemporalFilterConfiguration::Mog2 { .. }
+            );
+            for (rows, cols, typ) in geometries.iter().flat_map(|geometry| [*geometry; 2]) {
+                let name = configuration.name();
+                filter
+                    .push(random_frame(rows, cols, typ))
+                    .unwrap_or_else(|error| panic!("{name} should take type {typ}: {error}"));
+                let output = filter
+                    .output()
+                    .expect("should output")
+                    .expect("should have output after a push");
+                assert_eq!((output.rows(), output.cols()), (rows, cols), "{name}");
+                let expected = if is_mask { core::CV_8UC1 } else { typ };
+                assert_eq!(output.typ(), expected, "{name}");
+            }
+        }
+    }
+
+    #[test]
+    fn moving_average_equals_naive_average() {
+        for (rows, cols, typ, window) in [
+            (1, 1, core::CV_8UC1, 1),
+            (4, 3, core::CV_8UC3, 3),
+            (7, 5, core::CV_16SC1, 4),
+            (2, 9, core::CV_32FC4, 5),
+            (6, 6, core::CV_64FC2, 2),
+        ] {
+            let frames: Vec<_> = (0..window * 3 + 1)
+                .map(|_| random_frame(rows, cols, typ))
+                .collect();
+            let mut average = MatMovingAverage::new(window);
+            // push more frames than the window holds, so that it wraps around several times
+            for (index, frame) in frames.iter().enumerate() {
+                average.push(frame.clone()).expect("should push");
+                let start = (index + 1).saturating_sub(window);
+                let expected = naive_average(&frames[start..=index]);
+                let actual = average
+                    .average()
+                    .expect("should average")
+                    .expect("should exist");
+                assert_eq!(actual.typ(), typ);
+                // integer results may round differently from the naive sum
+                let tolerance = if core::CV_MAT_DEPTH(typ) >= core::CV_32F {
+                    1e-3
+                } else {
+                    1.
+                };
+                assert!(
+                    max_difference(&actual, &expected) <= tolerance,
+                    "{rows}x{cols} type {typ} window {window} after {} frames",
+                    index + 1
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn moving_average_of_size_zero_holds_one_frame() {
+        let mut average = MatMovingAverage::new(0);
+        for _ in 0..3 {
+            let frame = random_frame(2, 2, core::CV_8UC1);
+            average.push(frame.clone()).expect("should push");
+            assert_eq!(average.buffer.len(), 1);
+            let actual = average
+                .average()
+                .expect("should average")
+                .expect("should exist");
+            assert!(max_difference(&actual, &frame) < 1.);
+        }
+    }
+
+    #[test]
+    fn median_of_size_zero_holds_one_frame() {
+        let mut median = TemporalMedian::new(0);
+        for _ in 0..3 {
+            let frame = random_frame(3, 2, core::CV_8UC3);
+            median.push(frame.clone()).expect("should push");
+            assert_eq!(median.buffer.len(), 1);
+            let actual = median
+                .output()
+                .expect("should output")
+                .expect("should exist");
+            assert_eq!(max_difference(&actual, &frame), 0.);
+        }
+    }
+}
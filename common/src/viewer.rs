@@ -0,0 +1,100 @@
+use egui::{Pos2, Rect};
+
+/// Maps between image pixel coordinates and the screen rectangle an image is shown in, so that
+/// interaction and overlays work in image coordinates regardless of how the image is scaled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageTransform {
+    /// Where the image is shown.
+    pub rect: Rect,
+    /// Width and height of the image in pixels.
+    pub image_size: [usize; 2],
+}
+
+impl ImageTransform {
+    #[must_use]
+    pub fn new(rect: Rect, image_size: [usize; 2]) -> Self {
+        Self { rect, image_size }
+    }
+
+    /// Screen points per image pixel along each axis.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn scale(&self) -> [f32; 2] {
+        [
+            self.rect.width() / self.image_size[0].max(1) as f32,
+            self.rect.height() / self.image_size[1].max(1) as f32,
+        ]
+    }
+
+    /// The mean of the scales along the axes, for sizes that cannot be stretched unevenly.
+    #[must_use]
+    pub fn mean_scale(&self) -> f32 {
+        let [x, y] = self.scale();
+        (x + y) / 2.
+    }
+
+    #[must_use]
+    pub fn to_screen(&self, [x, y]: [f32; 2]) -> Pos2 {
+        let [scale_x, scale_y] = self.scale();
+        self.rect.min + egui::vec2(x * scale_x, y * scale_y)
+    }
+
+    #[must_use]
+    pub fn to_image(&self, pos: Pos2) -> [f32; 2] {
+        let [scale_x, scale_y] = self.scale();
+        let offset = pos - self.rect.min;
+        [offset.x / scale_x, offset.y / scale_y]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transform() -> ImageTransform {
+        // a 100x50 image shown at (10, 20), stretched to 200x200
+        ImageTransform::new(
+            Rect::from_min_size(Pos2::new(10., 20.), egui::vec2(200., 200.)),
+            [100, 50],
+        )
+    }
+
+    #[test]
+    fn scale_per_axis() {
+        assert_eq!(transform().scale(), [2., 4.]);
+        assert_eq!(transform().mean_scale(), 3.);
+    }
+
+    #[test]
+    fn image_to_screen() {
+        let transform = transform();
+        assert_eq!(transform.to_screen([0., 0.]), Pos2::new(10., 20.));
+        assert_eq!(transform.to_screen([100., 50.]), Pos2::new(210., 220.));
+        assert_eq!(transform.to_screen([25., 10.]), Pos2::new(60., 60.));
+    }
+
+    #[test]
+    fn screen_to_image() {
+        let transform = transform();
+        assert_eq!(transform.to_image(Pos2::new(10., 20.)), [0., 0.]);
+        assert_eq!(transform.to_image(Pos2::new(60., 60.)), [25., 10.]);
+        // points outside the image map outside of it
+        assert_eq!(transform.to_image(Pos2::new(0., 0.)), [-5., -5.]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let transform = transform();
+        let point = [12.5, 33.25];
+        assert_eq!(transform.to_image(transform.to_screen(point)), point);
+    }
+
+    #[test]
+    fn empty_image_does_not_divide_by_zero() {
+        let transform = ImageTransform::new(
+            Rect::from_min_size(Pos2::ZERO, egui::vec2(10., 10.)),
+            [0, 0],
+        );
+        assert_eq!(transform.scale(), [10., 10.]);
+    }
+}
@@ -1,47 +1,92 @@
-use eframe::egui::{ImageData, Key};
+use clap::Parser;
+use common::{
+    cam::create_frame_stream,
+    cli::LabArgs,
+    picking::{picks_overlay, Pick, PickTool, Picker},
+    preset::Preset,
+    profiler::{draw_profiler, Profiler},
+    stats::{select_roi, ImageStats, StatsPanel},
+    telemetry::{self, draw_log_console, LogConsole},
+};
+use eframe::egui::{ImageData, Key, Sense};
 use eframe::egui::{Separator, Widget};
 use eframe::{
-    egui::{self, CentralPanel, ComboBox, Context, SidePanel, Slider, TextureOptions},
+    egui::{
+        self, CentralPanel, ComboBox, Context, SidePanel, Slider, TextureOptions, TopBottomPanel,
+    },
     App, Frame,
 };
 use image::imageops::{self, FilterType};
-use image::{GrayImage, RgbImage};
-use lab_00_native_egui::{create_camera_stream, MyImageData};
-use nokhwa::utils::CameraIndex;
-use std::sync::{mpsc, Arc, RwLock};
+use image::{DynamicImage, GrayImage, RgbImage};
+use lab_00_native_egui::MyImageData;
+use std::{
+    sync::{mpsc::TryRecvError, Arc, Mutex, RwLock},
+    time::Instant,
+};
+use tracing::{debug_span, info};
 
 #[derive(Debug, Clone)]
 struct ImageProcessingConfiguration {
     gray_before_scale: bool,
     scale: u32,
     scale_filter: FilterType,
+    use_blur: bool,
     blur: f32,
+    use_canny: bool,
     canny_lo: f32,
     canny_hi: f32,
+    /// Picks of the picking panel, in pixels of the processed image.
+    picks: Vec<Pick>,
+    /// Draw `picks` onto the processed image.
+    annotate: bool,
 }
 
 impl ImageProcessingConfiguration {
     #[allow(clippy::needless_pass_by_value)] // to conform with interface
     fn call(&self, image: RgbImage) -> GrayImage {
         let image = if self.gray_before_scale {
-            let image = imageops::grayscale(&image);
-            imageops::resize(
-                &image,
-                image.width() / self.scale,
-                image.height() / self.scale,
-                self.scale_filter,
-            )
+            let image = debug_span!("grayscale").in_scope(|| imageops::grayscale(&image));
+            debug_span!("scale").in_scope(|| {
+                imageops::resize(
+                    &image,
+                    image.width() / self.scale,
+                    image.height() / self.scale,
+                    self.scale_filter,
+                )
+            })
+        } else {
+            let image = debug_span!("scale").in_scope(|| {
+                imageops::resize(
+                    &image,
+                    image.width() / self.scale,
+                    image.height() / self.scale,
+                    self.scale_filter,
+                )
+            });
+            debug_span!("grayscale").in_scope(|| imageops::grayscale(&image))
+        };
+        let image = if self.use_blur {
+            let _span = debug_span!("blur").entered();
+            imageproc::filter::gaussian_blur_f32(&image, self.blur)
         } else {
-            let image = imageops::resize(
-                &image,
-                image.width() / self.scale,
-                image.height() / self.scale,
-                self.scale_filter,
-            );
-            imageops::grayscale(&image)
+            image
+        };
+        let image = if self.use_canny {
+            let _span = debug_span!("canny").entered();
+            imageproc::edges::canny(&image, self.canny_lo, self.canny_hi)
+        } else {
+            image
+        };
+
+        let image = if self.annotate && !self.picks.is_empty() {
+            let _span = debug_span!("annotate").entered();
+            // the colors of the picks end up as their intensity
+            let mut rgb = DynamicImage::ImageLuma8(image).into_rgb8();
+            picks_overlay(&self.picks, None).draw_on_image(&mut rgb, None);
+            DynamicImage::ImageRgb8(rgb).into_luma8()
+        } else {
+            image
         };
-        let image = imageproc::filter::gaussian_blur_f32(&image, self.blur);
-        let image = imageproc::edges::canny(&image, self.canny_lo, self.canny_hi);
 
         #[allow(clippy::let_and_return)] // to easily add operations
         image
@@ -54,37 +99,107 @@ impl Default for ImageProcessingConfiguration {
             gray_before_scale: false,
             scale: 4,
             scale_filter: FilterType::Nearest,
+            use_blur: true,
             blur: 4.,
+            use_canny: true,
             canny_lo: 5.,
             canny_hi: 15.,
+            picks: Vec::new(),
+            annotate: false,
         }
     }
 }
 
+/// The closest equivalent of each preset with the Rust-native operations. There is no temporal or
+/// bilateral filtering here, so denoising is approximated with a mild blur.
+impl From<Preset> for ImageProcessingConfiguration {
+    fn from(preset: Preset) -> Self {
+        let default = Self::default();
+        match preset {
+            Preset::Raw => Self {
+                use_blur: false,
+                use_canny: false,
+                ..default
+            },
+            Preset::Smooth => Self {
+                blur: 2.,
+                use_canny: false,
+                ..default
+            },
+            Preset::Denoise => Self {
+                blur: 1.,
+                use_canny: false,
+                ..default
+            },
+            Preset::Edges => default,
+        }
+    }
+}
+
+/// Read frames, process them with Rust-native libraries and show them in an egui window.
+#[derive(Debug, Parser)]
+#[command(about)]
+struct Cli {
+    #[command(flatten)]
+    lab: LabArgs,
+}
+
 fn main() {
+    let cli = Cli::parse();
+    let telemetry =
+        telemetry::init(&cli.lab.telemetry).expect("should be able to set up telemetry");
+
+    #[allow(clippy::cast_precision_loss)]
     let options = eframe::NativeOptions {
-        initial_window_size: Some(egui::vec2(800., 600.)),
+        initial_window_size: Some(egui::vec2(
+            cli.lab.layout.window_size.width as f32,
+            cli.lab.layout.window_size.height as f32,
+        )),
+        fullscreen: cli.lab.layout.fullscreen,
         ..Default::default()
     };
 
-    let processor = Arc::new(RwLock::new(ImageProcessingConfiguration::default()));
+    let processor = Arc::new(RwLock::new(ImageProcessingConfiguration::from(
+        cli.lab.preset.unwrap_or(Preset::Edges),
+    )));
 
-    let stream_receiver = create_camera_stream(CameraIndex::Index(0), {
-        let processor = processor.clone();
-        move |img| processor.read().unwrap().call(img)
-    });
+    let source_options = cli.lab.source.options();
+    let stats = Arc::new(Mutex::new(StatsPanel::default()));
 
-    let stream = {
-        move || match stream_receiver.try_recv() {
-            Ok(img) => Some(img),
-            Err(mpsc::TryRecvError::Disconnected) => {
-                panic!("stream has no updater");
+    let stream_receiver = create_frame_stream(cli.lab.source.source, source_options, {
+        let processor = processor.clone();
+        let stats = stats.clone();
+        let mut previous: Option<Instant> = None;
+        move |img| {
+            let image = processor.read().unwrap().call(img);
+            let (enabled, roi) = {
+                let stats = stats.lock().unwrap();
+                (stats.enabled, stats.roi)
+            };
+            if enabled {
+                let frame_stats =
+                    debug_span!("statistics").in_scope(|| ImageStats::from_image(&image, roi));
+                stats.lock().unwrap().stats = Some(frame_stats);
             }
-            _ => None,
+
+            let now = Instant::now();
+            let fps = previous.map_or(0., |previous| {
+                1. / now.duration_since(previous).as_secs_f64()
+            });
+            previous = Some(now);
+            (image, fps)
         }
-    };
+    });
 
-    let app = MyApp::new(stream, processor);
+    let stream = move || stream_receiver.try_recv();
+
+    let app = MyApp::new(
+        stream,
+        processor,
+        stats,
+        telemetry.profiler.clone(),
+        telemetry.console.clone(),
+    );
 
     eframe::run_native("lab 00", options, Box::new(|_cc| Box::new(app)))
         .expect("should be able to run app");
@@ -93,46 +208,78 @@ fn main() {
 struct MyApp<ImageStreamFn, ToImageData>
 where
     ToImageData: Into<MyImageData> + Sized,
-    ImageStreamFn: FnMut() -> Option<(ToImageData, f64)>,
+    ImageStreamFn: FnMut() -> Result<(ToImageData, f64), TryRecvError>,
 {
     // option_updater: Updater<O>,
     image_stream: ImageStreamFn,
     latest_image: Option<ImageData>,
+    /// The frame source stopped, because it could not be opened, was exhausted or failed.
+    stream_ended: bool,
     latest_fps: f64,
     image_processing_configuration: Arc<RwLock<ImageProcessingConfiguration>>,
+    stats: Arc<Mutex<StatsPanel>>,
+    picker: Picker,
+    profiler: Profiler,
+    console: LogConsole,
 }
 
 impl<ImageStreamFn, ToImageData> MyApp<ImageStreamFn, ToImageData>
 where
     ToImageData: Into<MyImageData> + Sized,
-    ImageStreamFn: FnMut() -> Option<(ToImageData, f64)>,
+    ImageStreamFn: FnMut() -> Result<(ToImageData, f64), TryRecvError>,
 {
     fn new(
         image_stream: ImageStreamFn,
         image_processing_configuration: Arc<RwLock<ImageProcessingConfiguration>>,
+        stats: Arc<Mutex<StatsPanel>>,
+        profiler: Profiler,
+        console: LogConsole,
     ) -> Self {
         Self {
             image_stream,
             latest_image: None,
+            stream_ended: false,
             latest_fps: 0.,
             image_processing_configuration,
+            stats,
+            picker: Picker::default(),
+            profiler,
+            console,
         }
     }
+
+    /// Hands the picks to the processing thread.
+    fn publish_picks(&self) {
+        self.image_processing_configuration
+            .write()
+            .unwrap()
+            .picks
+            .clone_from(&self.picker.picks);
+    }
 }
 
 impl<ImageStreamFn, ToImageData> App for MyApp<ImageStreamFn, ToImageData>
 where
     ToImageData: Into<MyImageData> + Sized,
-    ImageStreamFn: FnMut() -> Option<(ToImageData, f64)>,
+    ImageStreamFn: FnMut() -> Result<(ToImageData, f64), TryRecvError>,
 {
     fn update(&mut self, ctx: &Context, epi_frame: &mut Frame) {
-        let (rgb, fps) =
-            (self.image_stream)().map_or_else(|| (None, None), |(rgb, fps)| (Some(rgb), Some(fps)));
+        let (rgb, fps) = match (self.image_stream)() {
+            Ok((rgb, fps)) => (Some(rgb), Some(fps)),
+            Err(e) => {
+                self.stream_ended |= e == TryRecvError::Disconnected;
+                (None, None)
+            }
+        };
 
         let mut configuration = self.image_processing_configuration.read().unwrap().clone();
 
         let mut changed = false;
 
+        TopBottomPanel::bottom("Log").show(ctx, |panel| {
+            draw_log_console(&self.console, panel);
+        });
+
         SidePanel::left("Configure").show(ctx, |sidebar| {
             sidebar.spacing_mut().item_spacing.y = 10.;
 
@@ -170,31 +317,58 @@ where
 
             Separator::default().ui(sidebar);
 
+            changed |= sidebar
+                .checkbox(&mut configuration.use_blur, "blur")
+                .changed();
+
             let slider = Slider::new(&mut configuration.blur, 1.0..=20.)
                 .step_by(1.)
                 .text("blur");
-            changed |= sidebar.add(slider).changed();
+            changed |= sidebar
+                .add_enabled(configuration.use_blur, slider)
+                .changed();
+
+            changed |= sidebar
+                .checkbox(&mut configuration.use_canny, "canny")
+                .changed();
 
             let slider = Slider::new(&mut configuration.canny_lo, 1.0..=configuration.canny_hi)
                 .step_by(1.)
                 .text("canny lo");
-            changed |= sidebar.add(slider).changed();
+            changed |= sidebar
+                .add_enabled(configuration.use_canny, slider)
+                .changed();
 
             let slider = Slider::new(&mut configuration.canny_hi, configuration.canny_lo..=30.)
                 .step_by(1.)
                 .text("canny hi");
-            changed |= sidebar.add(slider).changed();
+            changed |= sidebar
+                .add_enabled(configuration.use_canny, slider)
+                .changed();
+
+            changed |= sidebar
+                .checkbox(&mut configuration.annotate, "annotate picks")
+                .on_hover_text("draw the picks onto the processed image")
+                .changed();
 
             if let Some(fps) = fps {
                 self.latest_fps = fps;
             }
 
             Separator::default().ui(sidebar);
-            sidebar.label(format!("{:.1}", self.latest_fps));
+            sidebar.label(format!("{:.1} fps", self.latest_fps));
+
+            if self.picker.draw(sidebar) {
+                self.publish_picks();
+            }
+
+            draw_profiler(&self.profiler, sidebar);
         });
 
         if changed {
-            eprintln!("changing configuration to:\n{configuration:?}");
+            // the picks may have been published since the configuration was read
+            configuration.picks.clone_from(&self.picker.picks);
+            info!(?configuration, "changed configuration");
             self.image_processing_configuration
                 .write()
                 .unwrap()
@@ -202,24 +376,51 @@ where
         }
 
         if let Some(image) = rgb {
+            let _span = debug_span!("to image data").entered();
             self.latest_image = Some(image.into().0);
         }
 
-        CentralPanel::default().show(ctx, |image_draw_area| match &self.latest_image {
-            Some(image) => {
-                let tex = image_draw_area.ctx().load_texture(
-                    "frame",
-                    image.clone(),
-                    TextureOptions::LINEAR,
-                );
-                image_draw_area.image(&tex, image_draw_area.available_size());
-            }
-            None => {
+        SidePanel::right("Statistics").show(ctx, |sidebar| {
+            self.stats.lock().unwrap().draw(sidebar);
+        });
+
+        CentralPanel::default().show(ctx, |image_draw_area| {
+            if self.stream_ended {
                 image_draw_area.colored_label(
                     image_draw_area.visuals().error_fg_color,
-                    "image stream returned nothing",
+                    "the frame source stopped, see the log",
                 );
             }
+            match &self.latest_image {
+                Some(image) => {
+                    let tex = debug_span!("texture upload").in_scope(|| {
+                        image_draw_area.ctx().load_texture(
+                            "frame",
+                            image.clone(),
+                            TextureOptions::LINEAR,
+                        )
+                    });
+                    let response = image_draw_area
+                        .image(&tex, image_draw_area.available_size())
+                        .interact(Sense::click_and_drag());
+                    let mut stats = self.stats.lock().unwrap();
+                    if stats.enabled && self.picker.tool == PickTool::None {
+                        select_roi(image_draw_area, &response, image.size(), &mut stats.roi);
+                    }
+                    if self
+                        .picker
+                        .interact(image_draw_area, &response, image.size())
+                    {
+                        self.publish_picks();
+                    }
+                }
+                None => {
+                    image_draw_area.colored_label(
+                        image_draw_area.visuals().error_fg_color,
+                        "image stream returned nothing",
+                    );
+                }
+            }
         });
 
         if ctx.input(|i| {
@@ -0,0 +1,50 @@
+use common::convert::to_mat;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use image::{ImageBuffer, Rgb, RgbImage};
+use lab_00_opencv_egui::MyImageData;
+use opencv::{
+    core::Mat,
+    imgproc::{self, cvt_color},
+};
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 480;
+
+fn synthetic_rgb() -> RgbImage {
+    #[allow(clippy::cast_possible_truncation)]
+    ImageBuffer::from_fn(WIDTH, HEIGHT, |x, y| {
+        Rgb([(x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8])
+    })
+}
+
+fn mat_conversion(c: &mut Criterion) {
+    let rgb = synthetic_rgb();
+
+    let mut group = c.benchmark_group("Mat conversion");
+    group.bench_function("RgbImage to Mat", |b| {
+        b.iter_batched(|| rgb.clone(), to_mat, BatchSize::LargeInput);
+    });
+    group.finish();
+}
+
+fn display(c: &mut Criterion) {
+    let rgb = to_mat(synthetic_rgb()).expect("should be able to convert synthetic frame");
+    let gray = {
+        let mut out = Mat::default();
+        cvt_color(&rgb, &mut out, imgproc::COLOR_RGB2GRAY, 1)
+            .expect("should be able to convert rgb to grayscale");
+        out
+    };
+
+    let mut group = c.benchmark_group("display");
+    group.bench_function("MyImageData from 3-channel Mat", |b| {
+        b.iter_batched(|| rgb.clone(), MyImageData::from, BatchSize::LargeInput);
+    });
+    group.bench_function("MyImageData from 1-channel Mat", |b| {
+        b.iter_batched(|| gray.clone(), MyImageData::from, BatchSize::LargeInput);
+    });
+    group.finish();
+}
+
+criterion_group!(benches, mat_conversion, display);
+criterion_main!(benches);
@@ -1,5 +1,4 @@
 use eframe::egui::{ColorImage, ImageData};
-use image::RgbImage;
 use opencv::core::{Mat, MatTraitConst, MatTraitConstManual};
 use opencv::imgproc::{self, cvt_color};
 
@@ -40,35 +39,3 @@ impl From<Mat> for MyImageData {
         MyImageData(out.into())
     }
 }
-
-/// Turns an `image::RgbImage` into an `opencv::core::Mat`
-///
-/// # Arguments
-///
-/// * `image`: `RgbImage`
-///
-/// returns: Result<Mat, Error>
-///
-/// # Errors
-///
-/// * `opencv::Error` if constructing or copying Mats fails.
-pub fn to_mat(mut image: RgbImage) -> Result<Mat, opencv::Error> {
-    let data = image.as_mut_ptr();
-    let step = opencv::core::Mat_AUTO_STEP;
-    let mat = unsafe {
-        // SAFETY
-        // The Mat from this block references the owned image, which is dropped at the end of the
-        // function. Before the drop, the data pointed to by this Mat is cloned to an owning Mat,
-        // which makes it safe.
-        Mat::new_rows_cols_with_data(
-            i32::try_from(image.height()).expect("image size should fit in an i32"),
-            i32::try_from(image.width()).expect("image size should fit in an i32"),
-            opencv::core::CV_8UC3,
-            data.cast::<std::ffi::c_void>(),
-            step,
-        )?
-    };
-    let mut out = Mat::default();
-    mat.copy_to(&mut out)?;
-    Ok(out)
-}
@@ -1,205 +1,587 @@
 use anyhow::Result;
-use common::cam::{create_camera_stream, CameraIndex};
+use clap::Parser;
+use common::{
+    cam::create_frame_stream,
+    capture::{save_snapshot, Recorder, RecordingFormat},
+    cli::LabArgs,
+    convert::to_mat,
+    picking::{PickTool, Picker},
+    pipeline::{Pipeline, PipelineConfiguration, StageOutputs},
+    preset::Preset,
+    profiler::{draw_profiler, Profiler},
+    stats::{select_roi, ImageStats, StatsPanel},
+    telemetry::{self, draw_log_console, LogConsole},
+    util::TemporalFilterConfiguration,
+};
 use eframe::{
-    egui::{self, CentralPanel, Context, ImageData, Key, SidePanel, Slider, TextureOptions},
+    egui::{
+        self, CentralPanel, ComboBox, Context, ImageData, Key, Sense, Separator, SidePanel, Slider,
+        TextureOptions, TopBottomPanel, Widget,
+    },
     App, Frame,
 };
 use image::RgbImage;
-use lab_00_opencv_egui::{to_mat, MyImageData};
-use opencv::{
-    core::{Mat, Size},
-    imgproc::{self},
+use lab_00_opencv_egui::MyImageData;
+use opencv::core::Mat;
+use std::{
+    mem,
+    path::PathBuf,
+    sync::{mpsc::TryRecvError, Arc, Mutex, RwLock},
 };
-use std::sync::{mpsc::TryRecvError, Arc, RwLock};
-
-#[derive(Debug, Clone)]
-struct ImageProcessingConfiguration {
-    blur: bool,
-    blur_sigma: f64,
-    canny: bool,
-    canny_low: f64,
-    canny_high: f64,
+use tracing::{debug_span, error, info};
+
+/// Draws the parameter configuration GUI elements on the provided ui element, and returns
+/// Some(configuration) if the user changed the options.
+///
+/// # Arguments
+///
+/// * `configuration`: the current configuration
+/// * `ui`: egui element to draw on
+///
+/// returns: Option<PipelineConfiguration>
+fn draw_configuration(
+    configuration: &PipelineConfiguration,
+    ui: &mut egui::Ui,
+) -> Option<PipelineConfiguration> {
+    let mut configuration = configuration.clone();
+    let mut changed = false;
+
+    ui.spacing_mut().item_spacing.y = 10.;
+
+    changed |= ui.checkbox(&mut configuration.mirror, "mirror").changed();
+
+    changed |= ui
+        .checkbox(&mut configuration.temporal, "temporal filter")
+        .changed();
+
+    if configuration.temporal {
+        changed |= draw_temporal_filter(&mut configuration.temporal_filter, ui);
+    }
+
+    changed |= ui.checkbox(&mut configuration.blur, "blur").changed();
+
+    if configuration.blur {
+        changed |= ui
+            .add(
+                Slider::new(&mut configuration.blur_sigma, 1.0..=10.)
+                    .step_by(1.)
+                    .text("sigma"),
+            )
+            .changed();
+    }
+
+    changed |= ui
+        .checkbox(&mut configuration.bilateral, "bilateral")
+        .changed();
+
+    if configuration.bilateral {
+        changed |= ui
+            .add(Slider::new(&mut configuration.bilateral_diameter, 1..=30).text("diameter"))
+            .changed();
+        changed |= ui
+            .add(
+                Slider::new(&mut configuration.bilateral_sigma_color, 1.0..=200.)
+                    .text("sigma color"),
+            )
+            .changed();
+        changed |= ui
+            .add(
+                Slider::new(&mut configuration.bilateral_sigma_space, 1.0..=200.)
+                    .text("sigma space"),
+            )
+            .changed();
+    }
+
+    changed |= ui.checkbox(&mut configuration.canny, "canny").changed();
+
+    if configuration.canny {
+        changed |= ui
+            .add(
+                Slider::new(&mut configuration.canny_low, 1.0..=configuration.canny_high)
+                    .step_by(0.5)
+                    .text("low"),
+            )
+            .changed();
+        changed |= ui
+            .add(
+                Slider::new(&mut configuration.canny_high, configuration.canny_low..=50.)
+                    .step_by(0.5)
+                    .text("high"),
+            )
+            .changed();
+    }
+
+    changed |= ui
+        .checkbox(&mut configuration.annotate, "annotate picks")
+        .on_hover_text("draw the picks onto the output, e.g. for snapshots and recordings")
+        .changed();
+
+    changed.then_some(configuration)
+}
+
+/// Processing pipeline which converts an `image::RgbImage` to an `egui::ImageData`.
+fn process(
+    configuration: &PipelineConfiguration,
+    pipeline: &mut Pipeline,
+    capture: &Mutex<Capture>,
+    stats: &Mutex<StatsPanel>,
+    image: RgbImage,
+) -> Result<ImageData> {
+    let mat = debug_span!("to mat")
+        .in_scope(|| to_mat(image))
+        .expect("RgbImage should be convertible to Mat");
+
+    // only copy the raw frame when it is needed, and don't hold the lock while processing
+    let snapshot = mem::take(&mut capture.lock().unwrap().snapshot_requested);
+    let raw = snapshot.then(|| mat.clone());
+
+    let stages = pipeline.process(configuration, mat)?;
+    // write the files without holding the lock, which the GUI takes on every frame
+    let job = capture.lock().unwrap().start_job(raw);
+    let outcome = job.run(&stages, configuration);
+    capture.lock().unwrap().finish_job(outcome);
+
+    let (enabled, roi) = {
+        let stats = stats.lock().unwrap();
+        (stats.enabled, stats.roi)
+    };
+    if enabled {
+        let frame_stats =
+            debug_span!("statistics").in_scope(|| ImageStats::from_mat(stages.output(), roi))?;
+        stats.lock().unwrap().stats = Some(frame_stats);
+    }
+
+    // convert to image data here
+    let _span = debug_span!("to image data").entered();
+    Ok(MyImageData::from(stages.into_output()).0)
+}
+
+/// Snapshot and recording requests from the GUI. They are carried out on the processing thread,
+/// where the raw frame and the intermediate images are available.
+struct Capture {
+    directory: PathBuf,
+    fps: f64,
+    snapshot_requested: bool,
+    recording: bool,
+    format: RecordingFormat,
+    /// The recorder between frames. While a frame is written, it is in the [`CaptureJob`].
+    recorder: Option<Recorder>,
+    /// Where the current recording goes, shown in the GUI.
+    recording_path: Option<PathBuf>,
+    /// Outcome of the latest snapshot or recording, shown in the GUI.
+    status: Option<String>,
 }
 
-impl Default for ImageProcessingConfiguration {
-    fn default() -> Self {
+impl Capture {
+    fn new(directory: PathBuf, fps: f64) -> Self {
         Self {
-            blur: false,
-            blur_sigma: 1.,
-            canny: false,
-            canny_low: 10.,
-            canny_high: 15.,
+            directory,
+            fps,
+            snapshot_requested: false,
+            recording: false,
+            format: RecordingFormat::default(),
+            recorder: None,
+            recording_path: None,
+            status: None,
+        }
+    }
+
+    /// Takes what is needed to save the snapshot bundle if `raw` is given, and to start, continue
+    /// or stop the recording, so that the files can be written after the lock is released.
+    fn start_job(&mut self, raw: Option<Mat>) -> CaptureJob {
+        CaptureJob {
+            directory: self.directory.clone(),
+            fps: self.fps,
+            raw,
+            recording: self.recording,
+            format: self.format,
+            recorder: self.recorder.take(),
+        }
+    }
+
+    fn finish_job(&mut self, outcome: CaptureOutcome) {
+        if outcome.status.is_some() {
+            self.status = outcome.status;
+        }
+        if outcome.failed {
+            self.recording = false;
+        }
+        self.recording_path = outcome
+            .recorder
+            .as_ref()
+            .map(|recorder| recorder.path().to_path_buf());
+        self.recorder = outcome.recorder;
+    }
+}
+
+/// The snapshot and recording work of one frame.
+struct CaptureJob {
+    directory: PathBuf,
+    fps: f64,
+    raw: Option<Mat>,
+    recording: bool,
+    format: RecordingFormat,
+    recorder: Option<Recorder>,
+}
+
+struct CaptureOutcome {
+    recorder: Option<Recorder>,
+    status: Option<String>,
+    /// Whether the recording failed and has to be stopped.
+    failed: bool,
+}
+
+impl CaptureJob {
+    fn run(self, stages: &StageOutputs, configuration: &PipelineConfiguration) -> CaptureOutcome {
+        let mut status =
+            self.raw.map(
+                |raw| match save_snapshot(&self.directory, &raw, stages, configuration) {
+                    Ok(path) => format!("saved snapshot to {}", path.display()),
+                    Err(e) => {
+                        error!(%e, "could not save snapshot");
+                        format!("could not save snapshot: {e}")
+                    }
+                },
+            );
+
+        if !self.recording {
+            if let Some(recorder) = self.recorder {
+                // dropping the recorder finishes the file
+                let path = recorder.path().to_path_buf();
+                drop(recorder);
+                status = Some(format!("saved recording to {}", path.display()));
+            }
+            return CaptureOutcome {
+                recorder: None,
+                status,
+                failed: false,
+            };
+        }
+
+        let recorder = match self.recorder {
+            Some(recorder) => Ok(recorder),
+            None => Recorder::new(&self.directory, self.format, self.fps),
+        };
+        match recorder.and_then(|mut recorder| {
+            recorder.write(stages.output())?;
+            Ok(recorder)
+        }) {
+            Ok(recorder) => CaptureOutcome {
+                recorder: Some(recorder),
+                status,
+                failed: false,
+            },
+            Err(e) => {
+                error!(%e, "could not record");
+                CaptureOutcome {
+                    recorder: None,
+                    status: Some(format!("recording stopped: {e}")),
+                    failed: true,
+                }
+            }
         }
     }
 }
 
-impl ImageProcessingConfiguration {
-    /// Draws the parameter configuration GUI elements on the provided ui element, and returns
-    /// Some(Self) if the user changed the options.
-    ///
-    /// # Arguments
-    ///
-    /// * `ui`: egui element to draw on
-    ///
-    /// returns: Option<ImageProcessingConfiguration>
-    fn draw(&self, ui: &mut egui::Ui) -> Option<Self> {
-        let mut configuration = self.clone();
-        let mut changed = false;
+/// Draws the snapshot and recording controls.
+fn draw_capture(capture: &mut Capture, ui: &mut egui::Ui) {
+    if ui
+        .button("snapshot")
+        .on_hover_text("save the raw frame, every stage and the configuration (S)")
+        .clicked()
+    {
+        capture.snapshot_requested = true;
+    }
 
-        ui.spacing_mut().item_spacing.y = 10.;
+    ui.add_enabled_ui(!capture.recording, |ui| {
+        ComboBox::from_label("recording format")
+            .selected_text(capture.format.name())
+            .show_ui(ui, |ui| {
+                for format in RecordingFormat::ALL {
+                    ui.selectable_value(&mut capture.format, format, format.name());
+                }
+            });
+    });
+    ui.checkbox(&mut capture.recording, "record")
+        .on_hover_text("record the pipeline output (R)");
 
-        changed |= ui.checkbox(&mut configuration.blur, "blur").changed();
+    if let Some(path) = &capture.recording_path {
+        ui.colored_label(
+            ui.visuals().error_fg_color,
+            format!("recording to {}", path.display()),
+        );
+    } else if let Some(status) = &capture.status {
+        ui.label(status);
+    }
+}
 
-        if configuration.blur {
+/// Draws the selection and parameters of a temporal filter, and returns whether the user changed
+/// them.
+fn draw_temporal_filter(
+    configuration: &mut TemporalFilterConfiguration,
+    ui: &mut egui::Ui,
+) -> bool {
+    let mut changed = false;
+
+    ComboBox::from_label("temporal filter kind")
+        .selected_text(configuration.name())
+        .show_ui(ui, |ui| {
+            for candidate in TemporalFilterConfiguration::defaults() {
+                let selected = candidate.name() == configuration.name();
+                if ui.selectable_label(selected, candidate.name()).clicked() && !selected {
+                    *configuration = candidate;
+                    changed = true;
+                }
+            }
+        });
+
+    match configuration {
+        TemporalFilterConfiguration::MovingAverage { size }
+        | TemporalFilterConfiguration::Median { size } => {
+            changed |= ui.add(Slider::new(size, 1..=30).text("frames")).changed();
+        }
+        TemporalFilterConfiguration::ExponentialMovingAverage { alpha } => {
             changed |= ui
                 .add(
-                    Slider::new(&mut configuration.blur_sigma, 1.0..=10.)
-                        .step_by(1.)
-                        .text("sigma"),
+                    Slider::new(alpha, 0.01..=1.)
+                        .logarithmic(true)
+                        .text("alpha"),
                 )
                 .changed();
         }
-
-        changed |= ui.checkbox(&mut configuration.canny, "canny").changed();
-
-        if configuration.canny {
+        TemporalFilterConfiguration::MinHold | TemporalFilterConfiguration::MaxHold => {}
+        TemporalFilterConfiguration::RunningGaussian { alpha, threshold } => {
             changed |= ui
                 .add(
-                    Slider::new(&mut configuration.canny_low, 1.0..=configuration.canny_high)
-                        .step_by(0.5)
-                        .text("low"),
+                    Slider::new(alpha, 0.001..=0.5)
+                        .logarithmic(true)
+                        .text("alpha"),
                 )
                 .changed();
             changed |= ui
                 .add(
-                    Slider::new(&mut configuration.canny_high, configuration.canny_low..=50.)
-                        .step_by(0.5)
-                        .text("high"),
+                    Slider::new(threshold, 0.5..=5.)
+                        .step_by(0.1)
+                        .text("threshold"),
                 )
                 .changed();
         }
-
-        changed.then_some(configuration)
+        TemporalFilterConfiguration::Mog2 {
+            history,
+            var_threshold,
+            detect_shadows,
+        } => {
+            changed |= ui
+                .add(Slider::new(history, 10..=1000).text("history"))
+                .changed();
+            changed |= ui
+                .add(Slider::new(var_threshold, 1.0..=64.).text("variance threshold"))
+                .changed();
+            changed |= ui.checkbox(detect_shadows, "detect shadows").changed();
+        }
     }
 
-    /// Processing pipeline which converts an `image::RgbImage` to an `egui::ImageData`.
-    fn process(&self, image: RgbImage) -> Result<ImageData> {
-        let mat = to_mat(image).expect("RgbImage should be convertible to Mat");
-
-        // Do processing here
-
-        let mat = if self.blur {
-            let mut out = Mat::default();
-            imgproc::gaussian_blur(
-                &mat,
-                &mut out,
-                Size::new(0, 0),
-                self.blur_sigma,
-                self.blur_sigma,
-                opencv::core::BORDER_REFLECT,
-            )?;
-            out
-        } else {
-            mat
-        };
-
-        let mat = if self.canny {
-            let mut out = Mat::default();
-            imgproc::canny(&mat, &mut out, self.canny_low, self.canny_high, 3, false)?;
-            out
-        } else {
-            mat
-        };
+    changed
+}
 
-        // convert to image data here
-        Ok(MyImageData::from(mat).0)
-    }
+/// Read frames, process them with OpenCV and show them in an egui window.
+#[derive(Debug, Parser)]
+#[command(about)]
+struct Cli {
+    #[command(flatten)]
+    lab: LabArgs,
+    /// Directory for snapshots and recordings
+    #[arg(long, default_value = "captures")]
+    capture_directory: PathBuf,
 }
 
 fn main() {
+    let cli = Cli::parse();
+    let telemetry =
+        telemetry::init(&cli.lab.telemetry).expect("should be able to set up telemetry");
+
+    #[allow(clippy::cast_precision_loss)]
     let options = eframe::NativeOptions {
-        initial_window_size: Some(egui::vec2(800., 600.)),
+        initial_window_size: Some(egui::vec2(
+            cli.lab.layout.window_size.width as f32,
+            cli.lab.layout.window_size.height as f32,
+        )),
+        fullscreen: cli.lab.layout.fullscreen,
 
         ..Default::default()
     };
-    let processor = Arc::new(RwLock::new(ImageProcessingConfiguration::default()));
+    let processor = Arc::new(RwLock::new(PipelineConfiguration::from(
+        cli.lab.preset.unwrap_or(Preset::Raw),
+    )));
+
+    // cameras may deliver a different rate than requested, so recordings can play back at a
+    // slightly wrong speed
+    let capture = Arc::new(Mutex::new(Capture::new(
+        cli.capture_directory,
+        cli.lab.source.fps.unwrap_or(30.),
+    )));
+
+    let stats = Arc::new(Mutex::new(StatsPanel::default()));
 
-    let camera_stream_receiver = create_camera_stream(CameraIndex::Index(4), {
+    let source_options = cli.lab.source.options();
+    let camera_stream_receiver = create_frame_stream(cli.lab.source.source, source_options, {
         let processor = processor.clone();
-        move |img| processor.read().unwrap().process(img).ok()
+        let capture = capture.clone();
+        let stats = stats.clone();
+        let mut pipeline = Pipeline::default();
+        move |img| {
+            process(
+                &processor.read().unwrap(),
+                &mut pipeline,
+                &capture,
+                &stats,
+                img,
+            )
+            .map_err(|e| error!(%e, "could not process frame"))
+            .ok()
+        }
     });
 
-    let stream = {
-        move || match camera_stream_receiver.try_recv() {
-            Ok(img) => img,
-            Err(TryRecvError::Disconnected) => {
-                panic!("stream has no updater")
-            }
-            _ => None,
-        }
-    };
+    let stream = move || camera_stream_receiver.try_recv();
 
-    let app = MyApp::new(stream, processor);
+    let app = MyApp::new(
+        stream,
+        processor,
+        capture,
+        stats,
+        telemetry.profiler.clone(),
+        telemetry.console.clone(),
+    );
 
     eframe::run_native("lab 00", options, Box::new(|_cc| Box::new(app))).unwrap();
 }
 
 struct MyApp<ImageStreamFn>
 where
-    ImageStreamFn: FnMut() -> Option<ImageData>,
+    ImageStreamFn: FnMut() -> Result<Option<ImageData>, TryRecvError>,
 {
     image_stream: ImageStreamFn,
     latest_image: Option<ImageData>,
-    image_processing_configuration: Arc<RwLock<ImageProcessingConfiguration>>,
+    /// The frame source stopped, because it could not be opened, was exhausted or failed.
+    stream_ended: bool,
+    image_processing_configuration: Arc<RwLock<PipelineConfiguration>>,
+    capture: Arc<Mutex<Capture>>,
+    stats: Arc<Mutex<StatsPanel>>,
+    picker: Picker,
+    profiler: Profiler,
+    console: LogConsole,
 }
 
 impl<ImageStreamFn> MyApp<ImageStreamFn>
 where
-    ImageStreamFn: FnMut() -> Option<ImageData>,
+    ImageStreamFn: FnMut() -> Result<Option<ImageData>, TryRecvError>,
 {
     fn new(
         image_stream: ImageStreamFn,
-        image_processing_configuration: Arc<RwLock<ImageProcessingConfiguration>>,
+        image_processing_configuration: Arc<RwLock<PipelineConfiguration>>,
+        capture: Arc<Mutex<Capture>>,
+        stats: Arc<Mutex<StatsPanel>>,
+        profiler: Profiler,
+        console: LogConsole,
     ) -> Self {
         Self {
             image_stream,
             latest_image: None,
+            stream_ended: false,
             image_processing_configuration,
+            capture,
+            stats,
+            picker: Picker::default(),
+            profiler,
+            console,
         }
     }
+
+    /// Hands the picks to the processing thread.
+    fn publish_picks(&self) {
+        self.image_processing_configuration
+            .write()
+            .unwrap()
+            .picks
+            .clone_from(&self.picker.picks);
+    }
 }
 
 impl<ImageStreamFn> App for MyApp<ImageStreamFn>
 where
-    ImageStreamFn: FnMut() -> Option<ImageData>,
+    ImageStreamFn: FnMut() -> Result<Option<ImageData>, TryRecvError>,
 {
     fn update(&mut self, ctx: &Context, epi_frame: &mut Frame) {
-        if let Some(image) = (self.image_stream)() {
-            self.latest_image = Some(image);
+        match (self.image_stream)() {
+            Ok(Some(image)) => self.latest_image = Some(image),
+            Ok(None) | Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => self.stream_ended = true,
         }
 
+        TopBottomPanel::bottom("Log").show(ctx, |panel| {
+            draw_log_console(&self.console, panel);
+        });
+
         SidePanel::left("Configure").show(ctx, |sidebar| {
-            let changed_configuration = self
-                .image_processing_configuration
-                .read()
-                .unwrap()
-                .draw(sidebar);
+            let changed_configuration = draw_configuration(
+                &self.image_processing_configuration.read().unwrap(),
+                sidebar,
+            );
             if let Some(configuration) = changed_configuration {
+                info!(?configuration, "changed configuration");
                 self.image_processing_configuration
                     .write()
                     .unwrap()
                     .clone_from(&configuration);
             }
+
+            Separator::default().ui(sidebar);
+
+            draw_capture(&mut self.capture.lock().unwrap(), sidebar);
+
+            Separator::default().ui(sidebar);
+
+            if self.picker.draw(sidebar) {
+                self.publish_picks();
+            }
+
+            Separator::default().ui(sidebar);
+
+            draw_profiler(&self.profiler, sidebar);
+        });
+
+        SidePanel::right("Statistics").show(ctx, |sidebar| {
+            self.stats.lock().unwrap().draw(sidebar);
         });
 
         CentralPanel::default().show(ctx, |image_draw_area| {
-            if let Some(ref image) = self.latest_image {
-                let texture = image_draw_area.ctx().load_texture(
-                    "frame",
-                    image.clone(),
-                    TextureOptions::LINEAR,
+            if self.stream_ended {
+                image_draw_area.colored_label(
+                    image_draw_area.visuals().error_fg_color,
+                    "the frame source stopped, see the log",
                 );
-                image_draw_area.image(&texture, image_draw_area.available_size());
+            }
+            if let Some(ref image) = self.latest_image {
+                let texture = debug_span!("texture upload").in_scope(|| {
+                    image_draw_area.ctx().load_texture(
+                        "frame",
+                        image.clone(),
+                        TextureOptions::LINEAR,
+                    )
+                });
+                let response = image_draw_area
+                    .image(&texture, image_draw_area.available_size())
+                    .interact(Sense::click_and_drag());
+                let mut stats = self.stats.lock().unwrap();
+                if stats.enabled && self.picker.tool == PickTool::None {
+                    select_roi(image_draw_area, &response, image.size(), &mut stats.roi);
+                }
+                if self
+                    .picker
+                    .interact(image_draw_area, &response, image.size())
+                {
+                    self.publish_picks();
+                }
             } else {
                 image_draw_area.colored_label(
                     image_draw_area.visuals().error_fg_color,
@@ -208,6 +590,13 @@ where
             }
         });
 
+        if ctx.input(|i| i.key_pressed(Key::S)) {
+            self.capture.lock().unwrap().snapshot_requested = true;
+        }
+        if ctx.input(|i| i.key_pressed(Key::R)) {
+            self.capture.lock().unwrap().recording ^= true;
+        }
+
         if ctx.input(|i| {
             [Key::Q, Key::Escape]
                 .into_iter()
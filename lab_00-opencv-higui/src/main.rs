@@ -1,76 +1,287 @@
-use common::util::MatMovingAverage;
-use opencv::{
-    core::{self, Mat, MatTraitConstManual, Size},
-    highgui,
-    imgproc::{self},
-    videoio::{self, VideoCaptureTrait, VideoCaptureTraitConst},
+use anyhow::Result;
+use clap::Parser;
+use common::{
+    capture::{timestamp, to_bgr, write_image, CaptureError},
+    cli::LabArgs,
+    convert::to_mat,
+    pipeline::{Pipeline, PipelineConfiguration, Stage},
+    preset::Preset,
+    profiler::Profiler,
+    telemetry,
+    util::TemporalFilterConfiguration,
 };
+use opencv::{core::Mat, highgui};
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tracing::{debug, debug_span, error, info};
 
 const FREQUENCY: f64 = 60.;
+/// Number of frames that may fail to be read or processed before giving up.
+const ERROR_LIMIT: u32 = 30;
 #[allow(clippy::cast_possible_truncation)]
 const WAIT_MS: i32 = ((1.0_f64 / FREQUENCY) * 1000.) as i32;
 
-fn main() -> opencv::Result<()> {
-    let camera_index = 0;
+const HELP: &str = "\
+keys:
+  q, esc  quit
+  t       toggle temporal filter
+  f       cycle temporal filter kind
+  b       toggle blur
+  l       toggle bilateral filter
+  c       toggle canny
+  m       toggle mirror
+  v       cycle view through the pipeline stages
+  p       cycle preset
+  s       save a snapshot of the current view
+  i       log the timings of the pipeline stages
+  h       print this help";
+
+/// Read frames, process them with OpenCV and show them in a highgui window.
+#[derive(Debug, Parser)]
+#[command(about, after_help = HELP)]
+struct Cli {
+    #[command(flatten)]
+    lab: LabArgs,
+    /// Directory for snapshots
+    #[arg(long, default_value = "captures")]
+    capture_directory: PathBuf,
+}
+
+/// A highgui trackbar bound to an integer-valued view of a configuration parameter.
+struct Trackbar {
+    name: &'static str,
+    max: i32,
+    get: fn(&PipelineConfiguration) -> i32,
+    set: fn(&mut PipelineConfiguration, i32),
+}
+
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_possible_wrap
+)]
+const TRACKBARS: [Trackbar; 7] = [
+    Trackbar {
+        name: "frames",
+        max: 30,
+        get: |c| match c.temporal_filter {
+            TemporalFilterConfiguration::MovingAverage { size }
+            | TemporalFilterConfiguration::Median { size } => size as i32,
+            _ => 0,
+        },
+        set: |c, v| match &mut c.temporal_filter {
+            TemporalFilterConfiguration::MovingAverage { size }
+            | TemporalFilterConfiguration::Median { size } => *size = v.max(1) as usize,
+            _ => {}
+        },
+    },
+    Trackbar {
+        name: "blur sigma x10",
+        max: 100,
+        get: |c| (c.blur_sigma * 10.).round() as i32,
+        set: |c, v| c.blur_sigma = f64::from(v.max(1)) / 10.,
+    },
+    Trackbar {
+        name: "bilateral diameter",
+        max: 30,
+        get: |c| c.bilateral_diameter,
+        set: |c, v| c.bilateral_diameter = v.max(1),
+    },
+    Trackbar {
+        name: "bilateral sigma color",
+        max: 200,
+        get: |c| c.bilateral_sigma_color.round() as i32,
+        set: |c, v| c.bilateral_sigma_color = f64::from(v),
+    },
+    Trackbar {
+        name: "bilateral sigma space",
+        max: 200,
+        get: |c| c.bilateral_sigma_space.round() as i32,
+        set: |c, v| c.bilateral_sigma_space = f64::from(v),
+    },
+    Trackbar {
+        name: "canny low",
+        max: 100,
+        get: |c| c.canny_low.round() as i32,
+        set: |c, v| c.canny_low = f64::from(v),
+    },
+    Trackbar {
+        name: "canny high",
+        max: 200,
+        get: |c| c.canny_high.round() as i32,
+        set: |c, v| c.canny_high = f64::from(v),
+    },
+];
+
+fn create_trackbars(window: &str, configuration: &PipelineConfiguration) -> Result<()> {
+    for trackbar in &TRACKBARS {
+        highgui::create_trackbar(trackbar.name, window, None, trackbar.max, None)?;
+    }
+    set_trackbars(window, configuration)
+}
+
+fn set_trackbars(window: &str, configuration: &PipelineConfiguration) -> Result<()> {
+    for trackbar in &TRACKBARS {
+        highgui::set_trackbar_pos(trackbar.name, window, (trackbar.get)(configuration))?;
+    }
+    Ok(())
+}
+
+fn read_trackbars(window: &str, configuration: &mut PipelineConfiguration) -> Result<()> {
+    for trackbar in &TRACKBARS {
+        let position = highgui::get_trackbar_pos(trackbar.name, window)?;
+        if position != (trackbar.get)(configuration) {
+            (trackbar.set)(configuration, position);
+        }
+    }
+    Ok(())
+}
+
+/// The temporal filter kind following the current one, wrapping around.
+fn next_temporal_filter(current: &TemporalFilterConfiguration) -> TemporalFilterConfiguration {
+    let candidates = TemporalFilterConfiguration::defaults();
+    let position = candidates
+        .iter()
+        .position(|candidate| candidate.name() == current.name())
+        .unwrap_or_default();
+    candidates[(position + 1) % candidates.len()].clone()
+}
+
+/// Saves `frame`, the output of `stage`, to a new PNG file in `directory`, and returns its path.
+fn save_snapshot(directory: &Path, frame: &Mat, stage: Stage) -> Result<PathBuf, CaptureError> {
+    fs::create_dir_all(directory)?;
+    let path = directory.join(format!(
+        "snapshot-{}-{}.png",
+        timestamp(),
+        stage.name().replace(' ', "_")
+    ));
+    write_image(&path, frame)?;
+    Ok(path)
+}
+
+/// Logs a frame that could not be read or processed, and returns whether `ERROR_LIMIT` frames
+/// have now failed.
+fn count_failure(fails: &mut u32, e: &impl Display) -> bool {
+    *fails += 1;
+    error!(fails = *fails, %e, "could not read or process frame");
+    if *fails >= ERROR_LIMIT {
+        error!(ERROR_LIMIT, "exceeded error limit");
+    }
+    *fails >= ERROR_LIMIT
+}
+
+fn log_timings(profiler: &Profiler) {
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.;
+    for (name, stats) in profiler.stats() {
+        info!(
+            stage = %name,
+            mean_ms = ms(stats.mean),
+            p95_ms = ms(stats.p95),
+            max_ms = ms(stats.max),
+            rate = stats.rate,
+            "stage timings"
+        );
+    }
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let telemetry = telemetry::init(&cli.lab.telemetry)?;
+
     let window = "video capture";
     highgui::named_window(window, highgui::WINDOW_GUI_NORMAL)?;
-    let mut cam = videoio::VideoCapture::new(camera_index, videoio::CAP_ANY)?;
-    let opened = cam.is_opened()?;
-    assert!(opened, "Could not open camera at index {camera_index}");
+    let window_size = cli.lab.layout.window_size;
+    highgui::resize_window(
+        window,
+        i32::try_from(window_size.width)?,
+        i32::try_from(window_size.height)?,
+    )?;
+    if cli.lab.layout.fullscreen {
+        highgui::set_window_property(
+            window,
+            highgui::WND_PROP_FULLSCREEN,
+            f64::from(highgui::WINDOW_FULLSCREEN),
+        )?;
+    }
+
+    let mut source = cli.lab.source.source.open(&cli.lab.source.options())?;
+    let mut pipeline = Pipeline::default();
+    let mut preset = cli.lab.preset.unwrap_or(Preset::Edges);
+    let mut configuration = PipelineConfiguration::from(preset);
+    create_trackbars(window, &configuration)?;
+    let mut view = 0;
+    let mut fails = 0;
+
+    eprintln!("{HELP}");
 
-    let mut read_frame = Mat::default().clone();
-    let mut buffer = MatMovingAverage::new(5);
     loop {
-        cam.read(&mut read_frame)?;
-        if read_frame.size()?.width > 0 {
-            buffer.push(read_frame.clone());
-            let mut frame = buffer
-                .average()
-                .expect("should have an image after pushing just before");
-            frame = {
-                let mut result = Mat::default();
-                imgproc::gaussian_blur(
-                    &frame,
-                    &mut result,
-                    Size::new(5, 5),
-                    4.,
-                    4.,
-                    core::BORDER_DEFAULT,
-                )?;
-                result
-            };
-
-            frame = {
-                let mut result = Mat::default();
-                imgproc::bilateral_filter(
-                    &frame,
-                    &mut result,
-                    10,
-                    100.,
-                    100.,
-                    core::BORDER_DEFAULT,
-                )?;
-                result
-            };
-
-            frame = {
-                let mut result = Mat::default();
-                imgproc::canny(&frame, &mut result, 10., 15., 3, true)?;
-                result
-            };
-
-            frame = {
-                let mut result = Mat::default();
-                core::flip(&frame, &mut result, 1).expect("should be able to flip");
-                result
-            };
-
-            highgui::imshow(window, &frame)?;
-        }
-        let key = highgui::wait_key(WAIT_MS)?;
+        let frame = match source.next_frame() {
+            Ok(Some(frame)) => frame,
+            Ok(None) => {
+                debug!("frame source exhausted");
+                break;
+            }
+            Err(e) if count_failure(&mut fails, &e) => break,
+            Err(_) => continue,
+        };
+        read_trackbars(window, &mut configuration)?;
+        let stages = match debug_span!("to mat")
+            .in_scope(|| to_mat(frame))
+            .and_then(|frame| {
+                debug_span!("process").in_scope(|| pipeline.process(&configuration, frame))
+            }) {
+            Ok(stages) => stages,
+            Err(e) if count_failure(&mut fails, &e) => break,
+            Err(_) => continue,
+        };
+        let (stage, frame) = stages
+            .iter()
+            .nth(view)
+            .or_else(|| stages.iter().last())
+            .expect("stages should at least contain the input");
+        let bgr = to_bgr(frame)?;
+        debug_span!("show").in_scope(|| {
+            highgui::set_window_title(window, &format!("{} [{}]", preset.name(), stage.name()))?;
+            highgui::imshow(window, &bgr)
+        })?;
 
-        if key > 0 && key != 255 {
-            break;
+        let key = highgui::wait_key(WAIT_MS)?;
+        if key < 0 {
+            continue;
+        }
+        match u8::try_from(key & 0xff).expect("masked key should fit in a u8") {
+            b'q' | 0x1b => break,
+            b't' => configuration.temporal ^= true,
+            b'f' => {
+                configuration.temporal_filter =
+                    next_temporal_filter(&configuration.temporal_filter);
+                info!(
+                    temporal_filter = configuration.temporal_filter.name(),
+                    "changed temporal filter"
+                );
+                set_trackbars(window, &configuration)?;
+            }
+            b'b' => configuration.blur ^= true,
+            b'l' => configuration.bilateral ^= true,
+            b'c' => configuration.canny ^= true,
+            b'm' => configuration.mirror ^= true,
+            b'v' => view = (view + 1) % stages.iter().count(),
+            b'p' => {
+                preset = preset.next();
+                configuration = PipelineConfiguration::from(preset);
+                set_trackbars(window, &configuration)?;
+            }
+            b's' => match save_snapshot(&cli.capture_directory, frame, *stage) {
+                Ok(path) => info!(path = %path.display(), "saved snapshot"),
+                Err(e) => error!(%e, "could not save snapshot"),
+            },
+            b'i' => log_timings(&telemetry.profiler),
+            b'h' => eprintln!("{HELP}"),
+            _ => {}
         }
     }
 
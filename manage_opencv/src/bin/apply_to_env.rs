@@ -1,101 +1,126 @@
+use anyhow::Result;
 use clap::Parser;
-use std::{
-    fmt::Display,
-    fs::{read_to_string, File, OpenOptions},
-    io::Write,
-    path::Path,
-};
-use toml::{map::Entry, Table, Value};
+use manage_opencv::cargo_config::{self, ArrayMerge, CargoConfigError};
+use std::path::PathBuf;
+use toml_edit::Document;
 
 /// Read TOML table and apply the key/values to the `env`-section of the provided Cargo config file.
+/// Other sections of the TOML table, such as `[build]` or `[target.<triple>]`, are merged into the
+/// corresponding sections.
 #[derive(Debug, Parser)]
 #[command(about)]
 struct Cli {
     /// File to read TOML table from
-    env_contents_toml_file: String,
+    #[arg(required_unless_present = "revert")]
+    env_contents_toml_file: Option<PathBuf>,
     /// .cargo/config.toml file to apply env variables to
-    cargo_config_toml_file: String,
+    #[arg(required_unless_present = "revert")]
+    cargo_config_toml_file: Option<PathBuf>,
+    /// Print a unified diff of the changes instead of writing them
+    #[arg(long)]
+    dry_run: bool,
+    /// How arrays are combined with arrays that are already in the Cargo config file
+    #[arg(long, value_enum, default_value_t)]
+    arrays: ArrayMerge,
+    /// Remove the keys of the TOML table from the Cargo config file instead of setting them
+    #[arg(long)]
+    remove: bool,
+    /// Restore the given Cargo config file from the backup made by the last change
+    #[arg(
+        long,
+        value_name = "CARGO_CONFIG_TOML_FILE",
+        conflicts_with_all = ["env_contents_toml_file", "cargo_config_toml_file", "remove"]
+    )]
+    revert: Option<PathBuf>,
 }
 
-fn create_or_get_file<P>(path: &P) -> (File, Option<String>)
-where
-    P: AsRef<Path> + Display,
-{
-    let contents = read_to_string(path).ok();
-    if let Some(contents) = &contents {
-        eprintln!(
-            "File at {path} already exists and contains {} characters",
-            contents.len()
-        );
-    } else {
-        eprintln!("File at {path} does not already exist");
-    }
-    let file = OpenOptions::new()
-        .write(true)
-        .read(false)
-        .append(false)
-        .create(true)
-        .truncate(true)
-        .open(path)
-        .expect("should be able to create or open");
-    (file, contents)
-}
-
-fn main() {
+fn main() -> Result<()> {
     let cli = Cli::parse();
 
-    let env_contents = read_to_string(cli.env_contents_toml_file)
-        .expect("env contents should be a file")
-        .parse::<Table>()
-        .expect("env contents file should be a valid TOML table");
-
-    let (mut cargo_config_file, cargo_config_contents) =
-        create_or_get_file(&cli.cargo_config_toml_file);
+    if let Some(path) = cli.revert {
+        if cli.dry_run {
+            let backup = cargo_config::backup_path(&path);
+            let read = |path: &PathBuf| std::fs::read_to_string(path).unwrap_or_default();
+            if !backup.is_file() {
+                return Err(CargoConfigError::NoBackup(backup).into());
+            }
+            print!(
+                "{}",
+                cargo_config::unified_diff(&read(&path), &read(&backup), &path)
+            );
+        } else {
+            cargo_config::revert(&path)?;
+            eprintln!("Restored {} from its backup", path.display());
+        }
+        return Ok(());
+    }
 
-    let cargo_config_contents = cargo_config_contents.unwrap_or_default();
+    let env_path = cli
+        .env_contents_toml_file
+        .expect("clap should require the env contents file");
+    let config_path = cli
+        .cargo_config_toml_file
+        .expect("clap should require the cargo config file");
 
-    let mut cargo_config = cargo_config_contents
-        .parse::<Table>()
-        .expect("cargo config file should be a valid TOML table");
+    let (_, env_contents) = cargo_config::load(&env_path)?
+        .ok_or_else(|| anyhow::anyhow!("{} does not exist", env_path.display()))?;
 
-    let cargo_config_env = match cargo_config.entry("env") {
-        Entry::Vacant(entry) => {
-            eprintln!("Creating new `env` section");
-            entry
-                .insert(Value::Table(Table::new()))
-                .as_table_mut()
-                .expect("newly inserted table should be parsable as table")
+    // parse everything before anything is written, so that an invalid file is left untouched
+    let (old_contents, mut cargo_config) = match cargo_config::load(&config_path)? {
+        Some((contents, document)) => {
+            eprintln!(
+                "File at {} already exists and contains {} characters",
+                config_path.display(),
+                contents.len()
+            );
+            (Some(contents), document)
         }
-        Entry::Occupied(entry) => {
-            eprintln!("Modifying existing `env` section");
-            entry
-                .into_mut()
-                .as_table_mut()
-                .expect("entry env should be a table")
+        None => {
+            eprintln!("File at {} does not already exist", config_path.display());
+            (None, Document::new())
         }
     };
 
-    for (key, val) in env_contents {
-        let val = val.as_str().expect("value should be a string");
-        match cargo_config_env.entry(&key) {
-            Entry::Vacant(entry) => {
-                eprintln!("Creating new variable {key}");
-                entry.insert(Value::String(val.into()));
-            }
-            Entry::Occupied(mut entry) => {
-                eprintln!("Replacing existing variable {key}");
-                entry.insert(val.into());
-            }
+    if cli.remove {
+        for key in cargo_config::remove(&mut cargo_config, &env_contents) {
+            eprintln!("Removing {key}");
+        }
+    } else {
+        if cargo_config.contains_key("env") {
+            eprintln!("Modifying existing `env` section");
+        } else {
+            eprintln!("Creating new `env` section");
+        }
+        for key in cargo_config::apply(&mut cargo_config, &env_contents, &env_path, cli.arrays)? {
+            eprintln!("Setting {key}");
         }
     }
 
-    let new_cargo_config_content = cargo_config.to_string();
-    let new_length = new_cargo_config_content.len();
-    cargo_config_file
-        .write_all(new_cargo_config_content.as_bytes())
-        .expect("should be able to write to file");
+    let old_contents_or_empty = old_contents.as_deref().unwrap_or_default();
+    let new_contents = cargo_config.to_string();
+    if new_contents == old_contents_or_empty && old_contents.is_some() {
+        eprintln!("{} is already up to date", config_path.display());
+        return Ok(());
+    }
+    if cli.dry_run {
+        print!(
+            "{}",
+            cargo_config::unified_diff(old_contents_or_empty, &new_contents, &config_path)
+        );
+        return Ok(());
+    }
+
+    cargo_config::write_with_backup(&config_path, old_contents.as_deref(), &new_contents)?;
+    if old_contents.is_some() {
+        eprintln!(
+            "Backed up the previous contents to {}",
+            cargo_config::backup_path(&config_path).display()
+        );
+    }
     eprintln!(
-        "Wrote {new_length} characters to {}",
-        cli.cargo_config_toml_file
+        "Wrote {} characters to {}",
+        new_contents.len(),
+        config_path.display()
     );
+    Ok(())
 }
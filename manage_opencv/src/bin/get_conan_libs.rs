@@ -1,13 +1,35 @@
-use anyhow::Result;
-use clap::Parser;
-use std::fs::read_to_string;
+use anyhow::{bail, Result};
+use clap::{Parser, ValueEnum};
+use manage_opencv::{
+    build_script::{write_link_directives, write_link_groups},
+    discovery::DiscoveryArgs,
+    env_file::{write_variables, EnvFormat},
+    link_order::{resolve_link_order, LinkOrdering},
+    setup::list_value,
+};
+use std::io;
 
-/// Parse conanbuildinfo.txt and output TOML-compatible lists
+/// Find OpenCV with Conan, pkg-config, vcpkg or CMake and output TOML-compatible lists
 #[derive(Debug, Parser)]
 #[command(about)]
 struct Cli {
-    #[arg(help = "file of where to get the conan build information")]
-    conan_build_info_file: String,
+    #[command(flatten)]
+    discovery: DiscoveryArgs,
+    #[arg(
+        short = 'p',
+        long = "package",
+        help = "only link these packages and what they require, which needs the requirements of \
+                Conan 2 or pkg-config [default: all packages]"
+    )]
+    packages: Vec<String>,
+    #[arg(
+        short = 'x',
+        long = "exclude",
+        help = "do not link these packages, nor what only they require"
+    )]
+    exclude: Vec<String>,
+    #[arg(long, help = "print the tree of the linked packages to standard error")]
+    tree: bool,
     #[arg(short = 'i', help = "variable to write the include directory list to")]
     include_dirs_var: Option<String>,
     #[arg(long = "ai", help = "append these include directories")]
@@ -22,91 +44,148 @@ struct Cli {
     libs_append: Vec<String>,
     #[arg(short = 's', help = "include system libraries in libs_var")]
     include_system_libs: bool,
+    #[arg(
+        long,
+        help = "write `{ value = \"...\", force = true }`, so that the variables override the \
+                environment"
+    )]
+    force: bool,
+    #[arg(long, value_enum, default_value_t, help = "order of the libraries")]
+    link_order: LinkOrdering,
+    #[arg(
+        long,
+        help = "link libraries that require each other as linker groups, with --link-order \
+                symbols and --output build-script"
+    )]
+    link_groups: bool,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "what to write to standard output"
+    )]
+    output: OutputFormat,
 }
 
-struct SectionContents(Vec<String>);
-
-const INCLUDE_DIRS_HEADER: &str = "includedirs";
-const LIB_DIRS_HEADER: &str = "libdirs";
-const LIBS_HEADER: &str = "libs";
-const SYSTEM_LIBS_HEADER: &str = "system_libs";
+/// How the link information is written to standard output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// `VARIABLE = "a,b,c"` lines for the `env` section of a Cargo configuration
+    #[default]
+    Toml,
+    /// `cargo:rustc-link-search` and `cargo:rustc-link-lib` lines for a build script
+    BuildScript,
+    /// `export VARIABLE='a,b,c'` lines for a shell
+    Shell,
+    /// `VARIABLE="a,b,c"` lines of a `.env` file
+    Dotenv,
+    /// A JSON object of the variables
+    Json,
+    /// A direnv `.envrc` that exports the variables
+    Direnv,
+    /// `VARIABLE=a,b,c` lines for the `GITHUB_ENV` file of GitHub Actions
+    GithubEnv,
+}
 
-impl SectionContents {
-    fn find(header: &str, haystack: &str) -> Option<Self> {
-        haystack
-            .split_once(&format!("[{header}]\n"))
-            .map(|(_, after)| {
-                let contents = after
-                    .lines()
-                    .take_while(|&line| !line.trim().is_empty() && !line.starts_with('['))
-                    .map(std::string::ToString::to_string)
-                    .collect();
-                Self(contents)
-            })
+impl OutputFormat {
+    fn env_format(self) -> Option<EnvFormat> {
+        match self {
+            Self::Toml | Self::BuildScript => None,
+            Self::Shell => Some(EnvFormat::Shell),
+            Self::Dotenv => Some(EnvFormat::Dotenv),
+            Self::Json => Some(EnvFormat::Json),
+            Self::Direnv => Some(EnvFormat::Direnv),
+            Self::GithubEnv => Some(EnvFormat::GithubEnv),
+        }
     }
 }
 
-fn print_variable<'a, S, A, Ap>(env: &str, sections: S, append: A) -> usize
-where
-    S: Iterator<Item = &'a SectionContents>,
-    A: Iterator<Item = &'a Ap>,
-    Ap: AsRef<str> + 'a,
-{
-    let contents: Vec<_> = sections
-        .flat_map(|s| s.0.iter())
-        .map(String::as_str)
-        .chain(append.map(AsRef::as_ref))
-        .collect();
-    let number_of_items = contents.len();
-    let contents = contents.join(",");
-    println!("{env} = \"{contents}\"");
-    number_of_items
+fn print_variable(env: &str, values: &[String], force: bool) -> usize {
+    println!("{env} = {}", list_value(values, force));
+    values.len()
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
-    eprintln!("Parsing {}", cli.conan_build_info_file);
-    let contents = read_to_string(cli.conan_build_info_file).expect("should be able to read file");
-    let contents = {
-        contents
-            .split_once("\n[USER_")
-            .expect("should have a section starting with `\\n[USER_` to delimit interesting info")
-            .0
-    };
+    let (backend, dependencies) = cli.discovery.discover()?;
+    eprintln!("Found OpenCV with the {backend:?} backend");
+    let dependencies = dependencies.select(&cli.packages, &cli.exclude)?;
+    if cli.tree {
+        let mut tree = String::new();
+        dependencies
+            .write_tree(&mut tree)
+            .expect("writing to a string should not fail");
+        eprint!("{tree}");
+    }
 
-    let n_include_dirs = if let Some(var) = cli.include_dirs_var {
-        let section = SectionContents::find(INCLUDE_DIRS_HEADER, contents)
-            .unwrap_or_else(|| panic!("contents should include {INCLUDE_DIRS_HEADER} section"));
-        print_variable(
-            &var,
-            std::iter::once(&section),
-            cli.include_dirs_append.iter(),
-        )
-    } else {
-        0
-    };
+    let mut info = dependencies.build_info();
+    info.include_dirs.extend(cli.include_dirs_append);
+    info.lib_dirs.extend(cli.lib_dirs_append);
+    if cli.include_system_libs {
+        info.libs.append(&mut info.system_libs);
+    }
+    info.system_libs.clear();
+    info.libs.extend(cli.libs_append);
 
-    let n_lib_dirs = if let Some(var) = cli.lib_dirs_var {
-        let section = SectionContents::find(LIB_DIRS_HEADER, contents)
-            .unwrap_or_else(|| panic!("contents should include {LIB_DIRS_HEADER} section"));
-        print_variable(&var, std::iter::once(&section), cli.lib_dirs_append.iter())
-    } else {
-        0
+    if cli.link_groups
+        && (cli.link_order != LinkOrdering::Symbols || cli.output != OutputFormat::BuildScript)
+    {
+        bail!("--link-groups needs --link-order symbols and --output build-script");
+    }
+    if cli.force && cli.output != OutputFormat::Toml {
+        bail!("--force only applies to --output toml");
+    }
+    let order = match cli.link_order {
+        LinkOrdering::Keep => None,
+        LinkOrdering::Symbols => {
+            let order = resolve_link_order(&info.libs, &info.lib_dirs)?;
+            for cycle in order.cycles() {
+                eprintln!("Libraries that require each other: {}", cycle.join(", "));
+            }
+            info.libs = order.libs(!cli.link_groups);
+            Some(order)
+        }
     };
 
-    let n_libs = if let Some(var) = cli.libs_var {
-        let section = SectionContents::find(LIBS_HEADER, contents)
-            .unwrap_or_else(|| panic!("contents should include {LIBS_HEADER} section"));
-        let mut sections = vec![section];
-        if cli.include_system_libs {
-            let system = SectionContents::find(SYSTEM_LIBS_HEADER, contents)
-                .unwrap_or_else(|| panic!("contents should include {SYSTEM_LIBS_HEADER} section"));
-            sections.push(system);
+    let (n_include_dirs, n_lib_dirs, n_libs) = match cli.output {
+        OutputFormat::Toml => {
+            let print = |var: Option<String>, values: &[String]| {
+                var.map_or(0, |var| print_variable(&var, values, cli.force))
+            };
+            (
+                print(cli.include_dirs_var, &info.include_dirs),
+                print(cli.lib_dirs_var, &info.lib_dirs),
+                print(cli.libs_var, &info.libs),
+            )
+        }
+        OutputFormat::BuildScript => {
+            if let Some(path) = cli.discovery.watched_path() {
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+            match order.filter(|_| cli.link_groups) {
+                Some(order) => write_link_groups(&info.lib_dirs, &order, &mut io::stdout().lock())?,
+                None => write_link_directives(&info, &mut io::stdout().lock())?,
+            }
+            (0, info.lib_dirs.len(), info.libs.len())
+        }
+        format => {
+            let format = format.env_format().expect("format should be an env format");
+            let lists = [
+                (cli.include_dirs_var, &info.include_dirs),
+                (cli.lib_dirs_var, &info.lib_dirs),
+                (cli.libs_var, &info.libs),
+            ];
+            let count = |index: usize| lists[index].0.as_ref().map_or(0, |_| lists[index].1.len());
+            let counts = (count(0), count(1), count(2));
+            let variables: Vec<_> = lists
+                .into_iter()
+                .filter_map(|(var, values)| var.map(|var| (var, values.join(","))))
+                .collect();
+            let watched = cli.discovery.watched_path();
+            write_variables(format, &variables, watched, &mut io::stdout().lock())?;
+            counts
         }
-        print_variable(&var, sections.iter(), cli.libs_append.iter())
-    } else {
-        0
     };
 
     eprintln!(
@@ -0,0 +1,192 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use manage_opencv::{
+    conanfile::ConanfileFormat,
+    doctor::{diagnose, Finding, LinkConfig, Severity},
+    modules::{conanfile_options, reconcile, workspace_modules, InstalledModules},
+    setup::{self, ProjectConfig, PROJECT_CONFIG_FILE},
+};
+use std::{fs, path::PathBuf, process::ExitCode};
+
+/// Install OpenCV with Conan and link the `opencv` crate to it, configured by manage_opencv.toml
+#[derive(Debug, Parser)]
+#[command(about)]
+struct Cli {
+    #[arg(
+        long,
+        global = true,
+        help = "project configuration [default: manage_opencv.toml in the current directory or \
+                its parents]"
+    )]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run conanfile if manage_opencv.toml has a [conanfile] section, then install, envs, apply
+    /// and doctor
+    Setup {
+        /// Arguments for `conan install`, after `--`
+        #[arg(last = true)]
+        conan_args: Vec<String>,
+    },
+    /// Write conanfile.txt or conanfile.py from the [conanfile] section and the opencv features
+    /// of the workspace
+    Conanfile {
+        /// Print a unified diff of the changes instead of writing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Install the Conan dependencies into the build directory
+    Install {
+        /// Arguments for `conan install`, after `--`
+        #[arg(last = true)]
+        conan_args: Vec<String>,
+    },
+    /// Find OpenCV and write the OPENCV_* variables to the build directory
+    Envs,
+    /// Write the variables to the env section of the Cargo configuration
+    Apply {
+        /// Print a unified diff of the changes instead of writing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check the OpenCV link configuration and suggest fixes
+    Doctor {
+        /// Only print warnings and errors
+        #[arg(short, long)]
+        quiet: bool,
+    },
+    /// Compare the installed OpenCV modules with the opencv features of the workspace
+    Modules {
+        /// Only print warnings and errors
+        #[arg(short, long)]
+        quiet: bool,
+    },
+    /// Remove the build directory
+    Clean {
+        /// Also remove the applied variables from the Cargo configuration
+        #[arg(long)]
+        unapply: bool,
+    },
+}
+
+fn conanfile(config: &ProjectConfig, dry_run: bool) -> Result<()> {
+    let (path, diff) = setup::generate_conanfile(config, dry_run)?;
+    if dry_run {
+        print!("{diff}");
+    } else if diff.is_empty() {
+        eprintln!("{} is already up to date", path.display());
+    } else {
+        eprintln!("Wrote {}", path.display());
+    }
+    // Conan takes conanfile.py over conanfile.txt
+    let format = config.conanfile.clone().unwrap_or_default().format;
+    let other = match format {
+        ConanfileFormat::Txt => ConanfileFormat::Py,
+        ConanfileFormat::Py => ConanfileFormat::Txt,
+    };
+    let other = config.root.join(other.file_name());
+    if other.is_file() {
+        eprintln!("Warning: {} exists as well, remove it", other.display());
+    }
+    Ok(())
+}
+
+fn envs(config: &ProjectConfig) -> Result<()> {
+    let backend = setup::write_envs(config)?;
+    eprintln!(
+        "Found OpenCV with the {backend:?} backend and wrote {}",
+        config.envs_file().display()
+    );
+    Ok(())
+}
+
+fn apply(config: &ProjectConfig, dry_run: bool) -> Result<()> {
+    let diff = setup::apply(config, dry_run)?;
+    if dry_run {
+        print!("{diff}");
+    } else if diff.is_empty() {
+        eprintln!("{} is already up to date", config.cargo_config.display());
+    } else {
+        eprintln!("Applied the variables to {}", config.cargo_config.display());
+    }
+    Ok(())
+}
+
+fn doctor(config: &ProjectConfig, quiet: bool) -> Result<ExitCode> {
+    report(&diagnose(&LinkConfig::load(&config.cargo_config)?), quiet)
+}
+
+fn modules(config: &ProjectConfig, quiet: bool) -> Result<ExitCode> {
+    let uses = workspace_modules(&config.root)?;
+    let installed = InstalledModules::find(&LinkConfig::load(&config.cargo_config)?);
+    // a conanfile written by hand may be either
+    let formats = match &config.conanfile {
+        Some(spec) => vec![spec.format],
+        None => vec![ConanfileFormat::Txt, ConanfileFormat::Py],
+    };
+    let options = formats
+        .into_iter()
+        .find_map(|format| {
+            let contents = fs::read_to_string(config.root.join(format.file_name())).ok()?;
+            Some(conanfile_options(&contents, format))
+        })
+        .unwrap_or_default();
+    report(&reconcile(&uses, &installed, &options), quiet)
+}
+
+fn report(findings: &[Finding], quiet: bool) -> Result<ExitCode> {
+    for finding in findings {
+        if !quiet || finding.severity > Severity::Ok {
+            println!("{finding}");
+        }
+    }
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    eprintln!("Found {errors} errors.");
+    Ok(if errors > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+fn main() -> Result<ExitCode> {
+    let cli = Cli::parse();
+
+    let config = match &cli.config {
+        Some(path) => ProjectConfig::load(path)?,
+        None => ProjectConfig::find(&setup::current_directory()?)?,
+    };
+    if cli.config.is_none() && !config.root.join(PROJECT_CONFIG_FILE).is_file() {
+        eprintln!("No {PROJECT_CONFIG_FILE} found, using the defaults");
+    }
+
+    match cli.command {
+        Command::Setup { conan_args } => {
+            if config.conanfile.is_some() {
+                conanfile(&config, false)?;
+            }
+            setup::install(&config, &conan_args)?;
+            envs(&config)?;
+            apply(&config, false)?;
+            return doctor(&config, true);
+        }
+        Command::Conanfile { dry_run } => conanfile(&config, dry_run)?,
+        Command::Install { conan_args } => setup::install(&config, &conan_args)?,
+        Command::Envs => envs(&config)?,
+        Command::Apply { dry_run } => apply(&config, dry_run)?,
+        Command::Doctor { quiet } => return doctor(&config, quiet),
+        Command::Modules { quiet } => return modules(&config, quiet),
+        Command::Clean { unapply } => {
+            setup::clean(&config, unapply)?;
+            eprintln!("Removed {}", config.build_directory.display());
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,40 @@
+use anyhow::Result;
+use clap::Parser;
+use manage_opencv::doctor::{diagnose, LinkConfig, Severity};
+use std::{path::PathBuf, process::ExitCode};
+
+/// Check the OpenCV link configuration of the `opencv` crate and suggest fixes
+#[derive(Debug, Parser)]
+#[command(about)]
+struct Cli {
+    #[arg(
+        long,
+        default_value = ".cargo/config.toml",
+        help = "Cargo configuration to read the OPENCV_* variables from, unless they are set in \
+                the environment"
+    )]
+    cargo_config: PathBuf,
+    #[arg(short, long, help = "only print warnings and errors")]
+    quiet: bool,
+}
+
+fn main() -> Result<ExitCode> {
+    let cli = Cli::parse();
+
+    let config = LinkConfig::load(&cli.cargo_config)?;
+    let findings = diagnose(&config);
+    for finding in &findings {
+        if !cli.quiet || finding.severity > Severity::Ok {
+            println!("{finding}");
+        }
+    }
+
+    let count = |severity| findings.iter().filter(|f| f.severity == severity).count();
+    let (warnings, errors) = (count(Severity::Warning), count(Severity::Error));
+    eprintln!("Found {errors} errors and {warnings} warnings.");
+    Ok(if errors > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
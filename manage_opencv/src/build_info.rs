@@ -0,0 +1,343 @@
+use std::fmt;
+
+/// What the `opencv` crate needs to know to link to an OpenCV installation, independent of where
+/// it was found.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildInfo {
+    pub include_dirs: Vec<String>,
+    pub lib_dirs: Vec<String>,
+    /// Libraries in link order, without `lib` prefix or file extension.
+    pub libs: Vec<String>,
+    /// Libraries provided by the operating system, e.g. `pthread` or `dl`.
+    pub system_libs: Vec<String>,
+}
+
+impl BuildInfo {
+    /// Appends the contents of `other`, skipping entries that are already present.
+    pub fn merge(&mut self, other: Self) {
+        extend_unique(&mut self.include_dirs, other.include_dirs);
+        extend_unique(&mut self.lib_dirs, other.lib_dirs);
+        extend_unique(&mut self.libs, other.libs);
+        extend_unique(&mut self.system_libs, other.system_libs);
+    }
+}
+
+/// Appends the items of `new` that are not already in `items`, keeping the order.
+pub fn extend_unique(items: &mut Vec<String>, new: impl IntoIterator<Item = String>) {
+    for item in new {
+        if !items.contains(&item) {
+            items.push(item);
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SelectionError {
+    #[error("there is no package `{name}`, the packages are: {available}")]
+    UnknownPackage { name: String, available: String },
+    #[error(
+        "cannot link only {names} and what they require, as the build information does not \
+         record which packages require which; use Conan 2's graph JSON or pkg-config, or \
+         exclude the other packages instead"
+    )]
+    UnknownRequirements { names: String },
+}
+
+/// A package of the dependency graph, e.g. `opencv` or `zlib`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Package {
+    pub name: String,
+    pub version: Option<String>,
+    /// Names of the packages this package directly requires.
+    pub requires: Vec<String>,
+    pub info: BuildInfo,
+}
+
+impl Package {
+    /// `name/version`, as Conan writes references.
+    #[must_use]
+    pub fn reference(&self) -> String {
+        match &self.version {
+            Some(version) => format!("{}/{version}", self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// The packages of a dependency graph, with every package before the packages it requires, as
+/// the linker needs them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dependencies {
+    pub packages: Vec<Package>,
+    /// Whether the `requires` of the packages are known. Conan 1's `conanbuildinfo.txt` does not
+    /// record them.
+    pub has_requirements: bool,
+}
+
+impl Dependencies {
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Package> {
+        self.packages.iter().find(|package| package.name == name)
+    }
+
+    /// The combined build information of all packages, in link order.
+    #[must_use]
+    pub fn build_info(&self) -> BuildInfo {
+        let mut info = BuildInfo::default();
+        for package in &self.packages {
+            info.merge(package.info.clone());
+        }
+        info
+    }
+
+    /// Reorders the packages so that every package comes before the packages it requires, keeping
+    /// the order otherwise.
+    #[must_use]
+    pub fn into_link_order(self) -> Self {
+        fn visit<'a>(
+            package: &'a Package,
+            packages: &'a [Package],
+            visited: &mut Vec<&'a str>,
+            order: &mut Vec<&'a Package>,
+        ) {
+            if visited.contains(&package.name.as_str()) {
+                return;
+            }
+            visited.push(&package.name);
+            // in reverse, so that the requirements keep their order after the final reversal
+            for requirement in package.requires.iter().rev() {
+                if let Some(requirement) = packages.iter().find(|p| &p.name == requirement) {
+                    visit(requirement, packages, visited, order);
+                }
+            }
+            order.push(package);
+        }
+
+        let mut order = Vec::new();
+        let mut visited = Vec::new();
+        for package in self.packages.iter().rev() {
+            visit(package, &self.packages, &mut visited, &mut order);
+        }
+        let packages = order.into_iter().rev().cloned().collect();
+        Self {
+            packages,
+            has_requirements: self.has_requirements,
+        }
+    }
+
+    /// Packages that no other package requires.
+    #[must_use]
+    pub fn roots(&self) -> Vec<&Package> {
+        self.packages
+            .iter()
+            .filter(|package| {
+                !self
+                    .packages
+                    .iter()
+                    .any(|other| other.requires.contains(&package.name))
+            })
+            .collect()
+    }
+
+    /// Keeps `include` and everything they require, or all packages if `include` is empty, except
+    /// the packages in `exclude` and what is only required through them.
+    ///
+    /// # Errors
+    ///
+    /// * `SelectionError::UnknownPackage` if a package in `include` or `exclude` does not exist.
+    /// * `SelectionError::UnknownRequirements` if `include` is not empty but the requirements of
+    ///   the packages are not known.
+    pub fn select(&self, include: &[String], exclude: &[String]) -> Result<Self, SelectionError> {
+        for name in include.iter().chain(exclude) {
+            if self.get(name).is_none() {
+                return Err(SelectionError::UnknownPackage {
+                    name: name.clone(),
+                    available: self
+                        .packages
+                        .iter()
+                        .map(|package| package.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                });
+            }
+        }
+        if !include.is_empty() && !self.has_requirements {
+            return Err(SelectionError::UnknownRequirements {
+                names: include
+                    .iter()
+                    .map(|name| format!("`{name}`"))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            });
+        }
+
+        let mut selected: Vec<&str> = if include.is_empty() {
+            self.roots()
+                .iter()
+                .map(|package| package.name.as_str())
+                .collect()
+        } else {
+            include.iter().map(String::as_str).collect()
+        };
+        selected.retain(|name| !exclude.iter().any(|excluded| excluded == name));
+        let mut index = 0;
+        while index < selected.len() {
+            let name = selected[index];
+            let package = self.get(name).expect("selected packages should exist");
+            for requirement in &package.requires {
+                let excluded = exclude.contains(requirement);
+                if !excluded && !selected.contains(&requirement.as_str()) {
+                    selected.push(requirement);
+                }
+            }
+            index += 1;
+        }
+
+        Ok(Self {
+            packages: self
+                .packages
+                .iter()
+                .filter(|package| selected.contains(&package.name.as_str()))
+                .cloned()
+                .collect(),
+            has_requirements: self.has_requirements,
+        })
+    }
+
+    /// Writes the dependency tree below the roots, marking packages that were already shown with
+    /// `(*)`.
+    ///
+    /// # Errors
+    ///
+    /// * `fmt::Error` if `out` fails.
+    pub fn write_tree(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        let mut shown = Vec::new();
+        for root in self.roots() {
+            self.write_subtree(root, "", &mut shown, out)?;
+        }
+        Ok(())
+    }
+
+    fn write_subtree<'a>(
+        &'a self,
+        package: &'a Package,
+        indent: &str,
+        shown: &mut Vec<&'a str>,
+        out: &mut impl fmt::Write,
+    ) -> fmt::Result {
+        let repeated = shown.contains(&package.name.as_str());
+        writeln!(
+            out,
+            "{}{}",
+            package.reference(),
+            if repeated { " (*)" } else { "" }
+        )?;
+        if repeated {
+            return Ok(());
+        }
+        shown.push(&package.name);
+
+        let requirements: Vec<_> = package
+            .requires
+            .iter()
+            .filter_map(|name| self.get(name))
+            .collect();
+        for (index, requirement) in requirements.iter().enumerate() {
+            let last = index + 1 == requirements.len();
+            write!(out, "{indent}{}", if last { "└── " } else { "├── " })?;
+            let indent = format!("{indent}{}", if last { "    " } else { "│   " });
+            self.write_subtree(requirement, &indent, shown, out)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, requires: &[&str]) -> Package {
+        Package {
+            name: name.into(),
+            requires: requires.iter().map(ToString::to_string).collect(),
+            info: BuildInfo {
+                libs: vec![name.into()],
+                ..BuildInfo::default()
+            },
+            ..Package::default()
+        }
+    }
+
+    /// The packages of OpenCV with FFmpeg, and without their requirements unless
+    /// `has_requirements`, as Conan 1 reads them.
+    fn dependencies(has_requirements: bool) -> Dependencies {
+        let requires = |requires| if has_requirements { requires } else { &[][..] };
+        Dependencies {
+            packages: vec![
+                package("opencv", requires(&["ffmpeg", "zlib"])),
+                package("ffmpeg", requires(&["openssl", "zlib"])),
+                package("openssl", &[]),
+                package("zlib", &[]),
+            ],
+            has_requirements,
+        }
+    }
+
+    fn names(dependencies: &Dependencies) -> Vec<&str> {
+        dependencies
+            .packages
+            .iter()
+            .map(|package| package.name.as_str())
+            .collect()
+    }
+
+    fn strings(strings: &[&str]) -> Vec<String> {
+        strings.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn select_follows_requirements() {
+        let all = dependencies(true);
+
+        let selected = all.select(&[], &[]).expect("should select all");
+        assert_eq!(names(&selected), ["opencv", "ffmpeg", "openssl", "zlib"]);
+        let selected = all
+            .select(&strings(&["ffmpeg"]), &[])
+            .expect("should select");
+        assert_eq!(names(&selected), ["ffmpeg", "openssl", "zlib"]);
+        // zlib is still required by opencv
+        let selected = all
+            .select(&[], &strings(&["ffmpeg"]))
+            .expect("should select");
+        assert_eq!(names(&selected), ["opencv", "zlib"]);
+        assert!(matches!(
+            all.select(&strings(&["opencv5"]), &[]),
+            Err(SelectionError::UnknownPackage { name, .. }) if name == "opencv5"
+        ));
+    }
+
+    #[test]
+    fn select_needs_requirements_to_include() {
+        let all = dependencies(false);
+
+        assert_eq!(
+            all.select(&[], &[]).expect("should select all").packages,
+            all.packages
+        );
+        let selected = all
+            .select(&[], &strings(&["ffmpeg"]))
+            .expect("should exclude");
+        assert_eq!(names(&selected), ["opencv", "openssl", "zlib"]);
+        match all.select(&strings(&["opencv", "zlib"]), &[]) {
+            Err(SelectionError::UnknownRequirements { names }) => {
+                assert_eq!(names, "`opencv`, `zlib`");
+            }
+            other => panic!("expected unknown requirements, got {other:?}"),
+        }
+        // unknown names are reported first
+        assert!(matches!(
+            all.select(&strings(&["opencv5"]), &[]),
+            Err(SelectionError::UnknownPackage { .. })
+        ));
+    }
+}
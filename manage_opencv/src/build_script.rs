@@ -0,0 +1,148 @@
+use crate::{
+    build_info::{BuildInfo, SelectionError},
+    conan::{self, BuildInfoFormat, ConanError},
+    link_order::LinkOrder,
+};
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum LinkError {
+    #[error(transparent)]
+    Conan(#[from] ConanError),
+    #[error(transparent)]
+    Selection(#[from] SelectionError),
+    #[error("could not write link directives: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Writes the lines that make Cargo link the libraries of `info`, as a build script prints them.
+///
+/// # Errors
+///
+/// * `io::Error` if `out` fails.
+pub fn write_link_directives(info: &BuildInfo, out: &mut impl Write) -> io::Result<()> {
+    for dir in &info.lib_dirs {
+        writeln!(out, "cargo:rustc-link-search=native={dir}")?;
+    }
+    for lib in info.libs.iter().chain(&info.system_libs) {
+        writeln!(out, "cargo:rustc-link-lib={lib}")?;
+    }
+    Ok(())
+}
+
+/// Writes the lines that make Cargo pass the libraries of `order` to the linker as arguments, with
+/// every cycle between `--start-group` and `--end-group`. Cargo keeps link arguments in order
+/// with each other, but not with the libraries of `cargo:rustc-link-lib`, so all libraries are
+/// passed this way. Link arguments only apply to the binaries, tests and examples of the crate.
+///
+/// # Errors
+///
+/// * `io::Error` if `out` fails.
+pub fn write_link_groups(
+    lib_dirs: &[String],
+    order: &LinkOrder,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    for dir in lib_dirs {
+        writeln!(out, "cargo:rustc-link-search=native={dir}")?;
+    }
+    for group in &order.groups {
+        let cycle = group.len() > 1;
+        if cycle {
+            writeln!(out, "cargo:rustc-link-arg=-Wl,--start-group")?;
+        }
+        for lib in group {
+            writeln!(out, "cargo:rustc-link-arg=-l{lib}")?;
+        }
+        if cycle {
+            writeln!(out, "cargo:rustc-link-arg=-Wl,--end-group")?;
+        }
+    }
+    Ok(())
+}
+
+/// Links `packages` and what they require, or every package if it is empty, from the Conan output
+/// at `path` into the crate whose build script calls this, and reruns the build script when the
+/// output changes. Returns the build information, e.g. for the include directories.
+///
+/// # Errors
+///
+/// * `LinkError::Conan` if the Conan output cannot be read.
+/// * `LinkError::Selection` if one of `packages` does not exist, or if `packages` is not empty
+///   and the Conan output does not record the requirements, as `conanbuildinfo.txt` does not.
+/// * `LinkError::Io` if the directives cannot be written.
+pub fn link_conan_packages(
+    path: impl AsRef<Path>,
+    packages: &[String],
+) -> Result<BuildInfo, LinkError> {
+    let path = path.as_ref();
+    println!("cargo:rerun-if-changed={}", path.display());
+    let info = conan::load(path, BuildInfoFormat::Auto)?
+        .select(packages, &[])?
+        .build_info();
+    write_link_directives(&info, &mut io::stdout().lock())?;
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(strings: &[&str]) -> Vec<String> {
+        strings.iter().map(ToString::to_string).collect()
+    }
+
+    fn written(write: impl FnOnce(&mut Vec<u8>) -> io::Result<()>) -> String {
+        let mut out = Vec::new();
+        write(&mut out).expect("writing to a Vec should succeed");
+        String::from_utf8(out).expect("directives should be UTF-8")
+    }
+
+    #[test]
+    fn link_directives_search_then_link() {
+        let info = BuildInfo {
+            include_dirs: strings(&["/opt/include"]),
+            lib_dirs: strings(&["/opt/lib", "/opt/lib64"]),
+            libs: strings(&["opencv_imgproc", "opencv_core"]),
+            system_libs: strings(&["dl"]),
+        };
+
+        assert_eq!(
+            written(|out| write_link_directives(&info, out)),
+            "cargo:rustc-link-search=native=/opt/lib\n\
+             cargo:rustc-link-search=native=/opt/lib64\n\
+             cargo:rustc-link-lib=opencv_imgproc\n\
+             cargo:rustc-link-lib=opencv_core\n\
+             cargo:rustc-link-lib=dl\n"
+        );
+    }
+
+    #[test]
+    fn link_groups_wrap_only_cycles() {
+        let order = LinkOrder {
+            groups: vec![
+                strings(&["opencv_imgproc"]),
+                strings(&["opencv_core", "tbb"]),
+                strings(&["z"]),
+            ],
+        };
+
+        assert_eq!(
+            written(|out| write_link_groups(&strings(&["/opt/lib"]), &order, out)),
+            "cargo:rustc-link-search=native=/opt/lib\n\
+             cargo:rustc-link-arg=-lopencv_imgproc\n\
+             cargo:rustc-link-arg=-Wl,--start-group\n\
+             cargo:rustc-link-arg=-lopencv_core\n\
+             cargo:rustc-link-arg=-ltbb\n\
+             cargo:rustc-link-arg=-Wl,--end-group\n\
+             cargo:rustc-link-arg=-lz\n"
+        );
+        assert_eq!(
+            written(|out| write_link_groups(&[], &LinkOrder::default(), out)),
+            ""
+        );
+    }
+}
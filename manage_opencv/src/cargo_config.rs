@@ -0,0 +1,541 @@
+use similar::TextDiff;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use tempfile::NamedTempFile;
+use toml_edit::{table, Document, InlineTable, Item, Table, TableLike, Value};
+
+/// Keys of the table form of an `env` entry, besides `value`.
+const ENV_FLAGS: [&str; 2] = ["force", "relative"];
+
+/// Appended to the file name of the configuration for the copy made before it is changed.
+const BACKUP_EXTENSION: &str = "bak";
+
+#[derive(Debug, thiserror::Error)]
+pub enum CargoConfigError {
+    #[error("could not read {path}: {source}")]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("could not write {path}: {source}")]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("{path} is not valid TOML: {source}")]
+    Invalid {
+        path: PathBuf,
+        #[source]
+        source: toml_edit::TomlError,
+    },
+    #[error("`env.{key}` in {path} {reason}")]
+    InvalidEnvValue {
+        path: PathBuf,
+        key: String,
+        reason: &'static str,
+    },
+    #[error("`{0}` should be a table")]
+    NotATable(String),
+    #[error("there is no backup {0} to revert to")]
+    NoBackup(PathBuf),
+}
+
+/// Reads and parses the TOML file at `path`, or returns `None` if it does not exist.
+///
+/// # Errors
+///
+/// * `CargoConfigError::Read` if the file exists but cannot be read.
+/// * `CargoConfigError::Invalid` if it is not TOML.
+pub fn load(path: &Path) -> Result<Option<(String, Document)>, CargoConfigError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(source) => {
+            let path = path.into();
+            return Err(CargoConfigError::Read { path, source });
+        }
+    };
+    let document = contents
+        .parse::<Document>()
+        .map_err(|source| CargoConfigError::Invalid {
+            path: path.into(),
+            source,
+        })?;
+    Ok(Some((contents, document)))
+}
+
+/// The `env` section of `config`, which is created if it is missing.
+///
+/// # Errors
+///
+/// * `CargoConfigError::NotATable` if `env` is something else.
+pub fn env_section(config: &mut Document) -> Result<&mut dyn TableLike, CargoConfigError> {
+    config
+        .entry("env")
+        .or_insert_with(table)
+        .as_table_like_mut()
+        .ok_or_else(|| CargoConfigError::NotATable("env".into()))
+}
+
+/// How arrays of the input are combined with arrays that already exist.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ArrayMerge {
+    /// Append the items that are not in the array yet
+    #[default]
+    Append,
+    /// Replace the array
+    Replace,
+}
+
+/// Applies `input` to `config`, keeping the comments and formatting of what already exists, and
+/// returns the dotted keys that changed.
+///
+/// Top-level keys of `input` and the keys of its `[env]` section are environment variables,
+/// either strings or tables with `value`, `force` and `relative`. Numbers and booleans are
+/// written as strings, as Cargo only passes strings. All other sections, such as `[build]` or
+/// `[target.x86_64-unknown-linux-gnu]`, are merged key by key.
+///
+/// # Errors
+///
+/// * `CargoConfigError::InvalidEnvValue` if an environment variable has another form.
+/// * `CargoConfigError::NotATable` if a section of `config` is not a table.
+pub fn apply(
+    config: &mut Document,
+    input: &Document,
+    input_path: &Path,
+    arrays: ArrayMerge,
+) -> Result<Vec<String>, CargoConfigError> {
+    let mut changed = Vec::new();
+    for (key, variable, item) in env_entries(input) {
+        let new = env_value(item).map_err(|reason| CargoConfigError::InvalidEnvValue {
+            path: input_path.into(),
+            key: variable.into(),
+            reason,
+        })?;
+        if set_value(env_section(config)?, variable, new) {
+            changed.push(key);
+        }
+    }
+
+    for (key, item) in input.iter() {
+        // inline tables are environment variables, only `[section]` headers are sections
+        if key == "env" || !item.is_table() {
+            continue;
+        }
+        let target = config.entry(key).or_insert(Item::None);
+        merge(target, item, key, arrays, &mut changed)?;
+    }
+    Ok(changed)
+}
+
+/// Removes what `input` would apply from `config`: environment variables by name, the items of
+/// arrays, and other keys by name, and then the sections and arrays that this left empty. Those
+/// that were empty before are kept. Returns the dotted keys that were removed.
+pub fn remove(config: &mut Document, input: &Document) -> Vec<String> {
+    let mut removed = Vec::new();
+    let filled: Vec<_> = config
+        .iter()
+        .filter(|(_, item)| item.as_table_like().is_some_and(|table| !table.is_empty()))
+        .map(|(key, _)| key.to_string())
+        .collect();
+    if let Some(section) = config.get_mut("env").and_then(Item::as_table_like_mut) {
+        for (key, variable, _) in env_entries(input) {
+            if section.remove(variable).is_some() {
+                removed.push(key);
+            }
+        }
+    }
+    for (key, item) in input.iter() {
+        // inline tables are environment variables, only `[section]` headers are sections
+        if key == "env" || !item.is_table() {
+            continue;
+        }
+        if let Some(target) = config.get_mut(key) {
+            unmerge(target, item, key, &mut removed);
+        }
+    }
+    for key in filled {
+        let emptied = config
+            .get(&key)
+            .and_then(Item::as_table_like)
+            .is_some_and(TableLike::is_empty);
+        if emptied {
+            config.remove(&key);
+        }
+    }
+    removed
+}
+
+/// The environment variables of `input` as the dotted key, the variable name and its item.
+fn env_entries(input: &Document) -> Vec<(String, &str, &Item)> {
+    let top_level = input.iter().filter(|(_, item)| item.is_value());
+    let section = input
+        .get("env")
+        .and_then(Item::as_table_like)
+        .into_iter()
+        .flat_map(TableLike::iter);
+    top_level
+        .chain(section)
+        .map(|(variable, item)| (format!("env.{variable}"), variable, item))
+        .collect()
+}
+
+/// The value that Cargo accepts for an `env` entry, or why `item` is not one.
+fn env_value(item: &Item) -> Result<Value, &'static str> {
+    let Some(fields) = item.as_table_like() else {
+        return scalar_string(item.as_value().ok_or("should be a value")?);
+    };
+    let mut entry = InlineTable::new();
+    for (key, field) in fields.iter() {
+        let field = field.as_value().ok_or("should only contain values")?;
+        if key == "value" {
+            entry.insert(key, scalar_string(field)?);
+        } else if ENV_FLAGS.contains(&key) {
+            let flag = field
+                .as_bool()
+                .ok_or("should have booleans for `force` and `relative`")?;
+            entry.insert(key, flag.into());
+        } else {
+            return Err("should only have `value`, `force` and `relative`");
+        }
+    }
+    if !entry.contains_key("value") {
+        return Err("should have a `value`");
+    }
+    if entry.len() == 1 {
+        // `{ value = "x" }` means the same as `"x"`
+        return Ok(entry.remove("value").expect("value should exist"));
+    }
+    entry.fmt();
+    Ok(Value::InlineTable(entry))
+}
+
+fn scalar_string(value: &Value) -> Result<Value, &'static str> {
+    let text = match value {
+        Value::String(string) => string.value().clone(),
+        Value::Integer(integer) => integer.value().to_string(),
+        Value::Float(float) => float.value().to_string(),
+        Value::Boolean(boolean) => boolean.value().to_string(),
+        _ => return Err("should be a string, number, boolean or table"),
+    };
+    Ok(text.into())
+}
+
+/// `value` without formatting, to compare values regardless of how they are written.
+fn canonical(value: &Value) -> String {
+    let mut value = value.clone();
+    value.decor_mut().clear();
+    match &mut value {
+        Value::Array(array) => array.fmt(),
+        Value::InlineTable(table) => table.fmt(),
+        _ => {}
+    }
+    value.to_string()
+}
+
+/// Sets `key` of `table` to `new`, keeping the comments around an existing value, and returns
+/// whether it changed.
+fn set_value(table: &mut dyn TableLike, key: &str, mut new: Value) -> bool {
+    match table.get_mut(key) {
+        Some(existing) => {
+            if let Some(existing) = existing.as_value() {
+                if canonical(existing) == canonical(&new) {
+                    return false;
+                }
+                *new.decor_mut() = existing.decor().clone();
+            }
+            *existing = Item::Value(new);
+        }
+        None => {
+            table.insert(key, Item::Value(new));
+        }
+    }
+    true
+}
+
+/// Merges the table `source` into `target`, key by key. New tables are built up rather than
+/// copied, so that they go after the existing tables instead of where they were in the input.
+fn merge(
+    target: &mut Item,
+    source: &Item,
+    path: &str,
+    arrays: ArrayMerge,
+    changed: &mut Vec<String>,
+) -> Result<(), CargoConfigError> {
+    let Some(source) = source.as_table_like() else {
+        return Err(CargoConfigError::NotATable(path.into()));
+    };
+    if target.is_none() {
+        let mut new = Table::new();
+        new.set_implicit(true);
+        *target = Item::Table(new);
+    }
+    let target = target
+        .as_table_like_mut()
+        .ok_or_else(|| CargoConfigError::NotATable(path.into()))?;
+    for (key, item) in source.iter() {
+        let path = format!("{path}.{key}");
+        if item.is_table_like() {
+            let entry = target.entry(key).or_insert(Item::None);
+            merge(entry, item, &path, arrays, changed)?;
+        } else if let Some(value) = item.as_value() {
+            if merge_value(target, key, value, arrays) {
+                changed.push(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sets `key` of `target` to `value`, appending to an existing array if `arrays` says so, and
+/// returns whether it changed.
+fn merge_value(target: &mut dyn TableLike, key: &str, value: &Value, arrays: ArrayMerge) -> bool {
+    let mut value = value.clone();
+    value.decor_mut().clear();
+    let existing = target.get_mut(key).and_then(Item::as_array_mut);
+    match (existing, &mut value, arrays) {
+        (Some(existing), Value::Array(items), ArrayMerge::Append) => {
+            let present: Vec<_> = existing.iter().map(canonical).collect();
+            let before = existing.len();
+            for item in items.iter() {
+                if !present.contains(&canonical(item)) {
+                    existing.push(item.clone());
+                }
+            }
+            existing.fmt();
+            existing.len() != before
+        }
+        (_, value, _) => {
+            if let Value::Array(array) = value {
+                array.fmt();
+            }
+            set_value(target, key, value.clone())
+        }
+    }
+}
+
+/// Removes what [`merge`] would add from `target`.
+fn unmerge(target: &mut Item, source: &Item, path: &str, removed: &mut Vec<String>) {
+    let (Some(target), Some(source)) = (target.as_table_like_mut(), source.as_table_like()) else {
+        return;
+    };
+    for (key, item) in source.iter() {
+        let path = format!("{path}.{key}");
+        let Some(existing) = target.get_mut(key) else {
+            continue;
+        };
+        if item.is_table_like() {
+            let was_empty = existing.as_table_like().is_some_and(TableLike::is_empty);
+            unmerge(existing, item, &path, removed);
+            if !was_empty && existing.as_table_like().is_some_and(TableLike::is_empty) {
+                target.remove(key);
+            }
+        } else if let (Some(array), Some(items)) = (existing.as_array_mut(), item.as_array()) {
+            let items: Vec<_> = items.iter().map(canonical).collect();
+            let before = array.len();
+            // backwards, so that removing an item does not shift the items still to check
+            for index in (0..array.len()).rev() {
+                let item = array.get(index).expect("index should be within the array");
+                if items.contains(&canonical(item)) {
+                    array.remove(index);
+                }
+            }
+            if array.len() != before {
+                removed.push(path);
+                if array.is_empty() {
+                    target.remove(key);
+                }
+            }
+        } else {
+            target.remove(key);
+            removed.push(path);
+        }
+    }
+}
+
+/// A unified diff from `old` to `new`, with `path` in the headers.
+#[must_use]
+pub fn unified_diff(old: &str, new: &str, path: &Path) -> String {
+    let path = path.display().to_string();
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(&path, &path)
+        .to_string()
+}
+
+/// Where the backup of `path` is kept, e.g. `.cargo/config.toml.bak`.
+#[must_use]
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(BACKUP_EXTENSION);
+    path.with_file_name(file_name)
+}
+
+/// Replaces the contents of `path` with `contents` by writing a temporary file next to it and
+/// renaming it, so that the file is never left half-written.
+///
+/// # Errors
+///
+/// * `CargoConfigError::Write` if the temporary file cannot be written or renamed.
+pub fn write_atomically(path: &Path, contents: &str) -> Result<(), CargoConfigError> {
+    let write_error = |source| CargoConfigError::Write {
+        path: path.into(),
+        source,
+    };
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut file = NamedTempFile::new_in(directory).map_err(write_error)?;
+    io::Write::write_all(&mut file, contents.as_bytes()).map_err(write_error)?;
+    file.as_file().sync_all().map_err(write_error)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        // keep the permissions of the original, as the temporary file is private
+        fs::set_permissions(file.path(), metadata.permissions()).map_err(write_error)?;
+    }
+    file.persist(path)
+        .map_err(|error| write_error(error.error))?;
+    Ok(())
+}
+
+/// Copies `old`, the current contents of `path`, to its backup and then writes `new` atomically.
+///
+/// # Errors
+///
+/// * `CargoConfigError::Write` if the backup or the file cannot be written.
+pub fn write_with_backup(
+    path: &Path,
+    old: Option<&str>,
+    new: &str,
+) -> Result<(), CargoConfigError> {
+    if let Some(old) = old {
+        write_atomically(&backup_path(path), old)?;
+    }
+    write_atomically(path, new)
+}
+
+/// Restores `path` from its backup, which is removed.
+///
+/// # Errors
+///
+/// * `CargoConfigError::NoBackup` if there is no backup.
+/// * `CargoConfigError::Write` if it cannot be moved.
+pub fn revert(path: &Path) -> Result<(), CargoConfigError> {
+    let backup = backup_path(path);
+    if !backup.is_file() {
+        return Err(CargoConfigError::NoBackup(backup));
+    }
+    fs::rename(&backup, path).map_err(|source| CargoConfigError::Write {
+        path: path.into(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = r#"OPENCV_LINK_LIBS = "opencv_imgproc,opencv_core"
+
+[env]
+OPENCV_INCLUDE_PATHS = { value = "include", relative = true }
+
+[build]
+rustflags = ["-C", "link-arg=-fuse-ld=lld"]
+
+[target.x86_64-unknown-linux-gnu]
+linker = "clang"
+"#;
+
+    fn parse(contents: &str) -> Document {
+        contents.parse().expect("should be TOML")
+    }
+
+    #[test]
+    fn remove_undoes_apply() {
+        let original = "# project configuration\n[alias]\n\n[net]\noffline = true\n";
+        let mut config = parse(original);
+        let input = parse(INPUT);
+
+        let changed = apply(
+            &mut config,
+            &input,
+            Path::new("input.toml"),
+            ArrayMerge::Append,
+        )
+        .expect("should apply");
+        assert_eq!(
+            changed,
+            [
+                "env.OPENCV_LINK_LIBS",
+                "env.OPENCV_INCLUDE_PATHS",
+                "build.rustflags",
+                "target.x86_64-unknown-linux-gnu.linker",
+            ]
+        );
+
+        let removed = remove(&mut config, &input);
+        assert_eq!(removed, changed);
+        // the empty [alias] of the user is kept
+        assert_eq!(config.to_string(), original);
+    }
+
+    #[test]
+    fn remove_keeps_what_was_empty_before() {
+        let original = r#"[env]
+OPENCV_LINK_LIBS = "opencv_core"
+
+[build]
+rustflags = []
+target-dir = "target"
+
+[target.x86_64-unknown-linux-gnu]
+
+[target.aarch64-unknown-linux-gnu]
+linker = "clang"
+"#;
+        let mut config = parse(original);
+
+        let removed = remove(&mut config, &parse(INPUT));
+        assert_eq!(removed, ["env.OPENCV_LINK_LIBS"]);
+        assert_eq!(
+            config.to_string(),
+            r#"
+[build]
+rustflags = []
+target-dir = "target"
+
+[target.x86_64-unknown-linux-gnu]
+
+[target.aarch64-unknown-linux-gnu]
+linker = "clang"
+"#
+        );
+    }
+
+    #[test]
+    fn remove_prunes_what_it_emptied() {
+        let mut config = parse(
+            r#"[build]
+rustflags = ["-C", "link-arg=-fuse-ld=lld"]
+
+[target.x86_64-unknown-linux-gnu]
+linker = "clang"
+
+[target.aarch64-unknown-linux-gnu]
+"#,
+        );
+
+        let removed = remove(&mut config, &parse(INPUT));
+        assert_eq!(
+            removed,
+            ["build.rustflags", "target.x86_64-unknown-linux-gnu.linker"]
+        );
+        assert_eq!(config.to_string(), "\n[target.aarch64-unknown-linux-gnu]\n");
+    }
+}
@@ -0,0 +1,405 @@
+use crate::{
+    build_info::{BuildInfo, Dependencies, Package},
+    vcpkg::library_name,
+};
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+const CONFIG_FILE: &str = "OpenCVConfig.cmake";
+
+/// Directories where distributions and `make install` put the CMake package of OpenCV.
+const SYSTEM_DIRECTORIES: [&str; 6] = [
+    "/usr/local/lib/cmake/opencv4",
+    "/usr/local/share/OpenCV",
+    "/usr/lib/x86_64-linux-gnu/cmake/opencv4",
+    "/usr/lib64/cmake/opencv4",
+    "/usr/lib/cmake/opencv4",
+    "/usr/share/OpenCV",
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum CmakeError {
+    #[error(
+        "no {CONFIG_FILE}, looked in {}",
+        candidates.iter().map(|c| c.display().to_string()).collect::<Vec<_>>().join(", ")
+    )]
+    NotFound { candidates: Vec<PathBuf> },
+    #[error("could not read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("{0} does not set OpenCV_LIB_COMPONENTS or OpenCV_LIBS")]
+    NoLibraries(PathBuf),
+}
+
+/// Finds the directory with `OpenCVConfig.cmake`, trying `directory`, then `OpenCV_DIR`, then
+/// the usual system directories.
+///
+/// # Errors
+///
+/// * `CmakeError::NotFound` if none of them has the file.
+pub fn config_directory(directory: Option<&Path>) -> Result<PathBuf, CmakeError> {
+    let candidates: Vec<_> = directory
+        .map(Path::to_path_buf)
+        .into_iter()
+        .chain(env::var_os("OpenCV_DIR").map(PathBuf::from))
+        .chain(SYSTEM_DIRECTORIES.iter().map(PathBuf::from))
+        .collect();
+    candidates
+        .iter()
+        .find(|candidate| candidate.join(CONFIG_FILE).is_file())
+        .cloned()
+        .ok_or(CmakeError::NotFound { candidates })
+}
+
+/// Variables set by the `set` and `get_filename_component` commands of CMake scripts, which is
+/// all that OpenCV's package configuration needs to describe its installation. Conditions,
+/// loops and functions are not evaluated, so the last assignment of a variable wins.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Variables(pub HashMap<String, String>);
+
+impl Variables {
+    /// Evaluates the script at `path`, with `CMAKE_CURRENT_LIST_DIR` and
+    /// `CMAKE_CURRENT_LIST_FILE` set as CMake does.
+    ///
+    /// # Errors
+    ///
+    /// * `CmakeError::Io` if the file cannot be read.
+    pub fn include(&mut self, path: &Path) -> Result<(), CmakeError> {
+        let contents = fs::read_to_string(path).map_err(|source| CmakeError::Io {
+            path: path.into(),
+            source,
+        })?;
+        let directory = path.parent().unwrap_or(Path::new("."));
+        self.set("CMAKE_CURRENT_LIST_DIR", directory.to_string_lossy());
+        self.set("CMAKE_CURRENT_LIST_FILE", path.to_string_lossy());
+        for (command, arguments) in commands(&contents) {
+            let arguments: Vec<_> = arguments.iter().map(|a| self.expand(a)).collect();
+            match (command.to_lowercase().as_str(), arguments.as_slice()) {
+                ("set", [name, values @ ..]) => {
+                    let values: Vec<_> = values
+                        .iter()
+                        .take_while(|value| !["CACHE", "PARENT_SCOPE"].contains(&value.as_str()))
+                        .map(String::as_str)
+                        .collect();
+                    self.set(name, values.join(";"));
+                }
+                ("get_filename_component", [name, file, mode, ..]) => {
+                    let file = Path::new(file);
+                    let value = match mode.as_str() {
+                        "PATH" | "DIRECTORY" => file.parent().map(Path::to_path_buf),
+                        "NAME" => file.file_name().map(PathBuf::from),
+                        "ABSOLUTE" | "REALPATH" => Some(normalize(file)),
+                        _ => None,
+                    };
+                    if let Some(value) = value {
+                        self.set(name, value.to_string_lossy());
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set(&mut self, name: &str, value: impl Into<String>) {
+        self.0.insert(name.into(), value.into());
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    /// The items of the list variable `name`, which are separated by `;`.
+    #[must_use]
+    pub fn list(&self, name: &str) -> Vec<String> {
+        self.get(name)
+            .unwrap_or_default()
+            .split(';')
+            .filter(|item| !item.is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    /// Replaces `${name}`, innermost first, with the value of the variable, or nothing if it is
+    /// undefined, as CMake does.
+    fn expand(&self, value: &str) -> String {
+        let mut expanded = value.to_string();
+        while let Some(start) = expanded.rfind("${") {
+            let Some(end) = expanded[start..].find('}') else {
+                break;
+            };
+            let name = &expanded[start + 2..start + end];
+            let variable = self.get(name).unwrap_or_default().to_string();
+            expanded.replace_range(start..=start + end, &variable);
+        }
+        expanded
+    }
+}
+
+/// Splits a CMake script into commands and their arguments, removing comments and quotes.
+fn commands(contents: &str) -> Vec<(String, Vec<String>)> {
+    let mut commands = Vec::new();
+    let mut chars = contents.chars().peekable();
+    let mut name = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '#' => {
+                chars.by_ref().find(|c| *c == '\n');
+                name.clear();
+            }
+            '(' if !name.is_empty() => {
+                let mut arguments = Vec::new();
+                let mut current = String::new();
+                let mut quoted = false;
+                let mut depth = 0;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => quoted = !quoted,
+                        '\\' if quoted => current.extend(chars.next()),
+                        '(' if !quoted => {
+                            depth += 1;
+                            current.push(c);
+                        }
+                        ')' if !quoted && depth > 0 => {
+                            depth -= 1;
+                            current.push(c);
+                        }
+                        ')' if !quoted => break,
+                        '#' if !quoted => {
+                            chars.by_ref().find(|c| *c == '\n');
+                        }
+                        c if c.is_whitespace() && !quoted => {
+                            if !current.is_empty() {
+                                arguments.push(std::mem::take(&mut current));
+                            }
+                        }
+                        c => current.push(c),
+                    }
+                }
+                if !current.is_empty() {
+                    arguments.push(current);
+                }
+                commands.push((std::mem::take(&mut name), arguments));
+            }
+            c if c.is_alphanumeric() || c == '_' => name.push(c),
+            _ => name.clear(),
+        }
+    }
+    commands
+}
+
+/// Removes `.` and `..` components without touching the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Reads the installation that the `OpenCVConfig.cmake` in `directory` describes. CMake does not
+/// tell the dependencies of static OpenCV libraries without evaluating its targets, so only the
+/// OpenCV libraries are linked.
+///
+/// # Errors
+///
+/// * `CmakeError::Io` if a script cannot be read.
+/// * `CmakeError::NoLibraries` if the configuration does not list the OpenCV libraries.
+pub fn load(directory: &Path) -> Result<Dependencies, CmakeError> {
+    let config = directory.join(CONFIG_FILE);
+    let mut variables = Variables::default();
+    let version_file = directory.join("OpenCVConfig-version.cmake");
+    if version_file.is_file() {
+        variables.include(&version_file)?;
+    }
+    variables.include(&config)?;
+
+    let libs = ["OpenCV_LIB_COMPONENTS", "OpenCV_LIBS"]
+        .iter()
+        .map(|name| variables.list(name))
+        .find(|libs| !libs.is_empty())
+        .ok_or_else(|| CmakeError::NoLibraries(config.clone()))?;
+    // the module list is in dependency order, the linker needs the reverse
+    let libs = libs.into_iter().rev().collect();
+
+    let lib_dirs = imported_locations(directory)?
+        .iter()
+        .filter_map(|location| location.parent())
+        .map(|dir| dir.to_string_lossy().into_owned())
+        .fold(Vec::new(), |mut dirs, dir| {
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+            dirs
+        });
+    let lib_dirs = if lib_dirs.is_empty() {
+        variables
+            .get("OpenCV_INSTALL_PATH")
+            .map(|install| Path::new(install).join("lib"))
+            .into_iter()
+            .map(|dir| dir.to_string_lossy().into_owned())
+            .collect()
+    } else {
+        lib_dirs
+    };
+
+    Ok(Dependencies {
+        packages: vec![Package {
+            name: "opencv".into(),
+            version: variables
+                .get("OpenCV_VERSION")
+                .or_else(|| variables.get("PACKAGE_VERSION"))
+                .map(String::from),
+            requires: Vec::new(),
+            info: BuildInfo {
+                include_dirs: variables.list("OpenCV_INCLUDE_DIRS"),
+                lib_dirs,
+                libs,
+                system_libs: Vec::new(),
+            },
+        }],
+        has_requirements: false,
+    })
+}
+
+/// The library files of the imported targets in the `OpenCVModules-<config>.cmake` files of
+/// `directory`.
+fn imported_locations(directory: &Path) -> Result<Vec<PathBuf>, CmakeError> {
+    let entries = fs::read_dir(directory).map_err(|source| CmakeError::Io {
+        path: directory.into(),
+        source,
+    })?;
+    let mut locations = Vec::new();
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if !(file_name.starts_with("OpenCVModules-") && file_name.ends_with(".cmake")) {
+            continue;
+        }
+        // the location refers to `_IMPORT_PREFIX`, which OpenCVModules.cmake computes from its
+        // own location, two or three directories up
+        let mut variables = Variables::default();
+        let prefix = ["../../..", "../.."]
+            .iter()
+            .map(|up| normalize(&directory.join(up)))
+            .find(|prefix| prefix.join("include").is_dir())
+            .unwrap_or_else(|| normalize(&directory.join("../../..")));
+        variables.set("_IMPORT_PREFIX", prefix.to_string_lossy());
+        let contents = fs::read_to_string(&path).map_err(|source| CmakeError::Io {
+            path: path.clone(),
+            source,
+        })?;
+        for (command, arguments) in commands(&contents) {
+            if !command.eq_ignore_ascii_case("set_target_properties") {
+                continue;
+            }
+            let mut arguments = arguments.iter();
+            while let Some(argument) = arguments.next() {
+                if argument.starts_with("IMPORTED_LOCATION") {
+                    if let Some(location) = arguments.next() {
+                        let location = PathBuf::from(variables.expand(location));
+                        let is_library = location
+                            .file_name()
+                            .is_some_and(|name| library_name(&name.to_string_lossy()).is_some());
+                        if is_library {
+                            locations.push(location);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(locations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(path)
+    }
+
+    #[test]
+    fn reads_the_installation_of_the_config() {
+        let directory = fixture("cmake/lib/cmake/opencv4");
+        assert_eq!(
+            config_directory(Some(&directory)).expect("should find the config"),
+            directory
+        );
+
+        let dependencies = load(&directory).expect("fixture should load");
+        let prefix = fixture("cmake");
+        assert_eq!(
+            dependencies.packages,
+            [Package {
+                name: "opencv".into(),
+                version: Some("4.5.5".into()),
+                requires: Vec::new(),
+                info: BuildInfo {
+                    include_dirs: vec![prefix.join("include/opencv4").display().to_string()],
+                    // from the imported locations
+                    lib_dirs: vec![prefix.join("lib").display().to_string()],
+                    libs: [
+                        "opencv_highgui",
+                        "opencv_imgcodecs",
+                        "opencv_imgproc",
+                        "opencv_core"
+                    ]
+                    .map(String::from)
+                    .to_vec(),
+                    system_libs: Vec::new(),
+                },
+            }]
+        );
+        assert!(!dependencies.has_requirements);
+    }
+
+    #[test]
+    fn evaluates_set_and_get_filename_component() {
+        let mut variables = Variables::default();
+        variables
+            .include(&fixture("cmake/lib/cmake/opencv4/OpenCVConfig.cmake"))
+            .expect("fixture should be read");
+
+        assert_eq!(
+            variables.get("OpenCV_INSTALL_PATH"),
+            Some(fixture("cmake").to_string_lossy().as_ref())
+        );
+        assert_eq!(variables.list("OpenCV_FIND_COMPONENTS").len(), 4);
+        // undefined variables expand to nothing
+        assert_eq!(variables.expand("a${OpenCV_MODULES_SUFFIX}b"), "ab");
+        assert_eq!(
+            variables.expand("${OpenCV_VERSION}-${OpenCV_${OpenCV_UNDEFINED}VERSION}"),
+            "4.5.5-4.5.5"
+        );
+    }
+
+    #[test]
+    fn reports_configs_without_libraries() {
+        let directory = fixture("cmake/no-libraries");
+        assert!(matches!(
+            load(&directory),
+            Err(CmakeError::NoLibraries(path)) if path == directory.join(CONFIG_FILE)
+        ));
+        assert!(matches!(
+            load(&fixture("cmake")),
+            Err(CmakeError::Io { .. })
+        ));
+    }
+}
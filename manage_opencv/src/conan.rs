@@ -0,0 +1,420 @@
+use crate::{
+    build_info::{extend_unique, BuildInfo, Dependencies, Package},
+    conan_build_info::{ConanBuildInfo, ParseError},
+    pkg_config::{Packages, PkgConfigError},
+};
+use serde_json::{Map, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// File generated by Conan 1's default `txt` generator.
+pub const CONAN_BUILD_INFO_FILE: &str = "conanbuildinfo.txt";
+/// Output of `conan install . --format=json` or `conan graph info . --format=json` with Conan 2.
+pub const GRAPH_JSON_FILE: &str = "graph.json";
+/// Entry of a Conan 2 `cpp_info` with the information that is not specific to a component.
+const ROOT_COMPONENT: &str = "root";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConanError {
+    #[error("could not read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("invalid {CONAN_BUILD_INFO_FILE}: {0}")]
+    Conan1(#[from] ParseError),
+    #[error("invalid conan graph JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("conan graph JSON should have a `graph.nodes` object or a `nodes` list")]
+    MissingNodes,
+    #[error(transparent)]
+    PkgConfig(#[from] PkgConfigError),
+    #[error(
+        "found neither {CONAN_BUILD_INFO_FILE}, {GRAPH_JSON_FILE} nor .pc files in {0}, use \
+         --format to tell which kind of file to read"
+    )]
+    UnknownFormat(PathBuf),
+}
+
+/// The kinds of Conan output that build information can be read from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum BuildInfoFormat {
+    /// Decide from the file name, or from the files in a directory
+    #[default]
+    Auto,
+    /// `conanbuildinfo.txt` from Conan 1
+    Conan1,
+    /// JSON dependency graph from Conan 2's `--format=json`
+    GraphJson,
+    /// Directory of `.pc` files from Conan 2's `PkgConfigDeps` generator
+    PkgConfig,
+}
+
+/// Reads the packages and their build information from `path`, which is either one of the files
+/// Conan generates or a directory containing them.
+///
+/// # Errors
+///
+/// * `ConanError::UnknownFormat` if the format is `Auto` and cannot be detected.
+/// * `ConanError::Io` if the file cannot be read.
+/// * The errors of the parser for the format.
+pub fn load(path: &Path, format: BuildInfoFormat) -> Result<Dependencies, ConanError> {
+    let (path, format) = match format {
+        BuildInfoFormat::Auto => detect(path)?,
+        format => (path.to_path_buf(), format),
+    };
+    let read = |path: &Path| {
+        fs::read_to_string(path).map_err(|source| ConanError::Io {
+            path: path.into(),
+            source,
+        })
+    };
+    match format {
+        BuildInfoFormat::Conan1 => parse_conan_build_info(&read(&path)?),
+        BuildInfoFormat::GraphJson => parse_graph_json(&read(&path)?),
+        BuildInfoFormat::PkgConfig => Ok(Packages::from_directory(&path)?.dependencies()?),
+        BuildInfoFormat::Auto => unreachable!("format should have been detected"),
+    }
+}
+
+/// Whether the directory `path` contains Conan output that [`load`] finds on its own, i.e.
+/// `conanbuildinfo.txt`, graph JSON or `.pc` files.
+#[must_use]
+pub fn has_output(path: &Path) -> bool {
+    path.is_dir() && detect(path).is_ok()
+}
+
+/// Finds the file to read and its format, preferring Conan 1's `conanbuildinfo.txt` over Conan 2's
+/// graph JSON over `.pc` files when `path` is a directory containing several.
+fn detect(path: &Path) -> Result<(PathBuf, BuildInfoFormat), ConanError> {
+    if path.is_file() {
+        let format = if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            BuildInfoFormat::GraphJson
+        } else {
+            BuildInfoFormat::Conan1
+        };
+        return Ok((path.into(), format));
+    }
+    for (file, format) in [
+        (CONAN_BUILD_INFO_FILE, BuildInfoFormat::Conan1),
+        (GRAPH_JSON_FILE, BuildInfoFormat::GraphJson),
+    ] {
+        let candidate = path.join(file);
+        if candidate.is_file() {
+            return Ok((candidate, format));
+        }
+    }
+    let has_pc_files = fs::read_dir(path)
+        .map_err(|source| ConanError::Io {
+            path: path.into(),
+            source,
+        })?
+        .filter_map(Result::ok)
+        .any(|entry| entry.path().extension().is_some_and(|e| e == "pc"));
+    if has_pc_files {
+        Ok((path.into(), BuildInfoFormat::PkgConfig))
+    } else {
+        Err(ConanError::UnknownFormat(path.into()))
+    }
+}
+
+/// Reads the packages of Conan 1's `conanbuildinfo.txt`, ordered like the aggregated `[libs]`
+/// section. The file does not record which package requires which.
+///
+/// # Errors
+///
+/// * `ConanError::Conan1` if the file is malformed or the `libs` section is missing.
+pub fn parse_conan_build_info(contents: &str) -> Result<Dependencies, ConanError> {
+    let build_info = ConanBuildInfo::parse(contents)?;
+    let link_order = build_info.aggregated.require("libs")?;
+
+    let mut packages: Vec<_> = build_info
+        .packages
+        .iter()
+        .map(|(name, fields)| Package {
+            name: name.clone(),
+            version: fields.get("version").first().cloned(),
+            requires: Vec::new(),
+            info: BuildInfo {
+                include_dirs: fields.get("includedirs").to_vec(),
+                lib_dirs: fields.get("libdirs").to_vec(),
+                libs: fields.get("libs").to_vec(),
+                // older versions of Conan do not write this section
+                system_libs: fields.get("system_libs").to_vec(),
+            },
+        })
+        .collect();
+    // header-only packages have no libraries, and go last
+    packages.sort_by_key(|package| {
+        package
+            .info
+            .libs
+            .first()
+            .and_then(|lib| link_order.iter().position(|ordered| ordered == lib))
+            .unwrap_or(usize::MAX)
+    });
+
+    Ok(Dependencies {
+        packages,
+        has_requirements: false,
+    })
+}
+
+/// Reads the packages and direct requirements of every host package in the JSON graph that
+/// Conan 2 prints with `--format=json`. Build tools such as CMake are skipped, and relative
+/// directories are resolved against the package folder.
+///
+/// # Errors
+///
+/// * `ConanError::Json` if `contents` is not JSON.
+/// * `ConanError::MissingNodes` if it does not describe a graph.
+pub fn parse_graph_json(contents: &str) -> Result<Dependencies, ConanError> {
+    let graph: Value = serde_json::from_str(contents)?;
+    // Conan 2.0 nests an object of nodes by id in `graph`, the betas had a top-level list
+    let nodes: Vec<(String, &Value)> = match (graph.pointer("/graph/nodes"), graph.get("nodes")) {
+        (Some(Value::Object(nodes)), _) => {
+            // ids are numbers in the order Conan expanded the graph, so dependents come first
+            let mut nodes: Vec<_> = nodes.iter().map(|(id, node)| (id.clone(), node)).collect();
+            nodes.sort_by_key(|(id, _)| id.parse::<usize>().unwrap_or(usize::MAX));
+            nodes
+        }
+        (_, Some(Value::Array(nodes))) => nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (index.to_string(), node))
+            .collect(),
+        _ => return Err(ConanError::MissingNodes),
+    };
+
+    // the consumer and packages that are not installed have no package folder
+    let host_packages: Vec<_> = nodes
+        .into_iter()
+        .filter_map(|(id, node)| {
+            let package_folder = node.get("package_folder").and_then(Value::as_str)?;
+            let build_context = node.get("context").and_then(Value::as_str) == Some("build");
+            (!build_context).then_some((id, node, package_folder))
+        })
+        .collect();
+    let names: HashMap<&str, (String, Option<String>)> = host_packages
+        .iter()
+        .map(|(id, node, _)| (id.as_str(), name_and_version(node)))
+        .collect();
+
+    let packages = host_packages
+        .iter()
+        .map(|(id, node, package_folder)| {
+            let (name, version) = names[id.as_str()].clone();
+            let requires = node
+                .get("dependencies")
+                .and_then(Value::as_object)
+                .into_iter()
+                .flatten()
+                .filter(|(_, edge)| {
+                    edge.get("direct").and_then(Value::as_bool) == Some(true)
+                        && edge.get("build").and_then(Value::as_bool) != Some(true)
+                })
+                .filter_map(|(id, _)| names.get(id.as_str()).map(|(name, _)| name.clone()))
+                .collect();
+
+            let mut info = BuildInfo::default();
+            let cpp_info = node.get("cpp_info").and_then(Value::as_object);
+            for component in cpp_info.map(components_in_link_order).unwrap_or_default() {
+                let strings = |key: &str| json_strings(component, key);
+                let dirs = |key: &str| {
+                    strings(key).into_iter().map(|dir| {
+                        Path::new(package_folder)
+                            .join(dir)
+                            .to_string_lossy()
+                            .into_owned()
+                    })
+                };
+                extend_unique(&mut info.include_dirs, dirs("includedirs"));
+                extend_unique(&mut info.lib_dirs, dirs("libdirs"));
+                extend_unique(&mut info.libs, strings("libs"));
+                extend_unique(&mut info.system_libs, strings("system_libs"));
+            }
+
+            Package {
+                name,
+                version,
+                requires,
+                info,
+            }
+        })
+        .collect();
+
+    Ok(Dependencies {
+        packages,
+        has_requirements: true,
+    }
+    .into_link_order())
+}
+
+/// The name and version of a node, from its own fields or from its reference
+/// `name/version@user/channel#revision`.
+fn name_and_version(node: &Value) -> (String, Option<String>) {
+    let reference = node.get("ref").and_then(Value::as_str).unwrap_or_default();
+    let reference = reference.split(['@', '#']).next().unwrap_or_default();
+    let (reference_name, reference_version) = reference
+        .split_once('/')
+        .map_or((reference, None), |(name, version)| (name, Some(version)));
+    let field = |key: &str| node.get(key).and_then(Value::as_str).map(String::from);
+    (
+        field("name").unwrap_or_else(|| reference_name.into()),
+        field("version").or_else(|| reference_version.map(String::from)),
+    )
+}
+
+fn json_strings(value: &Value, key: &str) -> Vec<String> {
+    value
+        .get(key)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(String::from)
+        .collect()
+}
+
+/// The package-wide `root` entry of a `cpp_info`, followed by the components with every
+/// component before the components of the same package it requires.
+fn components_in_link_order(cpp_info: &Map<String, Value>) -> Vec<&Value> {
+    fn visit<'a>(
+        name: &'a str,
+        cpp_info: &'a Map<String, Value>,
+        visited: &mut HashSet<&'a str>,
+        order: &mut Vec<&'a Value>,
+    ) {
+        let Some(component) = cpp_info.get(name) else {
+            return;
+        };
+        if !visited.insert(name) {
+            return;
+        }
+        // requirements of other packages are written as `package::component`
+        for requirement in component
+            .get("requires")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .filter(|requirement| !requirement.contains("::"))
+        {
+            visit(requirement, cpp_info, visited, order);
+        }
+        order.push(component);
+    }
+
+    let mut order = Vec::new();
+    let mut visited = HashSet::new();
+    for name in cpp_info.keys().filter(|name| *name != ROOT_COMPONENT) {
+        visit(name, cpp_info, &mut visited, &mut order);
+    }
+    order.extend(cpp_info.get(ROOT_COMPONENT));
+    order.reverse();
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(path)
+    }
+
+    fn strings(strings: &[&str]) -> Vec<String> {
+        strings.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn graph_json_has_host_packages_in_link_order() {
+        let contents = fs::read_to_string(fixture("conan2/graph.json")).expect("should read");
+        let dependencies = parse_graph_json(&contents).expect("fixture should parse");
+
+        assert!(dependencies.has_requirements);
+        let references: Vec<_> = dependencies
+            .packages
+            .iter()
+            .map(Package::reference)
+            .collect();
+        // no consumer and no cmake, which is a build requirement
+        assert_eq!(references, ["opencv/4.5.5", "zlib/1.2.13"]);
+
+        let opencv = dependencies.get("opencv").expect("should have opencv");
+        assert_eq!(opencv.requires, ["zlib"]);
+        // every component before the components it requires
+        assert_eq!(
+            opencv.info.libs,
+            strings(&["opencv_imgcodecs", "opencv_imgproc", "opencv_core"])
+        );
+        assert_eq!(
+            opencv.info.include_dirs,
+            strings(&[
+                "/home/user/.conan2/p/opencv1a2b/p/include",
+                "/home/user/.conan2/p/opencv1a2b/p/include/opencv4",
+            ])
+        );
+        assert_eq!(
+            opencv.info.lib_dirs,
+            strings(&["/home/user/.conan2/p/opencv1a2b/p/lib"])
+        );
+        assert_eq!(opencv.info.system_libs, strings(&["dl", "m", "pthread"]));
+
+        assert_eq!(
+            dependencies.build_info().libs,
+            strings(&["opencv_imgcodecs", "opencv_imgproc", "opencv_core", "z"])
+        );
+    }
+
+    #[test]
+    fn graph_json_without_nodes_is_an_error() {
+        assert!(matches!(
+            parse_graph_json(r#"{"graph": {}}"#),
+            Err(ConanError::MissingNodes)
+        ));
+        assert!(matches!(parse_graph_json("{"), Err(ConanError::Json(_))));
+    }
+
+    #[test]
+    fn load_detects_the_format() {
+        let conan1 = load(&fixture("conan1"), BuildInfoFormat::Auto).expect("should load");
+        assert!(!conan1.has_requirements);
+        assert_eq!(
+            conan1
+                .packages
+                .iter()
+                .map(|package| package.name.as_str())
+                .collect::<Vec<_>>(),
+            ["opencv", "zlib"]
+        );
+
+        let graph = load(&fixture("conan2"), BuildInfoFormat::Auto).expect("should load");
+        assert_eq!(
+            graph.get("opencv").map(|opencv| opencv.requires.len()),
+            Some(1)
+        );
+
+        let pc_files =
+            load(&fixture("conan2/pkgconfig"), BuildInfoFormat::Auto).expect("should load");
+        assert_eq!(pc_files.packages.len(), 5);
+
+        assert!(matches!(
+            load(&fixture("conan2/pkgconfig"), BuildInfoFormat::GraphJson),
+            Err(ConanError::Io { .. })
+        ));
+        assert!(matches!(
+            load(&fixture(""), BuildInfoFormat::Auto),
+            Err(ConanError::UnknownFormat(_))
+        ));
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::BTreeMap;
+
+/// Sections that Conan 1 writes for the aggregate of all packages, and with a `_<package>` suffix
+/// for every package.
+const FIELDS: [&str; 22] = [
+    "includedirs",
+    "libdirs",
+    "bindirs",
+    "resdirs",
+    "builddirs",
+    "frameworkdirs",
+    "libs",
+    "system_libs",
+    "frameworks",
+    "defines",
+    "cppflags",
+    "cxxflags",
+    "cflags",
+    "sharedlinkflags",
+    "exelinkflags",
+    "sysroot",
+    "build_modules",
+    "rootpath",
+    "name",
+    "version",
+    "generatornames",
+    "generatorfilenames",
+];
+
+const USER_INFO_PREFIX: &str = "USER_";
+const ENV_INFO_PREFIX: &str = "ENV_";
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ParseError {
+    #[error("line {line}: `{text}` is not inside a section")]
+    OutsideSection { line: usize, text: String },
+    #[error("line {line}: invalid section header `{text}`")]
+    InvalidHeader { line: usize, text: String },
+    #[error("line {line}: section [{section}] appears a second time")]
+    DuplicateSection { line: usize, section: String },
+    #[error("line {line}: expected `name=value` in [{section}]")]
+    InvalidEntry { line: usize, section: String },
+    #[error("conan build info should include a [{0}] section")]
+    MissingSection(String),
+}
+
+/// The sections of one package, or of the aggregate of all packages, by field name, e.g. `libs`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fields(pub BTreeMap<String, Vec<String>>);
+
+impl Fields {
+    /// The lines of the section `field`, which are empty if it is missing.
+    #[must_use]
+    pub fn get(&self, field: &str) -> &[String] {
+        self.0.get(field).map_or(&[], Vec::as_slice)
+    }
+
+    /// The lines of the section `field`.
+    ///
+    /// # Errors
+    ///
+    /// * `ParseError::MissingSection` if there is no such section.
+    pub fn require(&self, field: &str) -> Result<&[String], ParseError> {
+        self.0
+            .get(field)
+            .map(Vec::as_slice)
+            .ok_or_else(|| ParseError::MissingSection(field.into()))
+    }
+}
+
+/// The contents of the `conanbuildinfo.txt` that Conan 1's `txt` generator writes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConanBuildInfo {
+    /// Sections without package suffix, which combine all packages in link order.
+    pub aggregated: Fields,
+    /// Sections with a package suffix, such as `[includedirs_opencv]`, by package name.
+    pub packages: BTreeMap<String, Fields>,
+    /// `name=value` pairs of the `[USER_<package>]` sections, by package name.
+    pub user_info: BTreeMap<String, BTreeMap<String, String>>,
+    /// `name=value` pairs of the `[ENV_<package>]` sections, by package name.
+    pub env_info: BTreeMap<String, BTreeMap<String, String>>,
+    /// Sections that are none of the above, kept so that nothing is silently dropped.
+    pub other: Fields,
+}
+
+/// Where the lines of the current section go.
+enum Section {
+    Aggregated(String),
+    Package(String, String),
+    UserInfo(String),
+    EnvInfo(String),
+    Other(String),
+}
+
+impl ConanBuildInfo {
+    /// Parses `contents`, which may have Unix or Windows line endings, blank lines and `#`
+    /// comments.
+    ///
+    /// # Errors
+    ///
+    /// * `ParseError` describing the first malformed line.
+    pub fn parse(contents: &str) -> Result<Self, ParseError> {
+        let mut info = Self::default();
+        let mut section: Option<(String, Section)> = None;
+
+        for (index, line) in contents.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[') {
+                let header = header
+                    .strip_suffix(']')
+                    .filter(|header| !header.is_empty() && !header.contains(['[', ']']))
+                    .ok_or_else(|| ParseError::InvalidHeader {
+                        line: line_number,
+                        text: line.into(),
+                    })?;
+                let kind = Self::classify(header);
+                if info.start(&kind) {
+                    return Err(ParseError::DuplicateSection {
+                        line: line_number,
+                        section: header.into(),
+                    });
+                }
+                section = Some((header.into(), kind));
+                continue;
+            }
+
+            let Some((header, kind)) = &section else {
+                return Err(ParseError::OutsideSection {
+                    line: line_number,
+                    text: line.into(),
+                });
+            };
+            match kind {
+                Section::Aggregated(field) => info.aggregated.0.get_mut(field),
+                Section::Package(field, package) => info
+                    .packages
+                    .get_mut(package)
+                    .and_then(|fields| fields.0.get_mut(field)),
+                Section::Other(name) => info.other.0.get_mut(name),
+                Section::UserInfo(package) | Section::EnvInfo(package) => {
+                    let (name, value) =
+                        line.split_once('=')
+                            .ok_or_else(|| ParseError::InvalidEntry {
+                                line: line_number,
+                                section: header.clone(),
+                            })?;
+                    let entries = if matches!(kind, Section::UserInfo(_)) {
+                        &mut info.user_info
+                    } else {
+                        &mut info.env_info
+                    };
+                    entries
+                        .entry(package.clone())
+                        .or_default()
+                        .insert(name.trim().into(), value.trim().into());
+                    continue;
+                }
+            }
+            .expect("section should have been created by its header")
+            .push(line.into());
+        }
+
+        Ok(info)
+    }
+
+    /// Decides whether `header` is an aggregated field, a field of a package, or user or
+    /// environment information of a package.
+    fn classify(header: &str) -> Section {
+        if let Some(package) = header.strip_prefix(USER_INFO_PREFIX) {
+            return Section::UserInfo(package.into());
+        }
+        if let Some(package) = header.strip_prefix(ENV_INFO_PREFIX) {
+            return Section::EnvInfo(package.into());
+        }
+        if FIELDS.contains(&header) {
+            return Section::Aggregated(header.into());
+        }
+        // the longest match, so that `system_libs_x` is not read as `libs` of `system_`
+        FIELDS
+            .iter()
+            .filter_map(|field| {
+                let package = header.strip_prefix(field)?.strip_prefix('_')?;
+                (!package.is_empty()).then_some((field, package))
+            })
+            .max_by_key(|(field, _)| field.len())
+            .map_or_else(
+                || Section::Other(header.into()),
+                |(field, package)| Section::Package((*field).into(), package.into()),
+            )
+    }
+
+    /// Creates the storage for a section, and returns whether it already existed.
+    fn start(&mut self, section: &Section) -> bool {
+        fn insert(fields: &mut Fields, field: &str) -> bool {
+            fields.0.insert(field.into(), Vec::new()).is_some()
+        }
+        match section {
+            Section::Aggregated(field) => insert(&mut self.aggregated, field),
+            Section::Package(field, package) => {
+                insert(self.packages.entry(package.clone()).or_default(), field)
+            }
+            Section::Other(name) => insert(&mut self.other, name),
+            Section::UserInfo(package) => self
+                .user_info
+                .insert(package.clone(), BTreeMap::new())
+                .is_some(),
+            Section::EnvInfo(package) => self
+                .env_info
+                .insert(package.clone(), BTreeMap::new())
+                .is_some(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../tests/fixtures/conan1/conanbuildinfo.txt");
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn parses_aggregated_and_package_sections() {
+        let info = ConanBuildInfo::parse(FIXTURE).expect("fixture should parse");
+
+        assert_eq!(
+            info.aggregated.get("libs"),
+            lines(&["opencv_imgproc", "opencv_core", "z"])
+        );
+        assert_eq!(
+            info.aggregated.get("system_libs"),
+            lines(&["dl", "pthread"])
+        );
+        assert!(info.aggregated.get("defines").is_empty());
+        assert!(info.aggregated.get("frameworks").is_empty());
+        assert!(info.aggregated.require("defines").is_ok());
+
+        assert_eq!(info.packages.keys().collect::<Vec<_>>(), ["opencv", "zlib"]);
+        let opencv = &info.packages["opencv"];
+        assert_eq!(
+            opencv.get("libs"),
+            lines(&["opencv_imgproc", "opencv_core"])
+        );
+        // not `libs` of a package `system`
+        assert_eq!(opencv.get("system_libs"), lines(&["dl", "pthread"]));
+        assert_eq!(opencv.get("version"), lines(&["4.5.5"]));
+        assert_eq!(info.packages["zlib"].get("libs"), lines(&["z"]));
+        assert!(info.packages["zlib"].get("system_libs").is_empty());
+
+        assert_eq!(info.other.get("generators"), lines(&["txt"]));
+    }
+
+    #[test]
+    fn parses_user_and_env_info() {
+        let info = ConanBuildInfo::parse(FIXTURE).expect("fixture should parse");
+
+        assert_eq!(info.user_info["opencv"]["version"], "4.5.5");
+        assert!(info.user_info["zlib"].is_empty());
+        assert_eq!(
+            info.env_info["zlib"]["PATH"],
+            r#"["/home/user/.conan/data/zlib/1.2.13/_/_/package/c0ff/bin"]"#
+        );
+        assert!(!info.env_info.contains_key("opencv"));
+    }
+
+    #[test]
+    fn crlf_line_endings_parse_the_same() {
+        let crlf = FIXTURE.replace('\n', "\r\n");
+        assert!(crlf.contains("\r\n"));
+
+        assert_eq!(
+            ConanBuildInfo::parse(&crlf).expect("fixture should parse with CRLF"),
+            ConanBuildInfo::parse(FIXTURE).expect("fixture should parse")
+        );
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let info = ConanBuildInfo::parse("# header\n\n[libs]\n  # not a library\nz\n\n")
+            .expect("should parse");
+
+        assert_eq!(info.aggregated.get("libs"), lines(&["z"]));
+    }
+
+    #[test]
+    fn reports_malformed_lines() {
+        assert_eq!(
+            ConanBuildInfo::parse("# comment\nz\n[libs]\n"),
+            Err(ParseError::OutsideSection {
+                line: 2,
+                text: "z".into()
+            })
+        );
+        for header in ["[libs", "[]", "[libs]]", "[li[bs]"] {
+            assert_eq!(
+                ConanBuildInfo::parse(&format!("[libs]\nz\n{header}\n")),
+                Err(ParseError::InvalidHeader {
+                    line: 3,
+                    text: header.into()
+                }),
+                "{header}"
+            );
+        }
+        assert_eq!(
+            ConanBuildInfo::parse("[libs_zlib]\nz\n\n[libs_zlib]\nz\n"),
+            Err(ParseError::DuplicateSection {
+                line: 4,
+                section: "libs_zlib".into()
+            })
+        );
+        assert_eq!(
+            ConanBuildInfo::parse("[USER_zlib]\na=1\n[USER_zlib]\n"),
+            Err(ParseError::DuplicateSection {
+                line: 3,
+                section: "USER_zlib".into()
+            })
+        );
+        assert_eq!(
+            ConanBuildInfo::parse("[USER_opencv]\nversion\n"),
+            Err(ParseError::InvalidEntry {
+                line: 2,
+                section: "USER_opencv".into()
+            })
+        );
+        assert_eq!(
+            ConanBuildInfo::parse("[libs]\nz\n")
+                .expect("should parse")
+                .aggregated
+                .require("libdirs"),
+            Err(ParseError::MissingSection("libdirs".into()))
+        );
+    }
+}
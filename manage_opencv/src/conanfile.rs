@@ -0,0 +1,379 @@
+use crate::modules::{workspace_modules, ModuleUse, ModulesError, MAIN_MODULES};
+use std::{collections::BTreeSet, fmt::Write, path::Path};
+
+/// The OpenCV version of the generated conanfile if the configuration does not name one.
+pub const DEFAULT_OPENCV_VERSION: &str = "4.5.5";
+/// The modules of the main repository that each module needs to be built, for OpenCV 4.
+const MODULE_DEPENDENCIES: [(&str, &[&str]); 14] = [
+    ("calib3d", &["features2d", "flann", "imgproc"]),
+    ("dnn", &["imgproc"]),
+    ("features2d", &["imgproc"]),
+    ("flann", &[]),
+    ("gapi", &["imgproc"]),
+    ("highgui", &["imgcodecs", "imgproc", "videoio"]),
+    ("imgcodecs", &["imgproc"]),
+    ("imgproc", &[]),
+    ("ml", &[]),
+    ("objdetect", &["calib3d", "imgproc"]),
+    ("photo", &["imgproc"]),
+    ("stitching", &["calib3d", "features2d", "flann", "imgproc"]),
+    ("video", &["imgproc"]),
+    ("videoio", &["imgcodecs", "imgproc"]),
+];
+
+/// The kind of conanfile to generate.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ConanfileFormat {
+    /// `conanfile.txt` with `[requires]` and `[options]`
+    #[default]
+    Txt,
+    /// `conanfile.py` with a `ConanFile` class
+    Py,
+}
+
+impl ConanfileFormat {
+    #[must_use]
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Self::Txt => "conanfile.txt",
+            Self::Py => "conanfile.py",
+        }
+    }
+}
+
+/// An option of a package, e.g. `with_v4l` of `opencv`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackageOption {
+    pub package: String,
+    pub name: String,
+    /// The value as Conan reads it, e.g. `True`.
+    pub value: String,
+    /// Lines of the comment that explains the option, written above it.
+    pub comment: Vec<String>,
+}
+
+impl PackageOption {
+    #[must_use]
+    pub fn new(package: &str, name: &str, value: &str) -> Self {
+        Self {
+            package: package.into(),
+            name: name.into(),
+            value: value.into(),
+            comment: Vec::new(),
+        }
+    }
+}
+
+/// The `[conanfile]` section of `manage_opencv.toml`, which declares what Conan installs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConanfileSpec {
+    pub format: ConanfileFormat,
+    /// `conanfile.opencv`, the version of the `opencv` package.
+    pub opencv_version: String,
+    /// `conanfile.modules`, or `None` for the `opencv` features of the workspace.
+    pub modules: Option<Vec<String>>,
+    /// `conanfile.module_options`, whether to set an option per module of `opencv`. Only recent
+    /// revisions of its recipe have them, and Conan 1 rejects options a recipe does not have.
+    pub module_options: bool,
+    /// `conanfile.requires`, other packages to install.
+    pub requires: Vec<String>,
+    /// `conanfile.options.<package>.<option>`. They take precedence over the options of the
+    /// modules.
+    pub options: Vec<PackageOption>,
+}
+
+impl Default for ConanfileSpec {
+    fn default() -> Self {
+        Self {
+            format: ConanfileFormat::Txt,
+            opencv_version: DEFAULT_OPENCV_VERSION.into(),
+            modules: None,
+            module_options: false,
+            requires: Vec::new(),
+            options: Vec::new(),
+        }
+    }
+}
+
+impl ConanfileSpec {
+    /// The modules to build: those of the spec, or else those that the crates of the workspace at
+    /// `root` enable.
+    ///
+    /// # Errors
+    ///
+    /// * The errors of [`workspace_modules`].
+    pub fn modules(&self, root: &Path) -> Result<BTreeSet<String>, ModulesError> {
+        Ok(match &self.modules {
+            Some(modules) => modules.iter().cloned().collect(),
+            None => workspace_modules(root)?
+                .into_iter()
+                .flat_map(|ModuleUse { modules, .. }| modules)
+                .collect(),
+        })
+    }
+}
+
+/// `modules` with the modules they need, transitively.
+#[must_use]
+pub fn with_dependencies(modules: &BTreeSet<String>) -> BTreeSet<String> {
+    let mut all = modules.clone();
+    let mut pending: Vec<_> = modules.iter().cloned().collect();
+    while let Some(module) = pending.pop() {
+        let dependencies = MODULE_DEPENDENCIES
+            .iter()
+            .find(|(name, _)| *name == module)
+            .map_or(&[][..], |(_, dependencies)| *dependencies);
+        for dependency in dependencies {
+            if all.insert((*dependency).into()) {
+                pending.push((*dependency).into());
+            }
+        }
+    }
+    all
+}
+
+/// The options of the `opencv` package that build `modules` and their dependencies and leave
+/// out the other modules: one per module of the main repository, and `contrib` for the others.
+#[must_use]
+pub fn module_options(modules: &BTreeSet<String>) -> Vec<(String, String)> {
+    let modules = with_dependencies(modules);
+    let flag = |enabled: bool| if enabled { "True" } else { "False" }.to_string();
+    let mut options: Vec<_> = MAIN_MODULES
+        .iter()
+        .filter(|module| **module != "core")
+        .map(|module| ((*module).to_string(), flag(modules.contains(*module))))
+        .collect();
+    let contrib = modules
+        .iter()
+        .any(|module| !MAIN_MODULES.contains(&module.as_str()));
+    options.push(("contrib".into(), flag(contrib)));
+    options
+}
+
+/// Renders the conanfile of `spec` for `modules`, which only matter with
+/// `spec.module_options`. Conan 2 wants options as `opencv/*:option`, Conan 1 as
+/// `opencv:option`.
+#[must_use]
+pub fn render(spec: &ConanfileSpec, modules: &BTreeSet<String>, conan_version: u8) -> String {
+    let pattern = |package: &str| {
+        if conan_version >= 2 {
+            format!("{package}/*")
+        } else {
+            package.to_string()
+        }
+    };
+    let of_modules = if spec.module_options {
+        module_options(modules)
+    } else {
+        Vec::new()
+    };
+    let mut options: Vec<_> = of_modules
+        .into_iter()
+        .filter(|(name, _)| {
+            !spec
+                .options
+                .iter()
+                .any(|option| option.package == "opencv" && option.name == *name)
+        })
+        .map(|(name, value)| PackageOption::new("opencv", &name, &value))
+        .collect();
+    options.extend(spec.options.iter().cloned());
+    let requires: Vec<_> = std::iter::once(format!("opencv/{}", spec.opencv_version))
+        .chain(spec.requires.iter().cloned())
+        .collect();
+
+    let mut out = String::new();
+    match spec.format {
+        ConanfileFormat::Txt => {
+            out.push_str("# generated by `manage_opencv conanfile` from manage_opencv.toml\n");
+            out.push_str("[requires]\n");
+            for reference in &requires {
+                writeln!(out, "{reference}").expect("writing to a string should not fail");
+            }
+            out.push_str("\n[options]\n");
+            for option in &options {
+                for line in &option.comment {
+                    writeln!(out, "# {line}").expect("writing to a string should not fail");
+                }
+                let PackageOption {
+                    package,
+                    name,
+                    value,
+                    ..
+                } = option;
+                writeln!(out, "{}:{name}={value}", pattern(package))
+                    .expect("writing to a string should not fail");
+            }
+        }
+        ConanfileFormat::Py => {
+            out.push_str("# generated by `manage_opencv conanfile` from manage_opencv.toml\n");
+            out.push_str("from conan import ConanFile\n\n\n");
+            out.push_str("class OpenCvLabConan(ConanFile):\n");
+            out.push_str("    settings = \"os\", \"compiler\", \"build_type\", \"arch\"\n");
+            out.push_str("    requires = (\n");
+            for reference in &requires {
+                writeln!(out, "        \"{reference}\",")
+                    .expect("writing to a string should not fail");
+            }
+            out.push_str("    )\n    default_options = {\n");
+            for option in &options {
+                for line in &option.comment {
+                    writeln!(out, "        # {line}").expect("writing to a string should not fail");
+                }
+                let PackageOption {
+                    package,
+                    name,
+                    value,
+                    ..
+                } = option;
+                let value = match value.as_str() {
+                    "True" | "False" => value.clone(),
+                    _ => format!("\"{value}\""),
+                };
+                writeln!(out, "        \"{}:{name}\": {value},", pattern(package))
+                    .expect("writing to a string should not fail");
+            }
+            out.push_str("    }\n");
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn modules(modules: &[&str]) -> BTreeSet<String> {
+        modules.iter().map(ToString::to_string).collect()
+    }
+
+    fn spec(format: ConanfileFormat, module_options: bool) -> ConanfileSpec {
+        ConanfileSpec {
+            format,
+            module_options,
+            requires: vec!["zlib/1.2.13".into()],
+            options: vec![
+                PackageOption::new("opencv", "with_v4l", "False"),
+                PackageOption::new("opencv", "videoio", "False"),
+                PackageOption {
+                    comment: vec!["freetype does not find brotli".into()],
+                    ..PackageOption::new("freetype", "with_brotli", "False")
+                },
+                PackageOption::new("libjpeg", "quality", "high"),
+            ],
+            ..ConanfileSpec::default()
+        }
+    }
+
+    #[test]
+    fn module_options_build_the_modules_and_their_dependencies() {
+        let options = module_options(&modules(&["calib3d", "xfeatures2d"]));
+
+        let enabled: Vec<_> = options
+            .iter()
+            .filter(|(_, value)| value == "True")
+            .map(|(option, _)| option.as_str())
+            .collect();
+        assert_eq!(
+            enabled,
+            ["calib3d", "features2d", "flann", "imgproc", "contrib"]
+        );
+        // one per module of the main repository but core, and contrib
+        assert_eq!(options.len(), MAIN_MODULES.len());
+        assert!(!options.iter().any(|(option, _)| option == "core"));
+
+        let options = module_options(&modules(&["imgproc"]));
+        assert!(options.contains(&("contrib".into(), "False".into())));
+    }
+
+    #[test]
+    fn renders_txt_for_conan_1_and_2() {
+        let txt = spec(ConanfileFormat::Txt, false);
+        let expected = |pattern: &str| {
+            format!(
+                "# generated by `manage_opencv conanfile` from manage_opencv.toml
+[requires]
+opencv/4.5.5
+zlib/1.2.13
+
+[options]
+opencv{pattern}:with_v4l=False
+opencv{pattern}:videoio=False
+# freetype does not find brotli
+freetype{pattern}:with_brotli=False
+libjpeg{pattern}:quality=high
+"
+            )
+        };
+        assert_eq!(render(&txt, &modules(&["imgproc"]), 1), expected(""));
+        assert_eq!(render(&txt, &modules(&["imgproc"]), 2), expected("/*"));
+    }
+
+    #[test]
+    fn renders_py_for_conan_1_and_2() {
+        let py = spec(ConanfileFormat::Py, false);
+        let expected = |pattern: &str| {
+            format!(
+                "# generated by `manage_opencv conanfile` from manage_opencv.toml
+from conan import ConanFile
+
+
+class OpenCvLabConan(ConanFile):
+    settings = \"os\", \"compiler\", \"build_type\", \"arch\"
+    requires = (
+        \"opencv/4.5.5\",
+        \"zlib/1.2.13\",
+    )
+    default_options = {{
+        \"opencv{pattern}:with_v4l\": False,
+        \"opencv{pattern}:videoio\": False,
+        # freetype does not find brotli
+        \"freetype{pattern}:with_brotli\": False,
+        \"libjpeg{pattern}:quality\": \"high\",
+    }}
+"
+            )
+        };
+        assert_eq!(render(&py, &modules(&["imgproc"]), 1), expected(""));
+        assert_eq!(render(&py, &modules(&["imgproc"]), 2), expected("/*"));
+    }
+
+    #[test]
+    fn renders_module_options_only_when_asked() {
+        for format in [ConanfileFormat::Txt, ConanfileFormat::Py] {
+            for conan_version in [1, 2] {
+                let rendered = |module_options| {
+                    render(
+                        &spec(format, module_options),
+                        &modules(&["videoio"]),
+                        conan_version,
+                    )
+                };
+                let without = rendered(false);
+                assert!(!without.contains(":imgcodecs"), "{without}");
+                assert!(!without.contains(":contrib"), "{without}");
+
+                let with = rendered(true);
+                let (option, separator) = match format {
+                    ConanfileFormat::Txt => (":imgcodecs=", "="),
+                    ConanfileFormat::Py => (":imgcodecs\": ", "\": "),
+                };
+                assert!(with.contains(&format!("{option}True")), "{with}");
+                assert!(
+                    with.contains(&format!(":calib3d{separator}False")),
+                    "{with}"
+                );
+                // the option of the spec wins over that of the module
+                assert!(
+                    with.contains(&format!(":videoio{separator}False")),
+                    "{with}"
+                );
+                assert!(
+                    !with.contains(&format!(":videoio{separator}True")),
+                    "{with}"
+                );
+                assert_eq!(with.matches(":videoio").count(), 1, "{with}");
+            }
+        }
+    }
+}
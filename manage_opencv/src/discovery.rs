@@ -0,0 +1,235 @@
+use crate::{
+    build_info::Dependencies,
+    cmake::{self, CmakeError},
+    conan::{self, BuildInfoFormat, ConanError},
+    pkg_config::{self, Packages, PkgConfigError},
+    vcpkg::{self, VcpkgError},
+};
+use std::path::PathBuf;
+
+/// Where the OpenCV installation and its dependencies are read from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Backend {
+    /// Conan if its output is given, otherwise the first of pkg-config, vcpkg and CMake that
+    /// finds OpenCV
+    #[default]
+    Auto,
+    /// The output of Conan 1 or Conan 2
+    Conan,
+    /// `opencv4.pc` in `PKG_CONFIG_PATH` or the system directories
+    PkgConfig,
+    /// A vcpkg installed tree, in classic or manifest mode
+    Vcpkg,
+    /// `OpenCVConfig.cmake` in `OpenCV_DIR` or the system directories
+    Cmake,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DiscoveryError {
+    #[error("the conan backend needs the path of Conan's output")]
+    MissingConanOutput,
+    #[error(transparent)]
+    Conan(#[from] ConanError),
+    #[error(transparent)]
+    PkgConfig(#[from] PkgConfigError),
+    #[error(transparent)]
+    Vcpkg(#[from] VcpkgError),
+    #[error(transparent)]
+    Cmake(#[from] CmakeError),
+    #[error("OpenCV was not found:{}", list_attempts(attempts))]
+    NotFound {
+        attempts: Vec<(Backend, DiscoveryError)>,
+    },
+}
+
+fn list_attempts(attempts: &[(Backend, DiscoveryError)]) -> String {
+    attempts
+        .iter()
+        .map(|(backend, error)| format!("\n  {backend:?}: {error}"))
+        .collect()
+}
+
+/// Command line arguments that choose and configure the backend.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct DiscoveryArgs {
+    #[arg(
+        help = "conanbuildinfo.txt (Conan 1), graph JSON (Conan 2), or a directory containing \
+                  either or the .pc files of PkgConfigDeps (Conan 2)"
+    )]
+    pub conan_build_info: Option<PathBuf>,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        help = "kind of Conan output to read"
+    )]
+    pub format: BuildInfoFormat,
+    #[arg(long, value_enum, default_value_t, help = "where to look for OpenCV")]
+    pub backend: Backend,
+    #[arg(
+        long = "pkg-config-path",
+        help = "search these directories for .pc files before PKG_CONFIG_PATH"
+    )]
+    pub pkg_config_path: Vec<PathBuf>,
+    #[arg(long, help = "vcpkg checkout [default: VCPKG_ROOT]")]
+    pub vcpkg_root: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "vcpkg triplet [default: VCPKG_DEFAULT_TRIPLET or the host's]"
+    )]
+    pub vcpkg_triplet: Option<String>,
+    #[arg(long, help = "directory with OpenCVConfig.cmake [default: OpenCV_DIR]")]
+    pub cmake_dir: Option<PathBuf>,
+}
+
+impl DiscoveryArgs {
+    /// Reads OpenCV and its dependencies with the chosen backend, or with the first backend that
+    /// finds them.
+    ///
+    /// # Errors
+    ///
+    /// * `DiscoveryError::MissingConanOutput` if the backend is `Conan` without a path.
+    /// * `DiscoveryError::NotFound` with the error of every backend, if `Auto` finds nothing.
+    /// * The error of the chosen backend otherwise.
+    pub fn discover(&self) -> Result<(Backend, Dependencies), DiscoveryError> {
+        if self.backend != Backend::Auto {
+            return Ok((self.backend, self.load(self.backend)?));
+        }
+        let backends = if self.conan_build_info.is_some() {
+            vec![Backend::Conan]
+        } else {
+            vec![Backend::PkgConfig, Backend::Vcpkg, Backend::Cmake]
+        };
+        let mut attempts = Vec::new();
+        for backend in backends {
+            match self.load(backend) {
+                Ok(dependencies) => return Ok((backend, dependencies)),
+                Err(error) => attempts.push((backend, error)),
+            }
+        }
+        Err(DiscoveryError::NotFound { attempts })
+    }
+
+    /// Reads OpenCV and its dependencies with `backend`.
+    ///
+    /// # Errors
+    ///
+    /// * The error of the backend.
+    pub fn load(&self, backend: Backend) -> Result<Dependencies, DiscoveryError> {
+        match backend {
+            Backend::Auto => self.discover().map(|(_, dependencies)| dependencies),
+            Backend::Conan => {
+                let path = self
+                    .conan_build_info
+                    .as_deref()
+                    .ok_or(DiscoveryError::MissingConanOutput)?;
+                Ok(conan::load(path, self.format)?)
+            }
+            Backend::PkgConfig => {
+                let search_path: Vec<_> = self
+                    .pkg_config_path
+                    .iter()
+                    .cloned()
+                    .chain(pkg_config::search_path())
+                    .collect();
+                Ok(Packages::find_opencv(&search_path)?.dependencies()?)
+            }
+            Backend::Vcpkg => {
+                let triplet = self
+                    .vcpkg_triplet
+                    .clone()
+                    .unwrap_or_else(vcpkg::default_triplet);
+                let installed = vcpkg::installed_directory(self.vcpkg_root.as_deref(), &triplet)?;
+                Ok(vcpkg::load(&installed)?)
+            }
+            Backend::Cmake => {
+                let directory = cmake::config_directory(self.cmake_dir.as_deref())?;
+                Ok(cmake::load(&directory)?)
+            }
+        }
+    }
+
+    /// A file whose change should make a build script read the build information again.
+    #[must_use]
+    pub fn watched_path(&self) -> Option<&std::path::Path> {
+        self.conan_build_info.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn fixture(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(path)
+    }
+
+    fn version(dependencies: &Dependencies, name: &str) -> Option<String> {
+        dependencies
+            .get(name)
+            .and_then(|package| package.version.clone())
+    }
+
+    #[test]
+    fn every_backend_reads_its_fixture() {
+        let args = DiscoveryArgs {
+            conan_build_info: Some(fixture("conan2")),
+            pkg_config_path: vec![fixture("pkgconfig/opencv4")],
+            vcpkg_root: Some(fixture("vcpkg")),
+            vcpkg_triplet: Some("x64-linux-dynamic".into()),
+            cmake_dir: Some(fixture("cmake/lib/cmake/opencv4")),
+            ..DiscoveryArgs::default()
+        };
+
+        let conan = args.load(Backend::Conan).expect("should read the graph");
+        assert_eq!(version(&conan, "opencv").as_deref(), Some("4.5.5"));
+        // the directories of --pkg-config-path come before the system ones
+        let pkg_config = args
+            .load(Backend::PkgConfig)
+            .expect("should find opencv4.pc");
+        assert_eq!(version(&pkg_config, "opencv4").as_deref(), Some("4.5.5"));
+        let vcpkg = args.load(Backend::Vcpkg).expect("should find the triplet");
+        assert_eq!(version(&vcpkg, "opencv4").as_deref(), Some("4.8.0"));
+        let cmake = args.load(Backend::Cmake).expect("should find the config");
+        assert_eq!(version(&cmake, "opencv").as_deref(), Some("4.5.5"));
+    }
+
+    #[test]
+    fn auto_prefers_conan_output() {
+        let args = DiscoveryArgs {
+            conan_build_info: Some(fixture("conan1")),
+            cmake_dir: Some(fixture("cmake/lib/cmake/opencv4")),
+            ..DiscoveryArgs::default()
+        };
+        let (backend, dependencies) = args.discover().expect("should read conan1");
+        assert_eq!(backend, Backend::Conan);
+        assert!(dependencies.get("zlib").is_some());
+
+        let args = DiscoveryArgs {
+            conan_build_info: Some(fixture("cmake")),
+            ..args
+        };
+        match args.discover() {
+            Err(DiscoveryError::NotFound { attempts }) => {
+                assert_eq!(attempts.len(), 1);
+                assert_eq!(attempts[0].0, Backend::Conan);
+            }
+            other => panic!("expected no OpenCV, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn conan_backend_needs_a_path() {
+        let args = DiscoveryArgs {
+            backend: Backend::Conan,
+            ..DiscoveryArgs::default()
+        };
+        assert!(matches!(
+            args.discover(),
+            Err(DiscoveryError::MissingConanOutput)
+        ));
+    }
+}
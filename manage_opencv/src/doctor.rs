@@ -0,0 +1,597 @@
+use crate::vcpkg::library_name;
+use std::{
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+};
+use toml_edit::Document;
+
+/// Variables that the build script of the `opencv` crate reads.
+pub const INCLUDE_PATHS_VARIABLE: &str = "OPENCV_INCLUDE_PATHS";
+pub const LINK_PATHS_VARIABLE: &str = "OPENCV_LINK_PATHS";
+pub const LINK_LIBS_VARIABLE: &str = "OPENCV_LINK_LIBS";
+
+/// Directories the linker searches without being told to.
+const SYSTEM_LIB_DIRECTORIES: [&str; 6] = [
+    "/usr/local/lib",
+    "/usr/lib/x86_64-linux-gnu",
+    "/usr/lib64",
+    "/usr/lib",
+    "/lib/x86_64-linux-gnu",
+    "/lib",
+];
+
+/// Header that every OpenCV installation has, relative to an include directory.
+const CORE_HEADER: &str = "opencv2/core.hpp";
+/// Header that defines `CV_VERSION_MAJOR` and friends, relative to an include directory.
+const VERSION_HEADER: &str = "opencv2/core/version.hpp";
+
+/// Libraries of the operating system that OpenCV commonly links, with the Debian package that
+/// provides them.
+const SYSTEM_LIB_PACKAGES: [(&str, &str); 16] = [
+    ("pthread", "libc6-dev"),
+    ("dl", "libc6-dev"),
+    ("m", "libc6-dev"),
+    ("rt", "libc6-dev"),
+    ("stdc++", "libstdc++-12-dev"),
+    ("z", "zlib1g-dev"),
+    ("png", "libpng-dev"),
+    ("jpeg", "libjpeg-dev"),
+    ("tiff", "libtiff-dev"),
+    ("webp", "libwebp-dev"),
+    ("gtk-3", "libgtk-3-dev"),
+    ("gdk-3", "libgtk-3-dev"),
+    ("glib-2.0", "libglib2.0-dev"),
+    ("gobject-2.0", "libglib2.0-dev"),
+    ("va", "libva-dev"),
+    ("X11", "libx11-dev"),
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum DoctorError {
+    #[error("could not read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("{path} is not valid TOML: {source}")]
+    InvalidConfig {
+        path: PathBuf,
+        #[source]
+        source: toml_edit::TomlError,
+    },
+}
+
+/// Where the value of a variable came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Environment,
+    CargoConfig(PathBuf),
+    Unset,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Environment => write!(f, "the environment"),
+            Self::CargoConfig(path) => write!(f, "{}", path.display()),
+            Self::Unset => write!(f, "nowhere"),
+        }
+    }
+}
+
+/// How a library is to be linked, as the prefix of an `OPENCV_LINK_LIBS` entry tells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    Static,
+    Dynamic,
+    Framework,
+    /// No prefix, so the linker takes whichever it finds.
+    Any,
+}
+
+/// The link configuration of the `opencv` crate, split into lists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkConfig {
+    pub include_paths: Vec<PathBuf>,
+    pub link_paths: Vec<PathBuf>,
+    pub link_libs: Vec<(LinkKind, String)>,
+    /// Where each of the three variables came from, in the order above.
+    pub sources: [Source; 3],
+}
+
+impl LinkConfig {
+    /// Reads the variables from the environment, or else from the `env` section of the Cargo
+    /// configuration at `cargo_config`, which Cargo applies to build scripts in the same order.
+    ///
+    /// # Errors
+    ///
+    /// * `DoctorError::Io` if the configuration exists but cannot be read.
+    /// * `DoctorError::InvalidConfig` if it is not TOML.
+    pub fn load(cargo_config: &Path) -> Result<Self, DoctorError> {
+        let contents = match fs::read_to_string(cargo_config) {
+            Ok(contents) => Some(contents),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+            Err(source) => {
+                let path = cargo_config.into();
+                return Err(DoctorError::Io { path, source });
+            }
+        };
+        let config = contents
+            .map(|contents| contents.parse::<Document>())
+            .transpose()
+            .map_err(|source| DoctorError::InvalidConfig {
+                path: cargo_config.into(),
+                source,
+            })?;
+        let read = |variable: &str| -> (String, Source) {
+            if let Ok(value) = env::var(variable) {
+                return (value, Source::Environment);
+            }
+            // either `NAME = "value"` or `NAME = { value = "value", ... }`
+            let configured = config
+                .as_ref()
+                .and_then(|config| config.get("env"))
+                .and_then(|env| env.get(variable))
+                .and_then(|item| item.as_str().or_else(|| item.get("value")?.as_str()));
+            configured.map_or((String::new(), Source::Unset), |value| {
+                (value.into(), Source::CargoConfig(cargo_config.into()))
+            })
+        };
+
+        let (include_paths, include_source) = read(INCLUDE_PATHS_VARIABLE);
+        let (link_paths, link_paths_source) = read(LINK_PATHS_VARIABLE);
+        let (link_libs, link_libs_source) = read(LINK_LIBS_VARIABLE);
+        Ok(Self {
+            include_paths: split_list(&include_paths).map(PathBuf::from).collect(),
+            link_paths: split_list(&link_paths).map(PathBuf::from).collect(),
+            link_libs: split_list(&link_libs).map(parse_lib).collect(),
+            sources: [include_source, link_paths_source, link_libs_source],
+        })
+    }
+}
+
+/// Items of a comma separated list, without the `+` that makes the `opencv` crate add them to
+/// what it found itself.
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',')
+        .map(|item| item.trim().trim_start_matches('+'))
+        .filter(|item| !item.is_empty())
+}
+
+fn parse_lib(lib: &str) -> (LinkKind, String) {
+    let (kind, name) = match lib.split_once('=') {
+        Some(("static", name)) => (LinkKind::Static, name),
+        Some(("dylib", name)) => (LinkKind::Dynamic, name),
+        Some(("framework", name)) => (LinkKind::Framework, name),
+        _ => (LinkKind::Any, lib),
+    };
+    // the `opencv` crate also accepts file names such as `libopencv_core.so`
+    let name = library_name(name).unwrap_or_else(|| name.into());
+    (kind, name)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// The outcome of one check, with a suggestion of how to fix it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+impl Finding {
+    pub(crate) fn ok(message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Ok,
+            message: message.into(),
+            suggestion: None,
+        }
+    }
+
+    pub(crate) fn warning(message: impl Into<String>, suggestion: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message: message.into(),
+            suggestion: Some(suggestion.into()),
+        }
+    }
+
+    pub(crate) fn error(message: impl Into<String>, suggestion: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            suggestion: Some(suggestion.into()),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self.severity {
+            Severity::Ok => "ok",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "[{label}] {}", self.message)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, "\n        fix: {suggestion}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks that the directories of `config` exist, that its libraries and OpenCV's headers can be
+/// found, and reports the OpenCV version of the headers.
+#[must_use]
+pub fn diagnose(config: &LinkConfig) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let variables = [
+        (INCLUDE_PATHS_VARIABLE, config.include_paths.is_empty()),
+        (LINK_PATHS_VARIABLE, config.link_paths.is_empty()),
+        (LINK_LIBS_VARIABLE, config.link_libs.is_empty()),
+    ];
+    for ((variable, empty), source) in variables.iter().zip(&config.sources) {
+        if *empty {
+            findings.push(Finding::warning(
+                format!("{variable} is not set, so the opencv crate falls back to probing"),
+                "run `manage_opencv setup` to write it to .cargo/config.toml",
+            ));
+        } else {
+            findings.push(Finding::ok(format!("{variable} is set by {source}")));
+        }
+    }
+
+    for (variable, dirs) in [
+        (INCLUDE_PATHS_VARIABLE, &config.include_paths),
+        (LINK_PATHS_VARIABLE, &config.link_paths),
+    ] {
+        for dir in dirs.iter().filter(|dir| !dir.is_dir()) {
+            findings.push(Finding::error(
+                format!("{} in {variable} is not a directory", dir.display()),
+                "install the dependencies again, or remove the directory from the list",
+            ));
+        }
+    }
+
+    findings.extend(check_headers(&config.include_paths));
+    findings.extend(check_libraries(config));
+    findings
+}
+
+fn check_headers(include_paths: &[PathBuf]) -> Vec<Finding> {
+    let Some(dir) = include_paths
+        .iter()
+        .find(|dir| dir.join(CORE_HEADER).is_file())
+    else {
+        // OpenCV 4 installs into `include/opencv4`, which is easy to miss
+        let nested = include_paths
+            .iter()
+            .map(|dir| dir.join("opencv4"))
+            .find(|dir| dir.join(CORE_HEADER).is_file());
+        let suggestion = nested.map_or_else(
+            || format!("add the directory that contains {CORE_HEADER} to {INCLUDE_PATHS_VARIABLE}"),
+            |dir| format!("add {} to {INCLUDE_PATHS_VARIABLE}", dir.display()),
+        );
+        return vec![Finding::error(
+            format!("{CORE_HEADER} is in none of the include directories"),
+            suggestion,
+        )];
+    };
+
+    let mut findings = vec![Finding::ok(format!(
+        "{CORE_HEADER} found in {}",
+        dir.display()
+    ))];
+    let version_header = dir.join(VERSION_HEADER);
+    match fs::read_to_string(&version_header)
+        .ok()
+        .and_then(|contents| header_version(&contents))
+    {
+        Some(version) => findings.push(Finding::ok(format!("OpenCV version {version}"))),
+        None => findings.push(Finding::warning(
+            format!(
+                "could not read the version from {}",
+                version_header.display()
+            ),
+            "check that the headers are complete",
+        )),
+    }
+    findings
+}
+
+/// The version of the `CV_VERSION_*` defines of `opencv2/core/version.hpp`.
+#[must_use]
+pub fn header_version(contents: &str) -> Option<String> {
+    let define = |name: &str| {
+        contents.lines().find_map(|line| {
+            let mut words = line.split_whitespace();
+            (words.next() == Some("#define") && words.next() == Some(name))
+                .then(|| words.next())
+                .flatten()
+                .map(String::from)
+        })
+    };
+    let version = format!(
+        "{}.{}.{}",
+        define("CV_VERSION_MAJOR")?,
+        define("CV_VERSION_MINOR")?,
+        define("CV_VERSION_REVISION")?
+    );
+    Some(match define("CV_VERSION_STATUS") {
+        Some(status) if status.trim_matches('"').is_empty() => version,
+        Some(status) => format!("{version}{}", status.trim_matches('"')),
+        None => version,
+    })
+}
+
+fn check_libraries(config: &LinkConfig) -> Vec<Finding> {
+    let system_dirs: Vec<_> = SYSTEM_LIB_DIRECTORIES.iter().map(PathBuf::from).collect();
+    let mut findings = Vec::new();
+    let mut resolved = 0;
+    for (kind, lib) in &config.link_libs {
+        if *kind == LinkKind::Framework {
+            continue;
+        }
+        if resolve_library(lib, *kind, &config.link_paths).is_some() {
+            resolved += 1;
+            continue;
+        }
+        if let Some(path) = resolve_library(lib, *kind, &system_dirs) {
+            resolved += 1;
+            findings.push(Finding::ok(format!(
+                "{lib} resolves to {}, which the linker searches by default",
+                path.display()
+            )));
+            continue;
+        }
+
+        let package = SYSTEM_LIB_PACKAGES
+            .iter()
+            .find(|(system_lib, _)| system_lib == lib)
+            .map(|(_, package)| *package);
+        let wanted = match kind {
+            LinkKind::Static => "a static library",
+            LinkKind::Dynamic => "a shared library",
+            LinkKind::Framework | LinkKind::Any => "a library",
+        };
+        let dirs: Vec<_> = config
+            .link_paths
+            .iter()
+            .chain(&system_dirs)
+            .cloned()
+            .collect();
+        if let Some(runtime) = find_runtime_library(lib, &dirs) {
+            findings.push(Finding::error(
+                format!(
+                    "{lib} is only installed as {}, which the linker does not take",
+                    runtime.display()
+                ),
+                package.map_or_else(
+                    || "install its development package".into(),
+                    |package| {
+                        format!(
+                            "install its development package, e.g. `sudo apt install {package}`"
+                        )
+                    },
+                ),
+            ));
+            continue;
+        }
+        findings.push(match package {
+            Some(package) => Finding::error(
+                format!("system library {lib} is missing"),
+                format!("install it, e.g. with `sudo apt install {package}`"),
+            ),
+            None => Finding::error(
+                format!("{lib} in {LINK_LIBS_VARIABLE} does not resolve to {wanted}"),
+                format!(
+                    "add its directory to {LINK_PATHS_VARIABLE}, or install the dependencies \
+                     again with `manage_opencv install`"
+                ),
+            ),
+        });
+    }
+    if resolved > 0 {
+        findings.push(Finding::ok(format!(
+            "{resolved} of {} libraries resolve",
+            config.link_libs.len()
+        )));
+    }
+    findings
+}
+
+/// The first file in `dirs` that the linker would take for `-l<name>`. Unlike [`library_name`],
+/// this ignores versioned shared libraries such as `libz.so.1`, which only the loader uses.
+#[must_use]
+pub fn resolve_library(name: &str, kind: LinkKind, dirs: &[PathBuf]) -> Option<PathBuf> {
+    let static_names = [format!("lib{name}.a"), format!("{name}.lib")];
+    let dynamic_names = [format!("lib{name}.so"), format!("lib{name}.dylib")];
+    let file_names: Vec<_> = match kind {
+        LinkKind::Static => static_names.iter().collect(),
+        LinkKind::Dynamic => dynamic_names.iter().collect(),
+        // the linker prefers shared libraries
+        LinkKind::Framework | LinkKind::Any => dynamic_names.iter().chain(&static_names).collect(),
+    };
+    dirs.iter().find_map(|dir| {
+        file_names
+            .iter()
+            .map(|file_name| dir.join(file_name))
+            .find(|path| path.is_file())
+    })
+}
+
+/// A versioned shared library for `-l<name>` in `dirs`, e.g. `libz.so.1` without `libz.so`,
+/// which means that the runtime package is installed but not the development package.
+fn find_runtime_library(name: &str, dirs: &[PathBuf]) -> Option<PathBuf> {
+    let prefix = format!("lib{name}.so.");
+    dirs.iter().find_map(|dir| {
+        fs::read_dir(dir)
+            .ok()?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .find(|path| {
+                path.file_name()
+                    .is_some_and(|file_name| file_name.to_string_lossy().starts_with(&prefix))
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(path)
+    }
+
+    fn config(include_paths: &[PathBuf], link_libs: &[&str]) -> LinkConfig {
+        LinkConfig {
+            include_paths: include_paths.to_vec(),
+            link_paths: vec![fixture("doctor/lib")],
+            link_libs: link_libs.iter().map(|lib| parse_lib(lib)).collect(),
+            sources: [Source::Environment, Source::Environment, Source::Unset],
+        }
+    }
+
+    fn messages(findings: &[Finding], severity: Severity) -> Vec<&str> {
+        findings
+            .iter()
+            .filter(|finding| finding.severity == severity)
+            .map(|finding| finding.message.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn reads_the_header_version() {
+        let header = "#define CV_VERSION_MAJOR    4\n\
+                      #define CV_VERSION_MINOR    5\n\
+                      #define CV_VERSION_REVISION 5\n";
+        assert_eq!(header_version(header).as_deref(), Some("4.5.5"));
+        assert_eq!(
+            header_version(&format!("{header}#define CV_VERSION_STATUS   \"-dev\"\n")).as_deref(),
+            Some("4.5.5-dev")
+        );
+        assert_eq!(
+            header_version(&format!("{header}#define CV_VERSION_STATUS   \"\"\n")).as_deref(),
+            Some("4.5.5")
+        );
+        assert_eq!(
+            header_version("#define CV_VERSION_MAJOR 4\n#define CV_VERSION_MINOR 5\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn splits_lists_and_library_kinds() {
+        assert_eq!(
+            split_list(" +opencv_core, ,zlib ,+").collect::<Vec<_>>(),
+            ["opencv_core", "zlib"]
+        );
+        assert_eq!(
+            parse_lib("static=opencv_core"),
+            (LinkKind::Static, "opencv_core".into())
+        );
+        assert_eq!(parse_lib("dylib=z"), (LinkKind::Dynamic, "z".into()));
+        assert_eq!(
+            parse_lib("framework=OpenCL"),
+            (LinkKind::Framework, "OpenCL".into())
+        );
+        assert_eq!(
+            parse_lib("libopencv_core.so"),
+            (LinkKind::Any, "opencv_core".into())
+        );
+        assert_eq!(parse_lib("static=libz.a"), (LinkKind::Static, "z".into()));
+        assert_eq!(
+            parse_lib("opencv_core"),
+            (LinkKind::Any, "opencv_core".into())
+        );
+    }
+
+    #[test]
+    fn resolves_libraries_the_linker_takes() {
+        let dirs = [fixture("vcpkg/installed/x64-linux/lib")];
+        let archive = dirs[0].join("libopencv_imgproc4.a");
+
+        assert_eq!(
+            resolve_library("opencv_imgproc4", LinkKind::Static, &dirs),
+            Some(archive.clone())
+        );
+        assert_eq!(
+            resolve_library("opencv_imgproc4", LinkKind::Any, &dirs),
+            Some(archive)
+        );
+        // only a versioned shared library, which the linker ignores
+        assert_eq!(
+            resolve_library("opencv_imgproc4", LinkKind::Dynamic, &dirs),
+            None
+        );
+        assert_eq!(
+            find_runtime_library("opencv_imgproc4", &dirs),
+            Some(dirs[0].join("libopencv_imgproc4.so.4.8.0"))
+        );
+        assert_eq!(resolve_library("opencv_dnn4", LinkKind::Any, &dirs), None);
+    }
+
+    #[test]
+    fn diagnoses_a_complete_installation() {
+        let findings = diagnose(&config(
+            &[fixture("doctor/include/opencv4")],
+            &["static=opencv_core"],
+        ));
+
+        assert_eq!(messages(&findings, Severity::Error), Vec::<&str>::new());
+        let ok = messages(&findings, Severity::Ok);
+        assert!(ok.contains(&"OpenCV version 4.8.0-dev"), "{ok:?}");
+        assert!(ok.contains(&"1 of 1 libraries resolve"), "{ok:?}");
+        assert!(
+            ok.contains(&format!("{INCLUDE_PATHS_VARIABLE} is set by the environment").as_str()),
+            "{ok:?}"
+        );
+        assert_eq!(messages(&findings, Severity::Warning), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn diagnoses_missing_headers_directories_and_libraries() {
+        let missing = fixture("doctor/missing");
+        let findings = diagnose(&config(
+            &[fixture("doctor/include"), missing.clone()],
+            &["opencv_core", "doctor_runtime_only", "dylib=opencv_core"],
+        ));
+
+        let errors: Vec<_> = findings
+            .iter()
+            .filter(|finding| finding.severity == Severity::Error)
+            .collect();
+        assert_eq!(errors.len(), 4, "{errors:#?}");
+        assert_eq!(
+            errors[0].message,
+            format!(
+                "{} in {INCLUDE_PATHS_VARIABLE} is not a directory",
+                missing.display()
+            )
+        );
+        assert_eq!(
+            errors[1].suggestion,
+            Some(format!(
+                "add {} to {INCLUDE_PATHS_VARIABLE}",
+                fixture("doctor/include/opencv4").display()
+            ))
+        );
+        assert!(errors[2]
+            .message
+            .starts_with("doctor_runtime_only is only installed as"));
+        assert_eq!(
+            errors[3].message,
+            format!("opencv_core in {LINK_LIBS_VARIABLE} does not resolve to a shared library")
+        );
+    }
+}
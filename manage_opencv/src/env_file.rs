@@ -0,0 +1,175 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+/// The formats that environment variables are written in, for tools other than Cargo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvFormat {
+    /// `export VARIABLE='value'` lines for a POSIX shell to `eval` or `source`
+    Shell,
+    /// `VARIABLE="value"` lines of a `.env` file, as IDEs and dotenv libraries read them
+    Dotenv,
+    /// A JSON object of the variables
+    Json,
+    /// A direnv `.envrc`, which exports the variables and reloads when `watched` changes
+    Direnv,
+    /// `VARIABLE=value` lines for the `GITHUB_ENV` file of GitHub Actions
+    GithubEnv,
+}
+
+/// Writes `variables` as names and values in `format`. `watched` is the file that the values were
+/// read from, which direnv watches for changes.
+///
+/// # Errors
+///
+/// * `io::Error` if `out` fails.
+pub fn write_variables(
+    format: EnvFormat,
+    variables: &[(String, String)],
+    watched: Option<&Path>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    match format {
+        EnvFormat::Shell => {
+            for (name, value) in variables {
+                writeln!(out, "export {name}={}", shell_quote(value))?;
+            }
+        }
+        EnvFormat::Direnv => {
+            if let Some(path) = watched {
+                writeln!(
+                    out,
+                    "watch_file {}",
+                    shell_quote(&path.display().to_string())
+                )?;
+            }
+            for (name, value) in variables {
+                writeln!(out, "export {name}={}", shell_quote(value))?;
+            }
+        }
+        EnvFormat::Dotenv => {
+            for (name, value) in variables {
+                writeln!(out, "{name}={}", dotenv_quote(value))?;
+            }
+        }
+        EnvFormat::Json => {
+            let object: serde_json::Map<_, _> = variables
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone().into()))
+                .collect();
+            serde_json::to_writer_pretty(&mut *out, &object)?;
+            writeln!(out)?;
+        }
+        EnvFormat::GithubEnv => {
+            for (name, value) in variables {
+                if value.contains('\n') {
+                    // multiline values need a delimiter that does not occur in them
+                    let mut delimiter = String::from("EOF");
+                    while value.lines().any(|line| line == delimiter) {
+                        delimiter.push('_');
+                    }
+                    writeln!(out, "{name}<<{delimiter}\n{value}\n{delimiter}")?;
+                } else {
+                    writeln!(out, "{name}={value}")?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `value` in single quotes, in which a shell expands nothing.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r#"'\''"#))
+}
+
+/// `value` in double quotes, with the characters that dotenv parsers expand escaped.
+fn dotenv_quote(value: &str) -> String {
+    let escaped = value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('$', "\\$")
+        .replace('\n', r"\n");
+    format!("\"{escaped}\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(format: EnvFormat, variables: &[(&str, &str)], watched: Option<&Path>) -> String {
+        let variables: Vec<_> = variables
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let mut out = Vec::new();
+        write_variables(format, &variables, watched, &mut out)
+            .expect("writing to a Vec should succeed");
+        String::from_utf8(out).expect("output should be UTF-8")
+    }
+
+    #[test]
+    fn shell_quotes_single_quotes() {
+        assert_eq!(shell_quote("/opt/lib"), "'/opt/lib'");
+        assert_eq!(shell_quote("it's $HOME"), r#"'it'\''s $HOME'"#);
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn dotenv_escapes_expansions_quotes_and_newlines() {
+        assert_eq!(dotenv_quote("/opt/lib"), "\"/opt/lib\"");
+        assert_eq!(dotenv_quote("a$b\"c\\d\ne"), r#""a\$b\"c\\d\ne""#);
+    }
+
+    #[test]
+    fn writes_shell_and_direnv_exports() {
+        let variables = [("OPENCV_LINK_LIBS", "opencv_core,z")];
+        assert_eq!(
+            written(EnvFormat::Shell, &variables, None),
+            "export OPENCV_LINK_LIBS='opencv_core,z'\n"
+        );
+        assert_eq!(
+            written(
+                EnvFormat::Direnv,
+                &variables,
+                Some(Path::new("build/opencv_envs.toml"))
+            ),
+            "watch_file 'build/opencv_envs.toml'\nexport OPENCV_LINK_LIBS='opencv_core,z'\n"
+        );
+    }
+
+    #[test]
+    fn writes_json() {
+        let json = written(
+            EnvFormat::Json,
+            &[
+                ("OPENCV_LINK_LIBS", "opencv_core"),
+                ("QUOTED", "say \"hi\""),
+            ],
+            None,
+        );
+        assert!(json.ends_with("}\n"), "{json}");
+        let object: serde_json::Value = serde_json::from_str(&json).expect("should be JSON");
+        assert_eq!(
+            object,
+            serde_json::json!({"OPENCV_LINK_LIBS": "opencv_core", "QUOTED": "say \"hi\""})
+        );
+    }
+
+    #[test]
+    fn github_env_delimiters_avoid_the_value() {
+        assert_eq!(
+            written(EnvFormat::GithubEnv, &[("SINGLE", "a=b")], None),
+            "SINGLE=a=b\n"
+        );
+        assert_eq!(
+            written(EnvFormat::GithubEnv, &[("MULTI", "a\nb")], None),
+            "MULTI<<EOF\na\nb\nEOF\n"
+        );
+        assert_eq!(
+            written(EnvFormat::GithubEnv, &[("MULTI", "EOF\nx\nEOF_")], None),
+            "MULTI<<EOF__\nEOF\nx\nEOF_\nEOF__\n"
+        );
+    }
+}
@@ -0,0 +1,15 @@
+pub mod build_info;
+pub mod build_script;
+pub mod cargo_config;
+pub mod cmake;
+pub mod conan;
+pub mod conan_build_info;
+pub mod conanfile;
+pub mod discovery;
+pub mod doctor;
+pub mod env_file;
+pub mod link_order;
+pub mod modules;
+pub mod pkg_config;
+pub mod setup;
+pub mod vcpkg;
@@ -0,0 +1,416 @@
+use crate::doctor::{resolve_library, LinkKind};
+use goblin::{
+    archive::Archive,
+    elf::{section_header::SHN_UNDEF, sym, Elf},
+};
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// How the libraries are ordered for the linker.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LinkOrdering {
+    /// The order of the packages and their build information
+    #[default]
+    Keep,
+    /// Every static library before the libraries that define the symbols it uses, read from the
+    /// ELF objects of the archives, with the libraries of a cycle repeated
+    Symbols,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LinkOrderError {
+    #[error("could not read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("{path} is not a valid archive: {source}")]
+    Archive {
+        path: PathBuf,
+        #[source]
+        source: goblin::error::Error,
+    },
+}
+
+/// The global symbols of the ELF objects in a static archive.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArchiveSymbols {
+    pub defined: HashSet<String>,
+    /// Symbols that members use but no member of the archive defines.
+    pub undefined: HashSet<String>,
+}
+
+impl ArchiveSymbols {
+    /// Reads the symbol tables of the ELF members of the archive at `path`. Members that are not
+    /// ELF objects, such as the symbol index, are skipped.
+    ///
+    /// # Errors
+    ///
+    /// * `LinkOrderError::Io` if the file cannot be read.
+    /// * `LinkOrderError::Archive` if it is not an `ar` archive.
+    pub fn load(path: &Path) -> Result<Self, LinkOrderError> {
+        let bytes = fs::read(path).map_err(|source| LinkOrderError::Io {
+            path: path.into(),
+            source,
+        })?;
+        let archive = Archive::parse(&bytes).map_err(|source| LinkOrderError::Archive {
+            path: path.into(),
+            source,
+        })?;
+
+        let mut symbols = Self::default();
+        let mut used = HashSet::new();
+        for index in 0..archive.len() {
+            let member = archive
+                .get_at(index)
+                .expect("index should be within the archive");
+            #[allow(clippy::cast_possible_truncation)]
+            let start = member.offset as usize;
+            let Some(object) = bytes.get(start..start + member.size()) else {
+                continue;
+            };
+            let Ok(elf) = Elf::parse(object) else {
+                continue;
+            };
+            for symbol in elf.syms.iter() {
+                let binding = symbol.st_bind();
+                if binding != sym::STB_GLOBAL && binding != sym::STB_WEAK {
+                    continue;
+                }
+                let Some(name) = elf.strtab.get_at(symbol.st_name).filter(|n| !n.is_empty()) else {
+                    continue;
+                };
+                if symbol.st_shndx != SHN_UNDEF as usize {
+                    symbols.defined.insert(name.to_string());
+                } else if binding == sym::STB_GLOBAL {
+                    // weak references may stay unresolved, so they do not require anything
+                    used.insert(name.to_string());
+                }
+            }
+        }
+        symbols.undefined = used.difference(&symbols.defined).cloned().collect();
+        Ok(symbols)
+    }
+
+    /// Whether this archive uses a symbol that `other` defines.
+    #[must_use]
+    pub fn requires(&self, other: &Self) -> bool {
+        self.undefined
+            .iter()
+            .any(|symbol| other.defined.contains(symbol))
+    }
+}
+
+/// Libraries in link order, as groups that require each other in a cycle. A group of one library
+/// is not part of a cycle.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkOrder {
+    pub groups: Vec<Vec<String>>,
+}
+
+impl LinkOrder {
+    /// The libraries in link order. With `repeat_cycles`, the libraries of a cycle are listed a
+    /// second time, which lets a single-pass linker resolve the symbols they need from each other
+    /// in most cases, where linker groups are not available.
+    #[must_use]
+    pub fn libs(&self, repeat_cycles: bool) -> Vec<String> {
+        self.groups
+            .iter()
+            .flat_map(|group| {
+                let times = if repeat_cycles && group.len() > 1 {
+                    2
+                } else {
+                    1
+                };
+                group.iter().cycle().take(group.len() * times).cloned()
+            })
+            .collect()
+    }
+
+    /// Groups of more than one library.
+    pub fn cycles(&self) -> impl Iterator<Item = &[String]> {
+        self.groups
+            .iter()
+            .filter(|group| group.len() > 1)
+            .map(Vec::as_slice)
+    }
+}
+
+/// Orders `libs` so that every static library comes before the libraries that define the symbols
+/// it uses, as a single-pass linker needs them. Libraries that require each other form a group.
+/// Otherwise the order of `libs` is kept. Libraries without a static archive in `lib_dirs`, such
+/// as shared and system libraries, go last.
+///
+/// # Errors
+///
+/// * The errors of [`ArchiveSymbols::load`].
+pub fn resolve_link_order(
+    libs: &[String],
+    lib_dirs: &[String],
+) -> Result<LinkOrder, LinkOrderError> {
+    let dirs: Vec<_> = lib_dirs.iter().map(PathBuf::from).collect();
+    let mut archives = Vec::new();
+    let mut others = Vec::new();
+    for lib in libs {
+        match resolve_library(lib, LinkKind::Static, &dirs) {
+            Some(path) => archives.push((lib.clone(), ArchiveSymbols::load(&path)?)),
+            None => others.push(lib.clone()),
+        }
+    }
+
+    // edges[a] contains b if a uses a symbol that b defines
+    let edges: Vec<Vec<usize>> = archives
+        .iter()
+        .enumerate()
+        .map(|(a, (_, symbols))| {
+            archives
+                .iter()
+                .enumerate()
+                .filter(|(b, (_, other))| *b != a && symbols.requires(other))
+                .map(|(b, _)| b)
+                .collect()
+        })
+        .collect();
+
+    let mut groups: Vec<Vec<String>> = order_components(&edges)
+        .into_iter()
+        .map(|component| {
+            component
+                .into_iter()
+                .map(|index| archives[index].0.clone())
+                .collect()
+        })
+        .collect();
+    groups.extend(others.into_iter().map(|lib| vec![lib]));
+    Ok(LinkOrder { groups })
+}
+
+/// The strongly connected components of the graph, each sorted by index, with every component
+/// before the components it has edges to, and otherwise by their smallest index.
+fn order_components(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let components = strongly_connected_components(edges);
+    let mut component_of = vec![0; edges.len()];
+    for (component, members) in components.iter().enumerate() {
+        for &member in members {
+            component_of[member] = component;
+        }
+    }
+
+    // Kahn's algorithm, taking the ready component with the smallest first member
+    let mut incoming = vec![0; components.len()];
+    let mut successors = vec![HashSet::new(); components.len()];
+    for (from, targets) in edges.iter().enumerate() {
+        for &to in targets {
+            let (from, to) = (component_of[from], component_of[to]);
+            if from != to && successors[from].insert(to) {
+                incoming[to] += 1;
+            }
+        }
+    }
+    let mut ordered = Vec::with_capacity(components.len());
+    let mut done = vec![false; components.len()];
+    while let Some(next) = (0..components.len())
+        .filter(|&c| !done[c] && incoming[c] == 0)
+        .min_by_key(|&c| components[c][0])
+    {
+        done[next] = true;
+        for &successor in &successors[next] {
+            incoming[successor] -= 1;
+        }
+        ordered.push(components[next].clone());
+    }
+    ordered
+}
+
+/// Tarjan's algorithm, with the members of every component sorted.
+fn strongly_connected_components(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    struct State<'a> {
+        edges: &'a [Vec<usize>],
+        index: Vec<Option<usize>>,
+        low: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        next_index: usize,
+        components: Vec<Vec<usize>>,
+    }
+
+    fn visit(state: &mut State, node: usize) {
+        state.index[node] = Some(state.next_index);
+        state.low[node] = state.next_index;
+        state.next_index += 1;
+        state.stack.push(node);
+        state.on_stack[node] = true;
+        for &next in &state.edges[node] {
+            match state.index[next] {
+                None => {
+                    visit(state, next);
+                    state.low[node] = state.low[node].min(state.low[next]);
+                }
+                Some(index) if state.on_stack[next] => {
+                    state.low[node] = state.low[node].min(index);
+                }
+                Some(_) => {}
+            }
+        }
+        if Some(state.low[node]) == state.index[node] {
+            let mut component = Vec::new();
+            while let Some(member) = state.stack.pop() {
+                state.on_stack[member] = false;
+                component.push(member);
+                if member == node {
+                    break;
+                }
+            }
+            component.sort_unstable();
+            state.components.push(component);
+        }
+    }
+
+    let mut state = State {
+        edges,
+        index: vec![None; edges.len()],
+        low: vec![0; edges.len()],
+        on_stack: vec![false; edges.len()],
+        stack: Vec::new(),
+        next_index: 0,
+        components: Vec::new(),
+    };
+    for node in 0..edges.len() {
+        if state.index[node].is_none() {
+            visit(&mut state, node);
+        }
+    }
+    state.components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(path)
+    }
+
+    fn strings(strings: &[&str]) -> Vec<String> {
+        strings.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn components_of_chains_and_cycles() {
+        assert_eq!(
+            strongly_connected_components(&[vec![1], vec![2], vec![]]),
+            [vec![2], vec![1], vec![0]]
+        );
+        assert_eq!(
+            strongly_connected_components(&[vec![1], vec![2], vec![0]]),
+            [vec![0, 1, 2]]
+        );
+        assert_eq!(
+            strongly_connected_components(&[vec![1], vec![0], vec![0]]),
+            [vec![0, 1], vec![2]]
+        );
+    }
+
+    #[test]
+    fn orders_users_before_definitions() {
+        // chain
+        assert_eq!(
+            order_components(&[vec![1], vec![2], vec![]]),
+            [vec![0], vec![1], vec![2]]
+        );
+        assert_eq!(
+            order_components(&[vec![], vec![0], vec![1]]),
+            [vec![2], vec![1], vec![0]]
+        );
+        // cycle, with a library that uses it
+        assert_eq!(
+            order_components(&[vec![2], vec![0], vec![1], vec![]]),
+            [vec![0, 1, 2], vec![3]]
+        );
+        assert_eq!(
+            order_components(&[vec![1], vec![2], vec![1]]),
+            [vec![0], vec![1, 2]]
+        );
+        // diamond
+        assert_eq!(
+            order_components(&[vec![1, 2], vec![3], vec![3], vec![]]),
+            [vec![0], vec![1], vec![2], vec![3]]
+        );
+    }
+
+    #[test]
+    fn keeps_the_order_of_independent_libraries() {
+        assert_eq!(
+            order_components(&[vec![], vec![], vec![]]),
+            [vec![0], vec![1], vec![2]]
+        );
+        assert_eq!(
+            order_components(&[vec![], vec![], vec![0]]),
+            [vec![1], vec![2], vec![0]]
+        );
+        assert!(order_components(&[]).is_empty());
+    }
+
+    #[test]
+    fn repeats_cycles_on_request() {
+        let order = LinkOrder {
+            groups: vec![strings(&["a"]), strings(&["b", "c"]), strings(&["d"])],
+        };
+
+        assert_eq!(order.libs(false), strings(&["a", "b", "c", "d"]));
+        assert_eq!(order.libs(true), strings(&["a", "b", "c", "b", "c", "d"]));
+        assert_eq!(order.cycles().collect::<Vec<_>>(), [strings(&["b", "c"])]);
+    }
+
+    #[test]
+    fn reads_the_symbols_of_an_archive() {
+        let symbols = ArchiveSymbols::load(&fixture("link_order/libimgproc.a"))
+            .expect("fixture should be an archive");
+
+        assert_eq!(symbols.defined, HashSet::from(["imgproc".to_string()]));
+        assert!(symbols.undefined.contains("core"));
+        // weak references do not require anything
+        assert!(!symbols.undefined.contains("optional"));
+
+        let core = ArchiveSymbols::load(&fixture("link_order/libcore.a"))
+            .expect("fixture should be an archive");
+        assert!(symbols.requires(&core));
+        assert!(!core.requires(&symbols));
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_archives() {
+        let path = fixture("link_order/core.c");
+        assert!(matches!(
+            ArchiveSymbols::load(&path),
+            Err(LinkOrderError::Archive { .. })
+        ));
+        assert!(matches!(
+            ArchiveSymbols::load(&fixture("link_order/missing.a")),
+            Err(LinkOrderError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn resolves_the_order_of_the_fixture_archives() {
+        let dirs = [fixture("link_order").display().to_string()];
+        let order = resolve_link_order(&strings(&["alloc", "imgproc", "core", "m"]), &dirs)
+            .expect("fixtures should be archives");
+
+        // imgproc uses core, and core and alloc use each other
+        assert_eq!(
+            order.groups,
+            [
+                strings(&["imgproc"]),
+                strings(&["alloc", "core"]),
+                strings(&["m"])
+            ]
+        );
+    }
+}
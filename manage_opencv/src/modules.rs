@@ -0,0 +1,639 @@
+use crate::{
+    conanfile::ConanfileFormat,
+    doctor::{Finding, LinkConfig, LINK_LIBS_VARIABLE},
+    vcpkg::library_name,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+use toml_edit::{Document, Item, TableLike};
+
+/// The name of the `opencv` crate on crates.io.
+const OPENCV_CRATE: &str = "opencv";
+/// Features of the `opencv` crate that are not OpenCV modules.
+const NON_MODULE_FEATURES: [&str; 2] = ["clang-runtime", "rgb"];
+/// Modules of the main OpenCV repository. All other modules are in `opencv_contrib`.
+pub const MAIN_MODULES: [&str; 15] = [
+    "calib3d",
+    "core",
+    "dnn",
+    "features2d",
+    "flann",
+    "gapi",
+    "highgui",
+    "imgcodecs",
+    "imgproc",
+    "ml",
+    "objdetect",
+    "photo",
+    "stitching",
+    "video",
+    "videoio",
+];
+/// Header that defines `HAVE_OPENCV_<MODULE>` for every module of the build, relative to an
+/// include directory.
+const MODULES_HEADER: &str = "opencv2/opencv_modules.hpp";
+const DEPENDENCY_SECTIONS: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum ModulesError {
+    #[error("could not read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("{path} is not valid TOML: {source}")]
+    Invalid {
+        path: PathBuf,
+        #[source]
+        source: toml_edit::TomlError,
+    },
+}
+
+/// The OpenCV modules that a crate of the workspace enables as features of the `opencv` crate.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModuleUse {
+    pub manifest: PathBuf,
+    pub modules: BTreeSet<String>,
+    /// Whether the default features are enabled, which are all modules the crate supports.
+    pub default_features: bool,
+}
+
+/// Reads the `opencv` dependencies of the workspace at `root`: its root package and members,
+/// including those of `[target.<cfg>]` sections and those inherited from
+/// `[workspace.dependencies]`. Crates without an `opencv` dependency are left out.
+///
+/// # Errors
+///
+/// * `ModulesError::Io` if a manifest cannot be read.
+/// * `ModulesError::Invalid` if a manifest is not TOML.
+pub fn workspace_modules(root: &Path) -> Result<Vec<ModuleUse>, ModulesError> {
+    let root_manifest = root.join("Cargo.toml");
+    let workspace = read_manifest(&root_manifest)?;
+    let inherited = workspace
+        .get("workspace")
+        .and_then(|workspace| workspace.get("dependencies"))
+        .and_then(Item::as_table_like)
+        .and_then(|dependencies| opencv_dependency(dependencies))
+        .map(|dependency| features(dependency.1));
+
+    let mut manifests = Vec::new();
+    if workspace.contains_key("package") {
+        manifests.push(root_manifest);
+    }
+    let members = workspace
+        .get("workspace")
+        .and_then(|workspace| workspace.get("members"))
+        .and_then(Item::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|member| member.as_str());
+    for member in members {
+        match member.strip_suffix("/*") {
+            // the common glob of a directory of crates, e.g. `crates/*`
+            Some(parent) => {
+                let parent = root.join(parent);
+                let entries = fs::read_dir(&parent).map_err(|source| ModulesError::Io {
+                    path: parent.clone(),
+                    source,
+                })?;
+                let mut found: Vec<_> = entries
+                    .filter_map(Result::ok)
+                    .map(|entry| entry.path().join("Cargo.toml"))
+                    .filter(|manifest| manifest.is_file())
+                    .collect();
+                found.sort();
+                manifests.extend(found);
+            }
+            None => manifests.push(root.join(member).join("Cargo.toml")),
+        }
+    }
+
+    let mut uses = Vec::new();
+    for manifest in manifests {
+        let document = read_manifest(&manifest)?;
+        let mut module_use = ModuleUse {
+            manifest,
+            ..ModuleUse::default()
+        };
+        let mut found = false;
+        for dependencies in dependency_tables(&document) {
+            let Some((_, dependency)) = opencv_dependency(dependencies) else {
+                continue;
+            };
+            found = true;
+            let (mut modules, mut default_features) = features(dependency);
+            if dependency.get("workspace").and_then(Item::as_bool) == Some(true) {
+                if let Some((workspace_modules, workspace_default)) = &inherited {
+                    modules.extend(workspace_modules.iter().cloned());
+                    default_features = default_features && *workspace_default;
+                }
+            }
+            module_use.modules.extend(modules);
+            module_use.default_features |= default_features;
+        }
+        if found {
+            uses.push(module_use);
+        }
+    }
+    Ok(uses)
+}
+
+fn read_manifest(path: &Path) -> Result<Document, ModulesError> {
+    let contents = fs::read_to_string(path).map_err(|source| ModulesError::Io {
+        path: path.into(),
+        source,
+    })?;
+    contents.parse().map_err(|source| ModulesError::Invalid {
+        path: path.into(),
+        source,
+    })
+}
+
+/// The dependency tables of a manifest, including those of `[target.<cfg>]`.
+fn dependency_tables(document: &Document) -> Vec<&dyn TableLike> {
+    let targets = document
+        .get("target")
+        .and_then(Item::as_table_like)
+        .into_iter()
+        .flat_map(TableLike::iter)
+        .filter_map(|(_, target)| target.as_table_like());
+    std::iter::once(document.as_table() as &dyn TableLike)
+        .chain(targets)
+        .flat_map(|table| {
+            DEPENDENCY_SECTIONS
+                .iter()
+                .filter_map(|section| table.get(section).and_then(Item::as_table_like))
+        })
+        .collect()
+}
+
+/// The `opencv` dependency of `dependencies`, also when it is renamed with `package`.
+fn opencv_dependency(dependencies: &dyn TableLike) -> Option<(&str, &Item)> {
+    dependencies.iter().find(|(name, dependency)| {
+        let package = dependency.get("package").and_then(Item::as_str);
+        package.unwrap_or(name) == OPENCV_CRATE
+    })
+}
+
+/// The modules among the features of a dependency, and whether it uses the default features.
+fn features(dependency: &Item) -> (BTreeSet<String>, bool) {
+    let modules = dependency
+        .get("features")
+        .and_then(Item::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|feature| feature.as_str())
+        .filter(|feature| !NON_MODULE_FEATURES.contains(feature))
+        .map(String::from)
+        .collect();
+    let default_features = dependency
+        .get("default-features")
+        .or_else(|| dependency.get("default_features"))
+        .and_then(Item::as_bool)
+        .unwrap_or(true);
+    (modules, default_features)
+}
+
+/// The OpenCV modules of an installation, as far as its headers and libraries tell.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InstalledModules {
+    /// Modules of the `HAVE_OPENCV_*` defines, or of the module headers without them.
+    pub headers: BTreeSet<String>,
+    /// Modules with a library in the link directories.
+    pub libraries: BTreeSet<String>,
+    /// Modules in `OPENCV_LINK_LIBS`.
+    pub linked: BTreeSet<String>,
+    /// Whether any of the `OPENCV_*` variables is set. Without them the installation is unknown.
+    pub configured: bool,
+}
+
+impl InstalledModules {
+    /// Inspects the include directories, link directories and libraries of `config`.
+    #[must_use]
+    pub fn find(config: &LinkConfig) -> Self {
+        let include_dirs = config
+            .include_paths
+            .iter()
+            .flat_map(|dir| [dir.clone(), dir.join("opencv4")]);
+        let mut headers = BTreeSet::new();
+        for dir in include_dirs {
+            if let Ok(contents) = fs::read_to_string(dir.join(MODULES_HEADER)) {
+                headers.extend(defined_modules(&contents));
+            } else if let Ok(entries) = fs::read_dir(dir.join("opencv2")) {
+                headers.extend(entries.filter_map(Result::ok).filter_map(|entry| {
+                    let file_name = entry.file_name().to_string_lossy().into_owned();
+                    let module = file_name.strip_suffix(".hpp")?;
+                    let is_module = module != "opencv" && entry.path().with_extension("").is_dir();
+                    is_module.then(|| module.to_string())
+                }));
+            }
+        }
+
+        let libraries = config
+            .link_paths
+            .iter()
+            .filter_map(|dir| fs::read_dir(dir).ok())
+            .flat_map(|entries| entries.filter_map(Result::ok))
+            .filter_map(|entry| library_name(&entry.file_name().to_string_lossy()))
+            .filter_map(|name| module_of_library(&name))
+            .collect();
+        let linked = config
+            .link_libs
+            .iter()
+            .filter_map(|(_, lib)| module_of_library(lib))
+            .collect();
+        Self {
+            headers,
+            libraries,
+            linked,
+            configured: !(config.include_paths.is_empty()
+                && config.link_paths.is_empty()
+                && config.link_libs.is_empty()),
+        }
+    }
+}
+
+/// The modules of the `#define HAVE_OPENCV_<MODULE>` lines of `opencv2/opencv_modules.hpp`.
+#[must_use]
+pub fn defined_modules(contents: &str) -> BTreeSet<String> {
+    contents
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("#define HAVE_OPENCV_"))
+        .filter_map(|rest| rest.split_whitespace().next())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// The module of a library such as `opencv_core`, `opencv_core4` of vcpkg or `opencv_core455`
+/// of Windows.
+fn module_of_library(name: &str) -> Option<String> {
+    let module = name.strip_prefix("opencv_")?;
+    Some(module.trim_end_matches(|c: char| c.is_ascii_digit()).into())
+}
+
+/// The options of the `opencv` package in the `[options]` of a `conanfile.txt`, or the
+/// `default_options` of a `conanfile.py` as `render` writes them, with the pattern they were
+/// given for, e.g. `opencv` of Conan 1 or `opencv/*` of Conan 2.
+#[must_use]
+pub fn conanfile_options(
+    contents: &str,
+    format: ConanfileFormat,
+) -> BTreeMap<String, (String, String)> {
+    let mut options = BTreeMap::new();
+    let mut in_options = false;
+    for line in contents.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let option = match format {
+            ConanfileFormat::Txt => {
+                if line.starts_with('[') {
+                    in_options = line == "[options]";
+                    continue;
+                }
+                line.split_once('=')
+            }
+            ConanfileFormat::Py => {
+                if line.starts_with("default_options") {
+                    in_options = true;
+                    continue;
+                }
+                if line.starts_with('}') {
+                    in_options = false;
+                }
+                // e.g. `"opencv/*:gapi": True,`
+                line.strip_prefix('"')
+                    .and_then(|line| line.split_once("\":"))
+                    .map(|(key, value)| (key, value.trim().trim_end_matches(',').trim_matches('"')))
+            }
+        };
+        let Some((pattern_and_option, value)) = option.filter(|_| in_options) else {
+            continue;
+        };
+        let Some((pattern, option)) = pattern_and_option.trim().rsplit_once(':') else {
+            continue;
+        };
+        if pattern == OPENCV_CRATE || pattern.starts_with("opencv/") {
+            options.insert(option.into(), (pattern.into(), value.trim().into()));
+        }
+    }
+    options
+}
+
+/// The Conan option that builds `module`: the option of the module itself, or `contrib` for
+/// the modules of `opencv_contrib`.
+fn conan_option(module: &str) -> &str {
+    if MAIN_MODULES.contains(&module) {
+        module
+    } else {
+        "contrib"
+    }
+}
+
+/// Compares the modules that the crates of the workspace enable with those of the installation
+/// and suggests the settings of the `[conanfile]` section that build the missing ones. `options`
+/// are those of [`conanfile_options`].
+#[must_use]
+pub fn reconcile(
+    uses: &[ModuleUse],
+    installed: &InstalledModules,
+    options: &BTreeMap<String, (String, String)>,
+) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut wanted: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for module_use in uses {
+        let crate_name = module_use
+            .manifest
+            .parent()
+            .and_then(Path::file_name)
+            .map_or_else(
+                || module_use.manifest.display().to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            );
+        if module_use.default_features {
+            findings.push(Finding::warning(
+                format!("{crate_name} enables the default features of opencv, i.e. every module"),
+                "set `default-features = false` and list the modules it uses as features",
+            ));
+        }
+        for module in &module_use.modules {
+            wanted.entry(module).or_default().push(crate_name.clone());
+        }
+    }
+    if !installed.configured {
+        findings.push(Finding::error(
+            "the OpenCV installation is unknown, as none of the OPENCV_* variables is set",
+            "run `manage_opencv envs` and `manage_opencv apply` first",
+        ));
+        return findings;
+    }
+
+    // the conanfile is generated from manage_opencv.toml, so that is where options are changed
+    let suggest_option = |module: &str, action: &str| {
+        let option = conan_option(module);
+        match options.get(option) {
+            Some((pattern, value)) if value == "True" => format!(
+                "`{pattern}:{option}=True` is set in the conanfile already, so build OpenCV \
+                 again with `manage_opencv install -- --build=opencv`"
+            ),
+            Some((pattern, value)) => format!(
+                "set `{option} = true` in the [conanfile.options.opencv] of manage_opencv.toml \
+                 instead of `{pattern}:{option}={value}`, and {action}"
+            ),
+            None => format!(
+                "set `module_options = true` in the [conanfile] of manage_opencv.toml, or \
+                 `{option} = true` in its [conanfile.options.opencv], and {action}"
+            ),
+        }
+    };
+    // `opencv_world` contains all modules of a build with `BUILD_opencv_world`
+    let world = installed.linked.contains("world");
+
+    for (module, crates) in &wanted {
+        let crates = crates.join(", ");
+        let has_header = installed.headers.contains(*module);
+        let has_library = installed.libraries.contains(*module) || world;
+        let is_linked = installed.linked.contains(*module) || world;
+        findings.push(match (has_header, has_library, is_linked) {
+            (true, _, true) => Finding::ok(format!("{module} is installed and linked")),
+            (false, false, false) => Finding::error(
+                format!("{module} is enabled by {crates}, but OpenCV was built without it"),
+                suggest_option(module, "run `manage_opencv setup`"),
+            ),
+            (true, true, false) => Finding::error(
+                format!("{module} is enabled by {crates}, but not in {LINK_LIBS_VARIABLE}"),
+                "check `link.packages` and `link.exclude` of manage_opencv.toml and run \
+                 `manage_opencv envs` and `manage_opencv apply`",
+            ),
+            (true, false, false) => Finding::error(
+                format!("{module} is enabled by {crates}, but has headers and no library"),
+                suggest_option(module, "run `manage_opencv setup` to rebuild OpenCV"),
+            ),
+            (false, _, _) => Finding::error(
+                format!("{module} is enabled by {crates}, but has no headers"),
+                "add the include directory of OpenCV to OPENCV_INCLUDE_PATHS",
+            ),
+        });
+    }
+
+    for module in &installed.linked {
+        if module != "core" && module != "world" && !wanted.contains_key(module.as_str()) {
+            findings.push(Finding::ok(format!(
+                "{module} is linked, but no crate enables it; `{} = false` in the \
+                 [conanfile.options.opencv] of manage_opencv.toml would leave it out",
+                conan_option(module)
+            )));
+        }
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        conanfile::{render, ConanfileSpec, PackageOption},
+        doctor::Severity,
+    };
+
+    fn fixture(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(path)
+    }
+
+    fn set(modules: &[&str]) -> BTreeSet<String> {
+        modules.iter().map(ToString::to_string).collect()
+    }
+
+    fn module_use(name: &str, modules: &[&str]) -> ModuleUse {
+        ModuleUse {
+            manifest: Path::new(name).join("Cargo.toml"),
+            modules: set(modules),
+            default_features: false,
+        }
+    }
+
+    fn option(pattern: &str, option: &str, value: &str) -> (String, (String, String)) {
+        (option.into(), (pattern.into(), value.into()))
+    }
+
+    #[test]
+    fn reads_the_modules_header() {
+        let header = "#ifndef OPENCV_MODULES_HPP\n\
+                      #define HAVE_OPENCV_CORE\n\
+                      #define HAVE_OPENCV_IMGPROC\n  \
+                      #define HAVE_OPENCV_XFEATURES2D  \n\
+                      #define OPENCV_MODULES_HPP\n";
+        assert_eq!(
+            defined_modules(header),
+            set(&["core", "imgproc", "xfeatures2d"])
+        );
+    }
+
+    #[test]
+    fn tells_the_module_of_a_library() {
+        assert_eq!(module_of_library("opencv_core").as_deref(), Some("core"));
+        assert_eq!(
+            module_of_library("opencv_imgproc4").as_deref(),
+            Some("imgproc")
+        );
+        assert_eq!(module_of_library("opencv_core455").as_deref(), Some("core"));
+        assert_eq!(module_of_library("opencv_world").as_deref(), Some("world"));
+        assert_eq!(module_of_library("z"), None);
+    }
+
+    #[test]
+    fn reads_the_opencv_options_of_either_conanfile() {
+        let txt = "[requires]\nopencv/4.5.5\n\n[options]\n# comment\nopencv:gapi=False\n\
+                   opencv:contrib = True # for xfeatures2d\nfreetype:with_brotli=False\n";
+        assert_eq!(
+            conanfile_options(txt, ConanfileFormat::Txt),
+            BTreeMap::from([
+                option("opencv", "contrib", "True"),
+                option("opencv", "gapi", "False")
+            ])
+        );
+
+        let spec = ConanfileSpec {
+            format: ConanfileFormat::Py,
+            options: vec![
+                PackageOption::new("opencv", "gapi", "False"),
+                PackageOption::new("opencv", "parallel", "tbb"),
+                PackageOption::new("freetype", "with_brotli", "False"),
+            ],
+            ..ConanfileSpec::default()
+        };
+        let py = render(&spec, &BTreeSet::new(), 2);
+        assert_eq!(
+            conanfile_options(&py, ConanfileFormat::Py),
+            BTreeMap::from([
+                option("opencv/*", "gapi", "False"),
+                option("opencv/*", "parallel", "tbb")
+            ])
+        );
+        // the requirements are not options
+        assert!(conanfile_options(&py, ConanfileFormat::Txt).is_empty());
+    }
+
+    #[test]
+    fn reads_the_opencv_features_of_the_workspace() {
+        let root = fixture("workspace");
+        let uses = workspace_modules(&root).expect("fixture should be a workspace");
+
+        assert_eq!(
+            uses,
+            [
+                ModuleUse {
+                    manifest: root.join("Cargo.toml"),
+                    modules: set(&["highgui"]),
+                    default_features: false,
+                },
+                // inherits the features and default features of the workspace
+                ModuleUse {
+                    manifest: root.join("crates/vision/Cargo.toml"),
+                    modules: set(&["core", "imgproc"]),
+                    default_features: false,
+                },
+                // renamed and in a target section
+                ModuleUse {
+                    manifest: root.join("app/Cargo.toml"),
+                    modules: set(&["videoio"]),
+                    default_features: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_an_unknown_installation_once() {
+        let findings = reconcile(
+            &[module_use("lab", &["core", "imgproc", "videoio"])],
+            &InstalledModules::default(),
+            &BTreeMap::new(),
+        );
+
+        assert_eq!(findings.len(), 1, "{findings:#?}");
+        assert_eq!(findings[0].severity, Severity::Error);
+        assert!(findings[0].message.contains("unknown"));
+    }
+
+    #[test]
+    fn reconciles_wanted_and_installed_modules() {
+        let installed = InstalledModules {
+            headers: set(&["core", "imgproc", "videoio"]),
+            libraries: set(&["core", "imgproc"]),
+            linked: set(&["core", "imgproc", "photo"]),
+            configured: true,
+        };
+        let options = BTreeMap::from([option("opencv", "gapi", "False")]);
+        let findings = reconcile(
+            &[
+                module_use("lab", &["imgproc", "gapi", "videoio"]),
+                module_use("features", &["xfeatures2d"]),
+            ],
+            &installed,
+            &options,
+        );
+        let finding = |start: &str| {
+            findings
+                .iter()
+                .find(|finding| finding.message.starts_with(start))
+                .unwrap_or_else(|| panic!("no finding for {start} in {findings:#?}"))
+        };
+
+        assert_eq!(finding("imgproc").severity, Severity::Ok);
+        let gapi = finding("gapi");
+        assert_eq!(
+            gapi.message,
+            "gapi is enabled by lab, but OpenCV was built without it"
+        );
+        assert!(gapi
+            .suggestion
+            .as_deref()
+            .is_some_and(|s| s.contains("`gapi = true`") && s.contains("opencv:gapi=False")));
+        let videoio = finding("videoio");
+        assert_eq!(
+            videoio.message,
+            "videoio is enabled by lab, but has headers and no library"
+        );
+        assert!(videoio
+            .suggestion
+            .as_deref()
+            .is_some_and(|s| s.contains("`module_options = true`")));
+        assert!(finding("xfeatures2d")
+            .suggestion
+            .as_deref()
+            .is_some_and(|s| s.contains("`contrib = true`")));
+        assert_eq!(finding("photo").severity, Severity::Ok);
+        assert_eq!(
+            findings
+                .iter()
+                .filter(|finding| finding.severity == Severity::Error)
+                .count(),
+            3
+        );
+    }
+
+    #[test]
+    fn world_contains_every_module() {
+        let installed = InstalledModules {
+            headers: set(&["core", "imgproc"]),
+            linked: set(&["world"]),
+            configured: true,
+            ..InstalledModules::default()
+        };
+        let findings = reconcile(
+            &[module_use("lab", &["core", "imgproc"])],
+            &installed,
+            &BTreeMap::new(),
+        );
+
+        assert_eq!(findings.len(), 2, "{findings:#?}");
+        assert!(findings
+            .iter()
+            .all(|finding| finding.severity == Severity::Ok));
+    }
+}
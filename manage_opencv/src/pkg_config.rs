@@ -0,0 +1,541 @@
+use crate::build_info::{extend_unique, BuildInfo, Dependencies, Package};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Names of the pkg-config package of OpenCV 4 and of older versions.
+pub const OPENCV_PACKAGES: [&str; 2] = ["opencv4", "opencv"];
+
+/// Directories that pkg-config searches when `PKG_CONFIG_PATH` is not set to something else.
+const SYSTEM_DIRECTORIES: [&str; 6] = [
+    "/usr/local/lib/pkgconfig",
+    "/usr/local/share/pkgconfig",
+    "/usr/lib/x86_64-linux-gnu/pkgconfig",
+    "/usr/lib64/pkgconfig",
+    "/usr/lib/pkgconfig",
+    "/usr/share/pkgconfig",
+];
+
+/// `PKG_CONFIG_PATH` followed by the usual system directories.
+#[must_use]
+pub fn search_path() -> Vec<PathBuf> {
+    env::var_os("PKG_CONFIG_PATH")
+        .map(|path| env::split_paths(&path).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .chain(SYSTEM_DIRECTORIES.iter().map(PathBuf::from))
+        .collect()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PkgConfigError {
+    #[error("could not read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("{path}:{line}: undefined variable `{name}`")]
+    UndefinedVariable {
+        path: PathBuf,
+        line: usize,
+        name: String,
+    },
+    #[error("{path}:{line}: expected `name=value` or `Field: value`")]
+    Syntax { path: PathBuf, line: usize },
+    #[error("package `{name}` is required, but there is no {name}.pc")]
+    MissingPackage { name: String },
+    #[error("no .pc files in {0}")]
+    NoPackages(PathBuf),
+}
+
+/// The fields of a pkg-config `.pc` file, with variables expanded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PcFile {
+    pub variables: HashMap<String, String>,
+    /// Fields such as `Name`, `Version`, `Requires`, `Cflags` and `Libs`.
+    pub fields: HashMap<String, String>,
+}
+
+impl PcFile {
+    /// Parses the contents of `path`.
+    ///
+    /// # Errors
+    ///
+    /// * `PkgConfigError::Syntax` for a line that is neither a variable nor a field.
+    /// * `PkgConfigError::UndefinedVariable` if a value refers to a variable that is not defined
+    ///   above it.
+    pub fn parse(contents: &str, path: &Path) -> Result<Self, PkgConfigError> {
+        let mut file = Self::default();
+        // defined by pkg-config itself, for relocatable packages such as those of vcpkg
+        if let Some(directory) = path.parent() {
+            file.variables
+                .insert("pcfiledir".into(), directory.to_string_lossy().into_owned());
+        }
+        for (index, line) in contents.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split_once('#').map_or(line, |(line, _comment)| line);
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let separator = line
+                .find(['=', ':'])
+                .ok_or_else(|| PkgConfigError::Syntax {
+                    path: path.into(),
+                    line: line_number,
+                })?;
+            let (name, value) = (line[..separator].trim(), &line[separator + 1..]);
+            let value =
+                file.expand(value.trim())
+                    .map_err(|name| PkgConfigError::UndefinedVariable {
+                        path: path.into(),
+                        line: line_number,
+                        name,
+                    })?;
+            if line.as_bytes()[separator] == b'=' {
+                file.variables.insert(name.into(), value);
+            } else {
+                file.fields.insert(name.into(), value);
+            }
+        }
+        Ok(file)
+    }
+
+    /// Reads and parses the file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// * `PkgConfigError::Io` if the file cannot be read.
+    /// * The errors of [`PcFile::parse`].
+    pub fn load(path: &Path) -> Result<Self, PkgConfigError> {
+        let contents = fs::read_to_string(path).map_err(|source| PkgConfigError::Io {
+            path: path.into(),
+            source,
+        })?;
+        Self::parse(&contents, path)
+    }
+
+    /// Replaces `${name}` with the value of the variable, or returns the name of the first
+    /// undefined variable.
+    fn expand(&self, value: &str) -> Result<String, String> {
+        let mut expanded = String::with_capacity(value.len());
+        let mut rest = value;
+        while let Some(start) = rest.find("${") {
+            let Some(end) = rest[start..].find('}') else {
+                // an unterminated reference is kept as is, along with the text before it
+                break;
+            };
+            expanded.push_str(&rest[..start]);
+            let name = &rest[start + 2..start + end];
+            let variable = self.variables.get(name).ok_or_else(|| name.to_string())?;
+            expanded.push_str(variable);
+            rest = &rest[start + end + 1..];
+        }
+        expanded.push_str(rest);
+        Ok(expanded)
+    }
+
+    #[must_use]
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(String::as_str)
+    }
+
+    /// Names of the packages in `Requires`, without version constraints.
+    #[must_use]
+    pub fn requires(&self) -> Vec<String> {
+        let Some(requires) = self.field("Requires") else {
+            return Vec::new();
+        };
+        let mut names = Vec::new();
+        let mut tokens = requires
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|token| !token.is_empty());
+        while let Some(token) = tokens.next() {
+            if ["=", "<", ">", "<=", ">=", "!="].contains(&token) {
+                // skip the version following the operator
+                tokens.next();
+            } else {
+                names.push(token.to_string());
+            }
+        }
+        names
+    }
+
+    /// Include directories, library directories and libraries of this package alone.
+    #[must_use]
+    pub fn build_info(&self) -> BuildInfo {
+        let mut info = BuildInfo::default();
+        for flag in split_flags(self.field("Cflags").unwrap_or_default()) {
+            if let Some(dir) = flag.strip_prefix("-I") {
+                extend_unique(&mut info.include_dirs, [dir.to_string()]);
+            }
+        }
+        for flag in split_flags(self.field("Libs").unwrap_or_default()) {
+            if let Some(dir) = flag.strip_prefix("-L") {
+                extend_unique(&mut info.lib_dirs, [dir.to_string()]);
+            } else if let Some(lib) = flag.strip_prefix("-l") {
+                extend_unique(&mut info.libs, [lib.to_string()]);
+            }
+        }
+        for flag in split_flags(self.field("Libs.private").unwrap_or_default()) {
+            if let Some(lib) = flag.strip_prefix("-l") {
+                extend_unique(&mut info.system_libs, [lib.to_string()]);
+            }
+        }
+        info
+    }
+}
+
+/// Splits flags at whitespace, except within double quotes, and removes the quotes and
+/// backslash escapes.
+fn split_flags(flags: &str) -> Vec<String> {
+    let mut split = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut chars = flags.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' => current.extend(chars.next()),
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    split.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        split.push(current);
+    }
+    split
+}
+
+/// A set of `.pc` files, e.g. those generated by Conan's `PkgConfigDeps`, by package name.
+#[derive(Debug, Clone, Default)]
+pub struct Packages(pub BTreeMap<String, PcFile>);
+
+impl Packages {
+    /// Loads the package `name` and everything it requires, each from the first directory in
+    /// `search_path` that has it.
+    ///
+    /// # Errors
+    ///
+    /// * `PkgConfigError::MissingPackage` if a package is in none of the directories.
+    /// * The errors of [`PcFile::load`].
+    pub fn find(name: &str, search_path: &[PathBuf]) -> Result<Self, PkgConfigError> {
+        let mut packages = BTreeMap::new();
+        let mut queue = vec![name.to_string()];
+        while let Some(name) = queue.pop() {
+            if packages.contains_key(&name) {
+                continue;
+            }
+            let path = search_path
+                .iter()
+                .map(|directory| directory.join(format!("{name}.pc")))
+                .find(|path| path.is_file())
+                .ok_or_else(|| PkgConfigError::MissingPackage { name: name.clone() })?;
+            let file = PcFile::load(&path)?;
+            queue.extend(file.requires());
+            packages.insert(name, file);
+        }
+        Ok(Self(packages))
+    }
+
+    /// Loads OpenCV and everything it requires, as `opencv4`, or as `opencv` if there is no
+    /// `opencv4.pc` anywhere in `search_path`, as OpenCV 3 and older name it.
+    ///
+    /// # Errors
+    ///
+    /// * `PkgConfigError::MissingPackage` for `opencv4` if neither name exists.
+    /// * The errors of [`Packages::find`] otherwise.
+    pub fn find_opencv(search_path: &[PathBuf]) -> Result<Self, PkgConfigError> {
+        let mut error = None;
+        for name in OPENCV_PACKAGES {
+            match Self::find(name, search_path) {
+                Ok(packages) => return Ok(packages),
+                // a missing package is only an error if neither name exists
+                Err(PkgConfigError::MissingPackage { name: missing }) if missing == name => {
+                    error.get_or_insert(PkgConfigError::MissingPackage { name: missing });
+                }
+                Err(other) => return Err(other),
+            }
+        }
+        Err(error.expect("there should be a pkg-config package name"))
+    }
+
+    /// Loads every `.pc` file in `directory`.
+    ///
+    /// # Errors
+    ///
+    /// * `PkgConfigError::NoPackages` if there are none.
+    /// * The errors of [`PcFile::load`].
+    pub fn from_directory(directory: &Path) -> Result<Self, PkgConfigError> {
+        let entries = fs::read_dir(directory).map_err(|source| PkgConfigError::Io {
+            path: directory.into(),
+            source,
+        })?;
+        let mut packages = BTreeMap::new();
+        for entry in entries {
+            let path = entry
+                .map_err(|source| PkgConfigError::Io {
+                    path: directory.into(),
+                    source,
+                })?
+                .path();
+            if path.extension().is_some_and(|extension| extension == "pc") {
+                let name = path
+                    .file_stem()
+                    .expect("a file with an extension should have a stem")
+                    .to_string_lossy()
+                    .into_owned();
+                packages.insert(name, PcFile::load(&path)?);
+            }
+        }
+        if packages.is_empty() {
+            return Err(PkgConfigError::NoPackages(directory.into()));
+        }
+        Ok(Self(packages))
+    }
+
+    /// Packages that no other package requires.
+    #[must_use]
+    pub fn roots(&self) -> Vec<String> {
+        let required: HashSet<_> = self.0.values().flat_map(PcFile::requires).collect();
+        self.0
+            .keys()
+            .filter(|name| !required.contains(*name))
+            .cloned()
+            .collect()
+    }
+
+    /// The packages in link order, with every package before the packages it requires.
+    ///
+    /// # Errors
+    ///
+    /// * `PkgConfigError::MissingPackage` if a required package is not in the set.
+    pub fn dependencies(&self) -> Result<Dependencies, PkgConfigError> {
+        let mut order = Vec::new();
+        let mut visited = HashSet::new();
+        for root in self.roots() {
+            self.visit(&root, &mut visited, &mut order)?;
+        }
+
+        let packages = order
+            .iter()
+            .rev()
+            .map(|name| {
+                let file = &self.0[name];
+                Package {
+                    name: name.clone(),
+                    version: file.field("Version").map(String::from),
+                    requires: file.requires(),
+                    info: file.build_info(),
+                }
+            })
+            .collect();
+        Ok(Dependencies {
+            packages,
+            has_requirements: true,
+        })
+    }
+
+    /// Depth-first post-order traversal of the requirements of `name`.
+    fn visit(
+        &self,
+        name: &str,
+        visited: &mut HashSet<String>,
+        order: &mut Vec<String>,
+    ) -> Result<(), PkgConfigError> {
+        if !visited.insert(name.into()) {
+            return Ok(());
+        }
+        let package = self
+            .0
+            .get(name)
+            .ok_or_else(|| PkgConfigError::MissingPackage { name: name.into() })?;
+        for requirement in package.requires() {
+            self.visit(&requirement, visited, order)?;
+        }
+        order.push(name.into());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(path)
+    }
+
+    fn strings(strings: &[&str]) -> Vec<String> {
+        strings.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn expands_variables_and_pcfiledir() {
+        let path = fixture("conan2/pkgconfig/opencv_core.pc");
+        let file = PcFile::load(&path).expect("fixture should parse");
+        let prefix = format!("{}/../opencv", fixture("conan2/pkgconfig").display());
+
+        assert_eq!(file.variables["prefix"], prefix);
+        assert_eq!(file.variables["libdir"], format!("{prefix}/lib"));
+        assert_eq!(file.field("Version"), Some("4.5.5"));
+        assert_eq!(
+            file.build_info(),
+            BuildInfo {
+                include_dirs: vec![format!("{prefix}/include/opencv4")],
+                lib_dirs: vec![format!("{prefix}/lib")],
+                libs: strings(&["opencv_core"]),
+                system_libs: strings(&["dl", "m", "pthread"]),
+            }
+        );
+    }
+
+    #[test]
+    fn keeps_unterminated_references() {
+        let file =
+            PcFile::parse("x=1\na=abc${x\nb=${x}${x\n", Path::new("x.pc")).expect("should parse");
+
+        assert_eq!(file.variables["a"], "abc${x");
+        assert_eq!(file.variables["b"], "1${x");
+    }
+
+    #[test]
+    fn requires_drops_version_constraints() {
+        let file = PcFile::parse(
+            "Requires: opencv >= 4, zlib = 1.2.13 libpng,jpeg != 9 webp\n",
+            Path::new("x.pc"),
+        )
+        .expect("should parse");
+
+        assert_eq!(
+            file.requires(),
+            strings(&["opencv", "zlib", "libpng", "jpeg", "webp"])
+        );
+        assert!(PcFile::default().requires().is_empty());
+    }
+
+    #[test]
+    fn reports_undefined_variables_and_syntax_errors() {
+        let path = Path::new("broken.pc");
+        match PcFile::parse("prefix=/usr\n\nlibdir=${exec_prefix}/lib\n", path) {
+            Err(PkgConfigError::UndefinedVariable { path, line, name }) => {
+                assert_eq!(
+                    (path.as_path(), line, name.as_str()),
+                    (Path::new("broken.pc"), 3, "exec_prefix")
+                );
+            }
+            other => panic!("expected an undefined variable, got {other:?}"),
+        }
+        // variables are only defined below their definition
+        assert!(matches!(
+            PcFile::parse("libdir=${prefix}/lib\nprefix=/usr\n", path),
+            Err(PkgConfigError::UndefinedVariable { line: 1, .. })
+        ));
+        assert!(matches!(
+            PcFile::parse("# comment\nLibs -lz\n", path),
+            Err(PkgConfigError::Syntax { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn dependencies_are_in_link_order() {
+        let packages =
+            Packages::from_directory(&fixture("conan2/pkgconfig")).expect("fixture should load");
+        assert_eq!(packages.roots(), ["opencv"]);
+
+        let dependencies = packages.dependencies().expect("should resolve");
+        assert!(dependencies.has_requirements);
+        let names: Vec<_> = dependencies
+            .packages
+            .iter()
+            .map(|package| package.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "opencv",
+                "opencv_imgcodecs",
+                "opencv_imgproc",
+                "opencv_core",
+                "zlib"
+            ]
+        );
+        assert_eq!(
+            dependencies
+                .get("opencv")
+                .map(|opencv| opencv.requires.clone()),
+            Some(strings(&[
+                "opencv_imgcodecs",
+                "opencv_imgproc",
+                "opencv_core"
+            ]))
+        );
+        assert_eq!(
+            dependencies.build_info().libs,
+            strings(&["opencv_imgcodecs", "opencv_imgproc", "opencv_core", "z"])
+        );
+    }
+
+    #[test]
+    fn missing_requirements_are_errors() {
+        let search_path = [fixture("conan2/pkgconfig")];
+        let found = Packages::find("opencv_imgproc", &search_path).expect("should find");
+        assert_eq!(
+            found.0.keys().collect::<Vec<_>>(),
+            ["opencv_core", "opencv_imgproc", "zlib"]
+        );
+
+        let mut packages = found;
+        packages.0.remove("zlib");
+        assert!(matches!(
+            packages.dependencies(),
+            Err(PkgConfigError::MissingPackage { name }) if name == "zlib"
+        ));
+        assert!(matches!(
+            Packages::find("opencv5", &search_path),
+            Err(PkgConfigError::MissingPackage { name }) if name == "opencv5"
+        ));
+        assert!(matches!(
+            Packages::from_directory(&fixture("conan1")),
+            Err(PkgConfigError::NoPackages(_))
+        ));
+    }
+
+    #[test]
+    fn find_opencv_falls_back_to_opencv() {
+        let opencv3 = fixture("pkgconfig/opencv3");
+        let opencv4 = fixture("pkgconfig/opencv4");
+
+        let packages =
+            Packages::find_opencv(std::slice::from_ref(&opencv3)).expect("should find opencv");
+        assert_eq!(packages.0.keys().collect::<Vec<_>>(), ["opencv"]);
+        assert_eq!(packages.0["opencv"].field("Version"), Some("3.2.0"));
+        assert_eq!(
+            packages.0["opencv"].build_info().include_dirs,
+            strings(&["/usr/include/opencv", "/usr/include"])
+        );
+
+        // opencv4 wherever it is in the search path
+        let packages = Packages::find_opencv(&[opencv3, opencv4]).expect("should find opencv4");
+        assert_eq!(packages.0.keys().collect::<Vec<_>>(), ["opencv4"]);
+
+        assert!(matches!(
+            Packages::find_opencv(&[fixture("cmake")]),
+            Err(PkgConfigError::MissingPackage { name }) if name == "opencv4"
+        ));
+        // an opencv4.pc with a missing requirement does not fall back
+        assert!(matches!(
+            Packages::find_opencv(&[fixture("pkgconfig/broken"), fixture("pkgconfig/opencv3")]),
+            Err(PkgConfigError::MissingPackage { name }) if name == "libpng"
+        ));
+    }
+}
@@ -0,0 +1,944 @@
+use crate::{
+    build_info::{BuildInfo, SelectionError},
+    cargo_config::{self, ArrayMerge, CargoConfigError},
+    conan,
+    conanfile::{render, ConanfileSpec, PackageOption},
+    discovery::{Backend, DiscoveryArgs, DiscoveryError},
+    doctor::{INCLUDE_PATHS_VARIABLE, LINK_LIBS_VARIABLE, LINK_PATHS_VARIABLE},
+    link_order::{resolve_link_order, LinkOrderError, LinkOrdering},
+    modules::ModulesError,
+};
+use clap::ValueEnum;
+use std::{
+    collections::BTreeSet,
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+use toml_edit::{Decor, Document, InlineTable, Item, RawString, Value};
+
+/// The project configuration, which is looked for in the current directory and its parents.
+pub const PROJECT_CONFIG_FILE: &str = "manage_opencv.toml";
+/// The variables for the `env` section of the Cargo configuration, in the build directory.
+pub const ENVS_FILE: &str = "opencv_envs.toml";
+
+#[derive(Debug, thiserror::Error)]
+pub enum SetupError {
+    #[error("could not access {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("{path} is not valid TOML: {source}")]
+    Invalid {
+        path: PathBuf,
+        #[source]
+        source: toml_edit::TomlError,
+    },
+    #[error("`{key}` in {path} should be {expected}")]
+    InvalidField {
+        path: PathBuf,
+        key: String,
+        expected: String,
+    },
+    #[error("could not run `conan`, is it installed and on the PATH? {0}")]
+    ConanMissing(#[source] io::Error),
+    #[error("could not tell the Conan version from `{0}`")]
+    UnknownConanVersion(String),
+    #[error("`conan install` failed with {0}")]
+    ConanFailed(std::process::ExitStatus),
+    #[error("{0} does not exist, run `manage_opencv envs` first")]
+    MissingEnvs(PathBuf),
+    #[error(transparent)]
+    Discovery(#[from] DiscoveryError),
+    #[error(transparent)]
+    Selection(#[from] SelectionError),
+    #[error(transparent)]
+    LinkOrder(#[from] LinkOrderError),
+    #[error(transparent)]
+    CargoConfig(#[from] CargoConfigError),
+    #[error(transparent)]
+    Modules(#[from] ModulesError),
+}
+
+/// The settings of `manage_opencv.toml`, with relative paths resolved against its directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectConfig {
+    /// The directory of the configuration file, or the current directory without one.
+    pub root: PathBuf,
+    /// Where Conan installs to and the variables are written to.
+    pub build_directory: PathBuf,
+    pub cargo_config: PathBuf,
+    /// `conan.version`, 1 or 2, or `None` to ask `conan --version`.
+    pub conan_version: Option<u8>,
+    /// `conan.args`, passed on to `conan install`.
+    pub conan_args: Vec<String>,
+    pub backend: Backend,
+    /// `link.packages`, the packages to link with what they require, or all if empty.
+    pub packages: Vec<String>,
+    /// `link.exclude`, packages not to link.
+    pub exclude: Vec<String>,
+    /// `link.include_dirs`, appended to the include directories that were found.
+    pub include_dirs: Vec<String>,
+    /// `link.lib_dirs`, appended to the library directories that were found.
+    pub lib_dirs: Vec<String>,
+    /// `link.libs`, appended to the libraries that were found.
+    pub libs: Vec<String>,
+    /// `link.system_libs`, whether to link the system libraries of the packages.
+    pub system_libs: bool,
+    pub link_order: LinkOrdering,
+    /// `link.force`, whether the variables override those of the environment.
+    pub force: bool,
+    /// The `[conanfile]` section, or `None` if the conanfile is written by hand.
+    pub conanfile: Option<ConanfileSpec>,
+}
+
+impl ProjectConfig {
+    /// The configuration of a project without `manage_opencv.toml` in `root`.
+    #[must_use]
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.into(),
+            build_directory: root.join("build"),
+            cargo_config: root.join(".cargo").join("config.toml"),
+            conan_version: None,
+            conan_args: Vec::new(),
+            backend: Backend::Auto,
+            packages: Vec::new(),
+            exclude: Vec::new(),
+            include_dirs: Vec::new(),
+            lib_dirs: Vec::new(),
+            libs: Vec::new(),
+            system_libs: true,
+            link_order: LinkOrdering::Keep,
+            force: false,
+            conanfile: None,
+        }
+    }
+
+    /// Loads the `manage_opencv.toml` of `start` or its closest parent that has one, or the
+    /// defaults for `start` if there is none.
+    ///
+    /// # Errors
+    ///
+    /// * The errors of [`ProjectConfig::load`].
+    pub fn find(start: &Path) -> Result<Self, SetupError> {
+        match start
+            .ancestors()
+            .map(|directory| directory.join(PROJECT_CONFIG_FILE))
+            .find(|path| path.is_file())
+        {
+            Some(path) => Self::load(&path),
+            None => Ok(Self::new(start)),
+        }
+    }
+
+    /// Reads and parses the configuration at `path`.
+    ///
+    /// # Errors
+    ///
+    /// * `SetupError::Io` if it cannot be read.
+    /// * The errors of [`ProjectConfig::parse`].
+    pub fn load(path: &Path) -> Result<Self, SetupError> {
+        let contents = fs::read_to_string(path).map_err(|source| SetupError::Io {
+            path: path.into(),
+            source,
+        })?;
+        Self::parse(&contents, path)
+    }
+
+    /// Parses `contents`, the configuration at `path`. Missing keys keep their defaults.
+    ///
+    /// # Errors
+    ///
+    /// * `SetupError::Invalid` if it is not TOML.
+    /// * `SetupError::InvalidField` if a key has the wrong type or an unknown value.
+    pub fn parse(contents: &str, path: &Path) -> Result<Self, SetupError> {
+        let document = contents
+            .parse::<Document>()
+            .map_err(|source| SetupError::Invalid {
+                path: path.into(),
+                source,
+            })?;
+        let root = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let fields = Fields {
+            document: &document,
+            path,
+        };
+
+        let mut config = Self::new(&root);
+        if let Some(directory) = fields.string("build_directory")? {
+            config.build_directory = root.join(directory);
+        }
+        if let Some(cargo_config) = fields.string("cargo_config")? {
+            config.cargo_config = root.join(cargo_config);
+        }
+        if let Some(version) = fields.get("conan.version") {
+            config.conan_version = match version.as_integer() {
+                Some(version @ (1 | 2)) => Some(u8::try_from(version).expect("should be 1 or 2")),
+                _ => return Err(fields.invalid("conan.version", "1 or 2")),
+            };
+        }
+        config.conan_args = fields.strings("conan.args")?.unwrap_or_default();
+        if let Some(backend) = fields.value_enum("link.backend")? {
+            config.backend = backend;
+        }
+        config.packages = fields.strings("link.packages")?.unwrap_or_default();
+        config.exclude = fields.strings("link.exclude")?.unwrap_or_default();
+        config.include_dirs = fields.directories("link.include_dirs", &root)?;
+        config.lib_dirs = fields.directories("link.lib_dirs", &root)?;
+        config.libs = fields.strings("link.libs")?.unwrap_or_default();
+        if let Some(system_libs) = fields.bool("link.system_libs")? {
+            config.system_libs = system_libs;
+        }
+        if let Some(link_order) = fields.value_enum("link.link_order")? {
+            config.link_order = link_order;
+        }
+        if let Some(force) = fields.bool("link.force")? {
+            config.force = force;
+        }
+        if fields.get("conanfile").is_some() {
+            config.conanfile = Some(fields.conanfile_spec()?);
+        }
+        Ok(config)
+    }
+
+    #[must_use]
+    pub fn envs_file(&self) -> PathBuf {
+        self.build_directory.join(ENVS_FILE)
+    }
+}
+
+/// The text of the `#` comments in `raw`, line by line.
+fn comment_lines(raw: Option<&RawString>) -> Vec<String> {
+    raw.and_then(RawString::as_str)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix('#'))
+        .map(|line| line.trim().to_string())
+        .collect()
+}
+
+/// Typed access to the dotted keys of a configuration file.
+struct Fields<'a> {
+    document: &'a Document,
+    path: &'a Path,
+}
+
+impl Fields<'_> {
+    fn get(&self, key: &str) -> Option<&Item> {
+        key.split('.')
+            .try_fold(self.document.as_item(), |item, part| item.get(part))
+    }
+
+    fn invalid(&self, key: &str, expected: &str) -> SetupError {
+        SetupError::InvalidField {
+            path: self.path.into(),
+            key: key.into(),
+            expected: expected.into(),
+        }
+    }
+
+    fn string(&self, key: &str) -> Result<Option<&str>, SetupError> {
+        self.get(key)
+            .map(|item| item.as_str().ok_or_else(|| self.invalid(key, "a string")))
+            .transpose()
+    }
+
+    fn bool(&self, key: &str) -> Result<Option<bool>, SetupError> {
+        self.get(key)
+            .map(|item| item.as_bool().ok_or_else(|| self.invalid(key, "a boolean")))
+            .transpose()
+    }
+
+    fn strings(&self, key: &str) -> Result<Option<Vec<String>>, SetupError> {
+        let Some(item) = self.get(key) else {
+            return Ok(None);
+        };
+        item.as_array()
+            .and_then(|array| {
+                array
+                    .iter()
+                    .map(|value| value.as_str().map(String::from))
+                    .collect()
+            })
+            .map(Some)
+            .ok_or_else(|| self.invalid(key, "an array of strings"))
+    }
+
+    /// An array of directories, relative to `root` unless they are absolute.
+    fn directories(&self, key: &str, root: &Path) -> Result<Vec<String>, SetupError> {
+        Ok(self
+            .strings(key)?
+            .unwrap_or_default()
+            .iter()
+            .map(|directory| root.join(directory).to_string_lossy().into_owned())
+            .collect())
+    }
+
+    fn conanfile_spec(&self) -> Result<ConanfileSpec, SetupError> {
+        let mut spec = ConanfileSpec::default();
+        if let Some(format) = self.value_enum("conanfile.format")? {
+            spec.format = format;
+        }
+        if let Some(version) = self.string("conanfile.opencv")? {
+            spec.opencv_version = version.into();
+        }
+        spec.modules = self.strings("conanfile.modules")?;
+        if let Some(module_options) = self.bool("conanfile.module_options")? {
+            spec.module_options = module_options;
+        }
+        spec.requires = self.strings("conanfile.requires")?.unwrap_or_default();
+
+        let Some(packages) = self.get("conanfile.options") else {
+            return Ok(spec);
+        };
+        let expected = "a table of packages with tables of options";
+        let packages = packages
+            .as_table_like()
+            .ok_or_else(|| self.invalid("conanfile.options", expected))?;
+        for (package, options) in packages.iter() {
+            let key = format!("conanfile.options.{package}");
+            let options = options
+                .as_table_like()
+                .ok_or_else(|| self.invalid(&key, "a table of options"))?;
+            for (option, item) in options.iter() {
+                // kept for the conanfile, where the option would otherwise be unexplained
+                let comment = [
+                    options.key_decor(option).and_then(Decor::prefix),
+                    item.as_value().and_then(|value| value.decor().suffix()),
+                ]
+                .into_iter()
+                .flat_map(comment_lines)
+                .collect();
+                // Conan writes booleans capitalized, like Python
+                let value = match item.as_value() {
+                    Some(Value::Boolean(flag)) => {
+                        if *flag.value() { "True" } else { "False" }.to_string()
+                    }
+                    Some(Value::String(text)) => text.value().clone(),
+                    Some(Value::Integer(number)) => number.value().to_string(),
+                    _ => {
+                        let key = format!("{key}.{option}");
+                        return Err(self.invalid(&key, "a boolean, string or integer"));
+                    }
+                };
+                spec.options.push(PackageOption {
+                    comment,
+                    ..PackageOption::new(package, option, &value)
+                });
+            }
+        }
+        Ok(spec)
+    }
+
+    fn value_enum<T: ValueEnum>(&self, key: &str) -> Result<Option<T>, SetupError> {
+        let Some(name) = self.string(key)? else {
+            return Ok(None);
+        };
+        T::from_str(name, true).map(Some).map_err(|_| {
+            let names: Vec<_> = T::value_variants()
+                .iter()
+                .filter_map(|variant| {
+                    Some(format!("`{}`", variant.to_possible_value()?.get_name()))
+                })
+                .collect();
+            self.invalid(key, &format!("one of {}", names.join(", ")))
+        })
+    }
+}
+
+/// The major version of the `conan` on the `PATH`.
+///
+/// # Errors
+///
+/// * `SetupError::ConanMissing` if it cannot be run.
+/// * `SetupError::UnknownConanVersion` if its output has no version.
+pub fn conan_version() -> Result<u8, SetupError> {
+    let output = Command::new("conan")
+        .arg("--version")
+        .output()
+        .map_err(SetupError::ConanMissing)?;
+    // e.g. `Conan version 2.0.4`
+    let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    text.rsplit(' ')
+        .next()
+        .and_then(|version| version.split('.').next())
+        .and_then(|major| major.parse().ok())
+        .ok_or(SetupError::UnknownConanVersion(text))
+}
+
+/// Runs `conan install` for the project into the build directory. Conan 2 also writes the
+/// dependency graph and the pkg-config files.
+///
+/// # Errors
+///
+/// * `SetupError::Io` if the build directory or the graph file cannot be created.
+/// * `SetupError::ConanFailed` if Conan fails.
+/// * The errors of [`conan_version`].
+pub fn install(config: &ProjectConfig, extra_args: &[String]) -> Result<(), SetupError> {
+    let build = &config.build_directory;
+    let io_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| SetupError::Io { path, source }
+    };
+    fs::create_dir_all(build).map_err(io_error(build))?;
+
+    let version = match config.conan_version {
+        Some(version) => version,
+        None => conan_version()?,
+    };
+    let mut command = Command::new("conan");
+    command.arg("install").arg(&config.root);
+    if version == 1 {
+        command
+            .arg("--install-folder")
+            .arg(build)
+            .arg("--output-folder")
+            .arg(build)
+            .arg("-bmissing");
+    } else {
+        let graph = build.join(conan::GRAPH_JSON_FILE);
+        let file = fs::File::create(&graph).map_err(io_error(&graph))?;
+        command
+            .arg("--output-folder")
+            .arg(build)
+            .arg("--build=missing")
+            .args(["-g", "PkgConfigDeps", "--format=json"])
+            .stdout(Stdio::from(file));
+    }
+    command.args(&config.conan_args).args(extra_args);
+
+    let status = command.status().map_err(SetupError::ConanMissing)?;
+    if !status.success() {
+        return Err(SetupError::ConanFailed(status));
+    }
+    Ok(())
+}
+
+/// Writes the conanfile of the `[conanfile]` section, or of its defaults if there is none, to the
+/// project directory, or with `dry_run` only returns the diff. Returns the path of the conanfile
+/// and the diff, which is empty if nothing changes.
+///
+/// # Errors
+///
+/// * The errors of [`ConanfileSpec::modules`] and [`conan_version`].
+/// * `SetupError::Io` if the conanfile cannot be read.
+/// * The errors of writing it.
+pub fn generate_conanfile(
+    config: &ProjectConfig,
+    dry_run: bool,
+) -> Result<(PathBuf, String), SetupError> {
+    let spec = config.conanfile.clone().unwrap_or_default();
+    let modules = if spec.module_options {
+        spec.modules(&config.root)?
+    } else {
+        BTreeSet::new()
+    };
+    let version = match config.conan_version {
+        Some(version) => version,
+        None => conan_version()?,
+    };
+    let new = render(&spec, &modules, version);
+
+    let path = config.root.join(spec.format.file_name());
+    let old = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(source) => return Err(SetupError::Io { path, source }),
+    };
+    let diff = cargo_config::unified_diff(&old, &new, &path);
+    if !dry_run && new != old {
+        cargo_config::write_atomically(&path, &new)?;
+    }
+    Ok((path, diff))
+}
+
+/// Finds OpenCV as the configuration says, preferring the Conan output of the build directory,
+/// and returns the backend that found it with the final link information. Without Conan output,
+/// which the build directory also lacks once [`write_envs`] created it for another backend, the
+/// `auto` backend falls back to the others.
+///
+/// # Errors
+///
+/// * The errors of discovery, package selection and link ordering.
+pub fn link_info(config: &ProjectConfig) -> Result<(Backend, BuildInfo), SetupError> {
+    let conan_output = match config.backend {
+        Backend::Auto => conan::has_output(&config.build_directory),
+        // let the conan backend tell what is missing
+        Backend::Conan => config.build_directory.is_dir(),
+        Backend::PkgConfig | Backend::Vcpkg | Backend::Cmake => false,
+    };
+    let discovery = DiscoveryArgs {
+        conan_build_info: conan_output.then(|| config.build_directory.clone()),
+        backend: config.backend,
+        ..DiscoveryArgs::default()
+    };
+    let (backend, dependencies) = discovery.discover()?;
+    let mut info = dependencies
+        .select(&config.packages, &config.exclude)?
+        .build_info();
+
+    info.include_dirs
+        .extend(config.include_dirs.iter().cloned());
+    info.lib_dirs.extend(config.lib_dirs.iter().cloned());
+    if config.system_libs {
+        info.libs.append(&mut info.system_libs);
+    }
+    info.system_libs.clear();
+    info.libs.extend(config.libs.iter().cloned());
+    if config.link_order == LinkOrdering::Symbols {
+        info.libs = resolve_link_order(&info.libs, &info.lib_dirs)?.libs(true);
+    }
+    Ok((backend, info))
+}
+
+/// The `OPENCV_*` variables for `info`, as `apply_to_env` reads them.
+#[must_use]
+pub fn envs_document(info: &BuildInfo, force: bool) -> Document {
+    let mut document = Document::new();
+    for (variable, values) in [
+        (INCLUDE_PATHS_VARIABLE, &info.include_dirs),
+        (LINK_PATHS_VARIABLE, &info.lib_dirs),
+        (LINK_LIBS_VARIABLE, &info.libs),
+    ] {
+        document.insert(variable, Item::Value(list_value(values, force)));
+    }
+    document
+}
+
+/// `values` as the comma separated list that the `opencv` crate reads, or with `force` as a
+/// table that makes Cargo override the variable of the environment.
+#[must_use]
+pub fn list_value(values: &[String], force: bool) -> Value {
+    let joined = Value::from(values.join(","));
+    if !force {
+        return joined;
+    }
+    let mut table = InlineTable::new();
+    table.insert("value", joined);
+    table.insert("force", true.into());
+    table.fmt();
+    Value::InlineTable(table)
+}
+
+/// Writes the variables of [`link_info`] to the envs file of the build directory, and returns the
+/// backend that found OpenCV.
+///
+/// # Errors
+///
+/// * `SetupError::Io` if the file cannot be written.
+/// * The errors of [`link_info`].
+pub fn write_envs(config: &ProjectConfig) -> Result<Backend, SetupError> {
+    let (backend, info) = link_info(config)?;
+    let path = config.envs_file();
+    let io_error = |source| SetupError::Io {
+        path: path.clone(),
+        source,
+    };
+    fs::create_dir_all(&config.build_directory).map_err(io_error)?;
+    fs::write(&path, envs_document(&info, config.force).to_string()).map_err(io_error)?;
+    Ok(backend)
+}
+
+/// Applies the envs file to the Cargo configuration with a backup, or with `dry_run` only returns
+/// the diff. Returns the diff, which is empty if nothing changes.
+///
+/// # Errors
+///
+/// * `SetupError::MissingEnvs` if the envs file has not been written.
+/// * The errors of reading and writing the Cargo configuration.
+pub fn apply(config: &ProjectConfig, dry_run: bool) -> Result<String, SetupError> {
+    let envs_path = config.envs_file();
+    let (_, envs) = cargo_config::load(&envs_path)?
+        .ok_or_else(|| SetupError::MissingEnvs(envs_path.clone()))?;
+    let (old, mut cargo_config) = cargo_config::load(&config.cargo_config)?
+        .map_or((None, Document::new()), |(contents, document)| {
+            (Some(contents), document)
+        });
+    cargo_config::apply(&mut cargo_config, &envs, &envs_path, ArrayMerge::Append)?;
+
+    let new = cargo_config.to_string();
+    let diff = cargo_config::unified_diff(
+        old.as_deref().unwrap_or_default(),
+        &new,
+        &config.cargo_config,
+    );
+    if dry_run || Some(&new) == old.as_ref() {
+        return Ok(diff);
+    }
+    if let Some(directory) = config.cargo_config.parent() {
+        fs::create_dir_all(directory).map_err(|source| SetupError::Io {
+            path: directory.into(),
+            source,
+        })?;
+    }
+    cargo_config::write_with_backup(&config.cargo_config, old.as_deref(), &new)?;
+    Ok(diff)
+}
+
+/// Removes the build directory, and with `unapply` first the applied variables from the Cargo
+/// configuration.
+///
+/// # Errors
+///
+/// * `SetupError::Io` if the build directory cannot be removed.
+/// * The errors of reading and writing the Cargo configuration.
+pub fn clean(config: &ProjectConfig, unapply: bool) -> Result<(), SetupError> {
+    if unapply {
+        let envs_path = config.envs_file();
+        if let (Some((_, envs)), Some((old, mut cargo_config))) = (
+            cargo_config::load(&envs_path)?,
+            cargo_config::load(&config.cargo_config)?,
+        ) {
+            cargo_config::remove(&mut cargo_config, &envs);
+            let new = cargo_config.to_string();
+            if new != old {
+                cargo_config::write_with_backup(&config.cargo_config, Some(&old), &new)?;
+            }
+        }
+    }
+    match fs::remove_dir_all(&config.build_directory) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(SetupError::Io {
+            path: config.build_directory.clone(),
+            source: error,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// The current directory, where the project configuration is looked for.
+///
+/// # Errors
+///
+/// * `SetupError::Io` if it cannot be determined.
+pub fn current_directory() -> Result<PathBuf, SetupError> {
+    env::current_dir().map_err(|source| SetupError::Io {
+        path: ".".into(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{conan::CONAN_BUILD_INFO_FILE, discovery::DiscoveryError};
+    use tempfile::TempDir;
+
+    fn fixture(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(path)
+    }
+
+    fn strings(strings: &[&str]) -> Vec<String> {
+        strings.iter().map(ToString::to_string).collect()
+    }
+
+    fn parse(contents: &str) -> ProjectConfig {
+        ProjectConfig::parse(contents, Path::new("project/manage_opencv.toml"))
+            .expect("should parse")
+    }
+
+    fn parse_error(contents: &str) -> SetupError {
+        ProjectConfig::parse(contents, Path::new("manage_opencv.toml"))
+            .expect_err("should not parse")
+    }
+
+    /// A project in a temporary directory, with Conan 1's output in its build directory.
+    fn conan_project() -> (TempDir, ProjectConfig) {
+        let root = tempfile::tempdir().expect("should create a temporary directory");
+        let config = ProjectConfig::new(root.path());
+        fs::create_dir_all(&config.build_directory).expect("should create the build directory");
+        fs::copy(
+            fixture("conan1/conanbuildinfo.txt"),
+            config.build_directory.join(CONAN_BUILD_INFO_FILE),
+        )
+        .expect("should copy the fixture");
+        (root, config)
+    }
+
+    /// Whether discovery failed without reading Conan output, or found OpenCV elsewhere.
+    fn skipped_conan(result: Result<Backend, SetupError>) -> bool {
+        match result {
+            Ok(backend) => backend != Backend::Conan,
+            Err(SetupError::Discovery(DiscoveryError::NotFound { attempts })) => attempts
+                .iter()
+                .all(|(backend, _)| *backend != Backend::Conan),
+            Err(_) => false,
+        }
+    }
+
+    #[test]
+    fn parses_the_project_settings() {
+        let config = parse(
+            r#"
+build_directory = "out"
+cargo_config = "cfg/config.toml"
+
+[conan]
+version = 2
+args = ["-s", "build_type=Release"]
+
+[link]
+backend = "pkg-config"
+packages = ["opencv"]
+exclude = ["ffmpeg"]
+include_dirs = ["include", "/usr/include"]
+lib_dirs = ["lib"]
+libs = ["extra"]
+system_libs = false
+link_order = "symbols"
+force = true
+"#,
+        );
+        let root = Path::new("project");
+
+        assert_eq!(
+            config,
+            ProjectConfig {
+                build_directory: root.join("out"),
+                cargo_config: root.join("cfg/config.toml"),
+                conan_version: Some(2),
+                conan_args: strings(&["-s", "build_type=Release"]),
+                backend: Backend::PkgConfig,
+                packages: strings(&["opencv"]),
+                exclude: strings(&["ffmpeg"]),
+                include_dirs: strings(&["project/include", "/usr/include"]),
+                lib_dirs: strings(&["project/lib"]),
+                libs: strings(&["extra"]),
+                system_libs: false,
+                link_order: LinkOrdering::Symbols,
+                force: true,
+                ..ProjectConfig::new(root)
+            }
+        );
+    }
+
+    #[test]
+    fn missing_settings_keep_their_defaults() {
+        assert_eq!(parse(""), ProjectConfig::new(Path::new("project")));
+        assert_eq!(
+            ProjectConfig::parse("", Path::new("manage_opencv.toml")).expect("should parse"),
+            ProjectConfig::new(Path::new("."))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        assert!(matches!(parse_error("[link"), SetupError::Invalid { .. }));
+        for (contents, expected_key, expected_type) in [
+            ("[conan]\nversion = 3\n", "conan.version", "1 or 2"),
+            ("[link]\nlibs = \"z\"\n", "link.libs", "an array of strings"),
+            ("[link]\nforce = \"yes\"\n", "link.force", "a boolean"),
+            ("build_directory = 1\n", "build_directory", "a string"),
+        ] {
+            match parse_error(contents) {
+                SetupError::InvalidField { key, expected, .. } => {
+                    assert_eq!(
+                        (key.as_str(), expected.as_str()),
+                        (expected_key, expected_type)
+                    );
+                }
+                error => panic!("unexpected error for {contents:?}: {error}"),
+            }
+        }
+        match parse_error("[link]\nbackend = \"apt\"\n") {
+            SetupError::InvalidField { key, expected, .. } => {
+                assert_eq!(key, "link.backend");
+                assert!(expected.contains("`pkg-config`"), "{expected}");
+            }
+            error => panic!("unexpected error: {error}"),
+        }
+    }
+
+    #[test]
+    fn finds_the_configuration_in_a_parent() {
+        let root = tempfile::tempdir().expect("should create a temporary directory");
+        let nested = root.path().join("crates/lab");
+        fs::create_dir_all(&nested).expect("should create the directories");
+        assert_eq!(
+            ProjectConfig::find(&nested).expect("should use the defaults"),
+            ProjectConfig::new(&nested)
+        );
+
+        fs::write(
+            root.path().join(PROJECT_CONFIG_FILE),
+            "build_directory = \"out\"\n",
+        )
+        .expect("should write the configuration");
+        let config = ProjectConfig::find(&nested).expect("should load the configuration");
+        assert_eq!(config.root, root.path());
+        assert_eq!(config.build_directory, root.path().join("out"));
+    }
+
+    #[test]
+    fn link_info_reads_the_conan_output_of_the_build_directory() {
+        let (_root, mut config) = conan_project();
+        config.libs = strings(&["extra"]);
+
+        let (backend, info) = link_info(&config).expect("should read the fixture");
+        assert_eq!(backend, Backend::Conan);
+        assert_eq!(
+            info.libs,
+            strings(&[
+                "opencv_imgproc",
+                "opencv_core",
+                "z",
+                "dl",
+                "pthread",
+                "extra"
+            ])
+        );
+        assert!(info.system_libs.is_empty());
+
+        config.system_libs = false;
+        let (_, info) = link_info(&config).expect("should read the fixture");
+        assert_eq!(
+            info.libs,
+            strings(&["opencv_imgproc", "opencv_core", "z", "extra"])
+        );
+    }
+
+    #[test]
+    fn link_info_only_reads_conan_output_that_exists() {
+        let (_root, mut config) = conan_project();
+        fs::remove_file(config.build_directory.join(CONAN_BUILD_INFO_FILE))
+            .expect("should remove the Conan output");
+
+        assert!(skipped_conan(
+            link_info(&config).map(|(backend, _)| backend)
+        ));
+        config.backend = Backend::Conan;
+        assert!(matches!(
+            link_info(&config),
+            Err(SetupError::Discovery(DiscoveryError::Conan(_)))
+        ));
+    }
+
+    #[test]
+    fn write_envs_runs_again_on_its_own_build_directory() {
+        let (_root, config) = conan_project();
+
+        assert_eq!(write_envs(&config).expect("should write"), Backend::Conan);
+        let envs = fs::read_to_string(config.envs_file()).expect("should have written envs");
+        assert!(envs.contains("OPENCV_LINK_LIBS = \"opencv_imgproc,opencv_core,z,dl,pthread\""));
+        assert_eq!(
+            write_envs(&config).expect("should write again"),
+            Backend::Conan
+        );
+        assert_eq!(fs::read_to_string(config.envs_file()).ok(), Some(envs));
+
+        // only the envs file is left, as after an earlier run with another backend
+        fs::remove_file(config.build_directory.join(CONAN_BUILD_INFO_FILE))
+            .expect("should remove the Conan output");
+        assert!(skipped_conan(write_envs(&config)));
+    }
+
+    #[test]
+    fn apply_writes_the_envs_to_the_cargo_configuration() {
+        let (_root, config) = conan_project();
+        assert!(matches!(
+            apply(&config, false),
+            Err(SetupError::MissingEnvs(path)) if path == config.envs_file()
+        ));
+        write_envs(&config).expect("should write");
+
+        let diff = apply(&config, true).expect("should diff");
+        assert!(diff.contains("+OPENCV_LINK_LIBS"), "{diff}");
+        assert!(!config.cargo_config.exists());
+
+        assert_eq!(apply(&config, false).expect("should apply"), diff);
+        let cargo_config =
+            fs::read_to_string(&config.cargo_config).expect("should have written the config");
+        assert!(cargo_config.contains("[env]"), "{cargo_config}");
+        assert!(cargo_config.contains("OPENCV_LINK_PATHS"), "{cargo_config}");
+        assert_eq!(apply(&config, false).expect("should apply again"), "");
+    }
+
+    #[test]
+    fn clean_removes_the_build_directory_and_the_applied_envs() {
+        let (_root, config) = conan_project();
+        write_envs(&config).expect("should write");
+        apply(&config, false).expect("should apply");
+
+        clean(&config, true).expect("should clean");
+        assert!(!config.build_directory.exists());
+        let cargo_config =
+            fs::read_to_string(&config.cargo_config).expect("should keep the config");
+        assert!(!cargo_config.contains("OPENCV_"), "{cargo_config}");
+
+        clean(&config, true).expect("should clean without a build directory");
+    }
+
+    #[test]
+    fn conanfile_options_keep_their_comments() {
+        let config = parse(
+            r#"
+[conanfile]
+opencv = "4.8.1"
+module_options = true
+
+[conanfile.options.opencv]
+with_v4l = false # v4l2 is missing on CI
+# bounded by the
+#   memory of the runners
+parallel = "tbb"
+
+[conanfile.options.freetype]
+
+with_brotli = false
+"#,
+        );
+        let spec = config.conanfile.expect("should have a [conanfile]");
+
+        assert_eq!(spec.opencv_version, "4.8.1");
+        assert!(spec.module_options);
+        assert_eq!(
+            spec.options,
+            [
+                PackageOption {
+                    comment: vec!["v4l2 is missing on CI".into()],
+                    ..PackageOption::new("opencv", "with_v4l", "False")
+                },
+                PackageOption {
+                    comment: vec!["bounded by the".into(), "memory of the runners".into()],
+                    ..PackageOption::new("opencv", "parallel", "tbb")
+                },
+                PackageOption::new("freetype", "with_brotli", "False"),
+            ]
+        );
+    }
+
+    #[test]
+    fn module_options_are_opt_in() {
+        let spec = parse("[conanfile]\n")
+            .conanfile
+            .expect("should have a [conanfile]");
+        assert!(!spec.module_options);
+
+        assert!(matches!(
+            ProjectConfig::parse(
+                "[conanfile]\nmodule_options = 1\n",
+                Path::new("manage_opencv.toml")
+            ),
+            Err(SetupError::InvalidField { key, .. }) if key == "conanfile.module_options"
+        ));
+    }
+
+    #[test]
+    fn conanfile_of_the_project_is_up_to_date() {
+        let config = parse(include_str!("../../manage_opencv.toml"));
+        let spec = config.conanfile.expect("should have a [conanfile]");
+
+        // the checked-in conanfile is that of Conan 1
+        assert_eq!(
+            render(&spec, &BTreeSet::new(), 1),
+            include_str!("../../conanfile.txt")
+        );
+    }
+}
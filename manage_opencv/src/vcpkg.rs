@@ -0,0 +1,296 @@
+use crate::{
+    build_info::{BuildInfo, Dependencies, Package},
+    pkg_config::{Packages, PkgConfigError, OPENCV_PACKAGES},
+};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Environment variables that point to a vcpkg checkout, the second is set on GitHub's runners.
+const ROOT_VARIABLES: [&str; 2] = ["VCPKG_ROOT", "VCPKG_INSTALLATION_ROOT"];
+
+/// Where vcpkg installs the dependencies of a manifest, relative to the project.
+const MANIFEST_INSTALL_DIRECTORY: &str = "vcpkg_installed";
+
+/// OpenCV modules with every module before the modules it depends on, for when there is no
+/// pkg-config file that tells the link order. Modules that are not listed, such as those of
+/// `opencv_contrib`, go first.
+const MODULE_LINK_ORDER: [&str; 18] = [
+    "stitching",
+    "gapi",
+    "superres",
+    "videostab",
+    "objdetect",
+    "calib3d",
+    "features2d",
+    "flann",
+    "highgui",
+    "videoio",
+    "imgcodecs",
+    "video",
+    "ml",
+    "dnn",
+    "photo",
+    "imgproc",
+    "core",
+    "world",
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum VcpkgError {
+    #[error(
+        "no vcpkg installation for {triplet}, looked in {}",
+        candidates.iter().map(|c| c.display().to_string()).collect::<Vec<_>>().join(", ")
+    )]
+    NotInstalled {
+        triplet: String,
+        candidates: Vec<PathBuf>,
+    },
+    #[error("no OpenCV libraries in {0}")]
+    NoOpenCv(PathBuf),
+    #[error("could not read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error(transparent)]
+    PkgConfig(#[from] PkgConfigError),
+}
+
+/// The triplet of the host, e.g. `x64-linux`, unless `VCPKG_DEFAULT_TRIPLET` says otherwise.
+#[must_use]
+pub fn default_triplet() -> String {
+    env::var("VCPKG_DEFAULT_TRIPLET").unwrap_or_else(|_| {
+        let architecture = match env::consts::ARCH {
+            "x86_64" => "x64",
+            "x86" => "x86",
+            "aarch64" => "arm64",
+            "arm" => "arm",
+            other => other,
+        };
+        let os = match env::consts::OS {
+            "macos" => "osx",
+            other => other,
+        };
+        format!("{architecture}-{os}")
+    })
+}
+
+/// Finds the directory that vcpkg installed the packages of `triplet` to, either in the classic
+/// mode tree of `root` (or of `VCPKG_ROOT`), or in the manifest mode tree of the current
+/// directory.
+///
+/// # Errors
+///
+/// * `VcpkgError::NotInstalled` if there is no such directory.
+pub fn installed_directory(root: Option<&Path>, triplet: &str) -> Result<PathBuf, VcpkgError> {
+    let roots = root.map(Path::to_path_buf).into_iter().chain(
+        ROOT_VARIABLES
+            .iter()
+            .filter_map(|variable| env::var_os(variable).map(PathBuf::from)),
+    );
+    let candidates: Vec<_> = roots
+        .map(|root| root.join("installed"))
+        .chain([PathBuf::from(MANIFEST_INSTALL_DIRECTORY)])
+        .map(|installed| installed.join(triplet))
+        .collect();
+    candidates
+        .iter()
+        .find(|candidate| candidate.is_dir())
+        .cloned()
+        .ok_or_else(|| VcpkgError::NotInstalled {
+            triplet: triplet.into(),
+            candidates,
+        })
+}
+
+/// Reads OpenCV from a vcpkg installed tree such as `<root>/installed/x64-linux`. The pkg-config
+/// files that vcpkg installs are preferred, as they include the dependencies of OpenCV. Otherwise
+/// only the OpenCV libraries in `lib` are linked.
+///
+/// # Errors
+///
+/// * `VcpkgError::NoOpenCv` if there are neither pkg-config files nor libraries of OpenCV.
+/// * `VcpkgError::PkgConfig` if the pkg-config files are invalid.
+pub fn load(installed: &Path) -> Result<Dependencies, VcpkgError> {
+    let pkg_config = installed.join("lib").join("pkgconfig");
+    if let Some(name) = OPENCV_PACKAGES
+        .iter()
+        .find(|name| pkg_config.join(format!("{name}.pc")).is_file())
+    {
+        let packages = Packages::find(name, &[pkg_config])?;
+        return Ok(packages.dependencies()?);
+    }
+
+    let lib_dir = installed.join("lib");
+    let entries = fs::read_dir(&lib_dir).map_err(|source| VcpkgError::Io {
+        path: lib_dir.clone(),
+        source,
+    })?;
+    let mut libs: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| library_name(&entry.file_name().to_string_lossy()))
+        .filter(|name| name.starts_with("opencv_"))
+        .collect();
+    if libs.is_empty() {
+        return Err(VcpkgError::NoOpenCv(lib_dir));
+    }
+    // static and shared variants of a library have the same name
+    libs.sort();
+    libs.dedup();
+    libs.sort_by_key(|lib| {
+        let module = lib
+            .trim_start_matches("opencv_")
+            .trim_end_matches(char::is_numeric);
+        MODULE_LINK_ORDER
+            .iter()
+            .position(|ordered| *ordered == module)
+            .map_or(0, |position| position + 1)
+    });
+
+    let include = installed.join("include");
+    let include_dirs = [include.join("opencv4"), include]
+        .into_iter()
+        .filter(|dir| dir.is_dir())
+        .map(|dir| dir.to_string_lossy().into_owned())
+        .collect();
+    Ok(Dependencies {
+        packages: vec![Package {
+            name: "opencv".into(),
+            version: None,
+            requires: Vec::new(),
+            info: BuildInfo {
+                include_dirs,
+                lib_dirs: vec![lib_dir.to_string_lossy().into_owned()],
+                libs,
+                system_libs: Vec::new(),
+            },
+        }],
+        has_requirements: false,
+    })
+}
+
+/// The name to pass to the linker for a library file such as `libopencv_core4.a` or
+/// `opencv_core4.lib`, or `None` if the file is not a library.
+pub fn library_name(file_name: &str) -> Option<String> {
+    // shared libraries may have a version after the extension, e.g. `libz.so.1.2.13`
+    let (stem, extension) = match file_name.split_once(".so") {
+        Some((stem, version)) if version.is_empty() || version.starts_with('.') => (stem, "so"),
+        _ => file_name.rsplit_once('.')?,
+    };
+    match extension {
+        "a" | "so" | "dylib" => stem.strip_prefix("lib").map(String::from),
+        "lib" => Some(stem.into()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(path: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(path)
+    }
+
+    #[test]
+    fn finds_the_installed_tree_of_the_triplet() {
+        let root = fixture("vcpkg");
+        assert_eq!(
+            installed_directory(Some(&root), "x64-linux").expect("should find the triplet"),
+            root.join("installed/x64-linux")
+        );
+        match installed_directory(Some(&root), "riscv64-linux") {
+            Err(VcpkgError::NotInstalled {
+                triplet,
+                candidates,
+            }) => {
+                assert_eq!(triplet, "riscv64-linux");
+                assert_eq!(candidates[0], root.join("installed/riscv64-linux"));
+                assert_eq!(
+                    candidates.last(),
+                    Some(&PathBuf::from("vcpkg_installed/riscv64-linux"))
+                );
+            }
+            other => panic!("expected no installation, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn orders_the_libraries_of_a_tree_without_pc_files() {
+        let installed = fixture("vcpkg/installed/x64-linux");
+        let dependencies = load(&installed).expect("fixture should load");
+
+        let opencv = dependencies.get("opencv").expect("should have opencv");
+        assert_eq!(
+            opencv.info.libs,
+            [
+                "opencv_calib3d4",
+                "opencv_features2d4",
+                "opencv_highgui4",
+                "opencv_imgproc4",
+                "opencv_core4",
+            ]
+        );
+        assert_eq!(
+            opencv.info.include_dirs,
+            [
+                installed.join("include/opencv4").display().to_string(),
+                installed.join("include").display().to_string(),
+            ]
+        );
+        assert_eq!(
+            opencv.info.lib_dirs,
+            [installed.join("lib").display().to_string()]
+        );
+        assert!(!dependencies.has_requirements);
+
+        assert!(matches!(
+            load(&fixture("cmake")),
+            Err(VcpkgError::NoOpenCv(_))
+        ));
+    }
+
+    #[test]
+    fn prefers_pc_files() {
+        let installed = fixture("vcpkg/installed/x64-linux-dynamic");
+        let dependencies = load(&installed).expect("fixture should load");
+
+        assert!(dependencies.has_requirements);
+        let names: Vec<_> = dependencies
+            .packages
+            .iter()
+            .map(|package| package.name.as_str())
+            .collect();
+        assert_eq!(names, ["opencv4", "zlib"]);
+        let prefix = format!("{}/../..", installed.join("lib/pkgconfig").display());
+        assert_eq!(
+            dependencies.build_info().lib_dirs,
+            [format!("{prefix}/lib")]
+        );
+        assert_eq!(
+            dependencies.build_info().libs,
+            ["opencv_imgproc4", "opencv_core4", "z"]
+        );
+    }
+
+    #[test]
+    fn library_names() {
+        for (file_name, name) in [
+            ("libopencv_core4.a", Some("opencv_core4")),
+            ("libz.so", Some("z")),
+            ("libz.so.1.2.13", Some("z")),
+            ("libopencv_core.4.5.dylib", Some("opencv_core.4.5")),
+            ("opencv_core4.lib", Some("opencv_core4")),
+            ("opencv_core4.dll", None),
+            ("libz.solution", None),
+            ("opencv_core.a", None),
+        ] {
+            assert_eq!(library_name(file_name).as_deref(), name, "{file_name}");
+        }
+    }
+}
@@ -0,0 +1,5 @@
+set(OpenCV_VERSION 4.5.5)
+set(PACKAGE_VERSION ${OpenCV_VERSION})
+
+set(PACKAGE_VERSION_EXACT False)
+set(PACKAGE_VERSION_COMPATIBLE False)
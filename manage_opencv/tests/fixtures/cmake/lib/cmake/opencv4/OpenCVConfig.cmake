@@ -0,0 +1,24 @@
+# ===================================================================================
+#  The OpenCV CMake configuration file, trimmed to what manage_opencv reads
+#
+#    Usage from an external project:
+#      find_package(OpenCV REQUIRED)
+#      target_link_libraries(MY_TARGET_NAME ${OpenCV_LIBS})
+# ===================================================================================
+
+set(OpenCV_VERSION 4.5.5)
+
+# Extract the directory where *this* file has been installed (determined at cmake run-time)
+get_filename_component(OpenCV_CONFIG_PATH "${CMAKE_CURRENT_LIST_FILE}" PATH CACHE)
+
+# Get the absolute path with no ../.. relative marks, to eliminate implicit linker warnings
+get_filename_component(OpenCV_INSTALL_PATH "${OpenCV_CONFIG_PATH}/../../../" REALPATH)
+
+include(${CMAKE_CURRENT_LIST_DIR}/OpenCVModules${OpenCV_MODULES_SUFFIX}.cmake)
+
+set(OpenCV_LIB_COMPONENTS opencv_core;opencv_imgproc;opencv_imgcodecs;opencv_highgui)
+set(OpenCV_INCLUDE_DIRS "${OpenCV_INSTALL_PATH}/include/opencv4")
+
+if(NOT OpenCV_FIND_COMPONENTS)
+  set(OpenCV_FIND_COMPONENTS ${OpenCV_LIB_COMPONENTS})
+endif()
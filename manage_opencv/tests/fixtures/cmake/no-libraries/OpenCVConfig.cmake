@@ -0,0 +1,2 @@
+set(OpenCV_VERSION 4.5.5)
+set(OpenCV_INCLUDE_DIRS "${CMAKE_CURRENT_LIST_DIR}/include")
@@ -0,0 +1 @@
+// placeholder for the include directory
@@ -0,0 +1,4 @@
+// built into liballoc.a with `gcc -c -Os alloc.c && ar rcsD liballoc.a alloc.o`
+int core(void);
+int alloc(void) { return 1; }
+int alloc_checked(void) { return core(); }
@@ -0,0 +1,3 @@
+// built into libcore.a with `gcc -c -Os core.c && ar rcsD libcore.a core.o`
+int alloc(void);
+int core(void) { return alloc(); }
@@ -0,0 +1,4 @@
+// built into libimgproc.a with `gcc -c -Os imgproc.c && ar rcsD libimgproc.a imgproc.o`
+int core(void);
+__attribute__((weak)) int optional(void);
+int imgproc(void) { return core() + (optional ? optional() : 0); }